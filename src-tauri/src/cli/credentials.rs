@@ -0,0 +1,297 @@
+//! `credentials` 子命令组
+//!
+//! 直接管理 JSON-RPC 模式使用的同一份凭证存储。运行中的 JSON-RPC / serve
+//! 进程每次写回存储前都会重新读取并合并，这里的修改在它下一次写回（如刷新
+//! token）时载入，不会被覆盖。
+
+use super::{print_json, render_table, OutputFormat};
+use crate::credentials::KiroCredentials;
+use crate::provider;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// 输出时需要脱敏的字段
+const SECRET_FIELDS: &[&str] = &["accessToken", "refreshToken", "clientSecret"];
+
+#[derive(Subcommand)]
+pub enum CredentialsCommand {
+    /// Add a credential from a JSON file (e.g. Kiro IDE kiro-auth-token.json)
+    Add {
+        /// Credential JSON file (`-` for stdin)
+        #[arg(long, default_value = "-")]
+        file: PathBuf,
+        /// Display name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// List credentials (secrets are never shown)
+    List {
+        /// Output format: json or table
+        #[arg(long, value_name = "FORMAT", default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Show a credential, with secrets redacted unless --reveal is given
+    Show {
+        /// Credential ID or a unique prefix of it
+        id: String,
+        /// Print tokens and client secret in clear text
+        #[arg(long)]
+        reveal: bool,
+    },
+    /// Remove a credential
+    Remove {
+        /// Credential ID or a unique prefix of it
+        id: String,
+    },
+    /// Enable a disabled credential
+    Enable {
+        /// Credential ID or a unique prefix of it
+        id: String,
+    },
+    /// Disable a credential so it is never handed out
+    Disable {
+        /// Credential ID or a unique prefix of it
+        id: String,
+    },
+    /// Rename a credential
+    Rename {
+        /// Credential ID or a unique prefix of it
+        id: String,
+        /// New display name
+        name: String,
+    },
+    /// Mark a credential healthy again and clear its error state and counters
+    Reset {
+        /// Credential ID or a unique prefix of it
+        id: String,
+    },
+}
+
+/// `list` 的单行摘要
+#[derive(Debug, Serialize)]
+struct CredentialSummary {
+    id: String,
+    name: Option<String>,
+    auth_method: Option<String>,
+    region: Option<String>,
+    healthy: bool,
+    disabled: bool,
+    expire: Option<String>,
+    usage_count: u64,
+    error_count: u64,
+    last_error: Option<String>,
+}
+
+impl CredentialSummary {
+    fn new(id: String, credential: KiroCredentials) -> Self {
+        Self {
+            id,
+            name: credential.name,
+            auth_method: credential.auth_method,
+            region: credential.region,
+            healthy: credential.is_healthy,
+            disabled: credential.disabled,
+            expire: credential.expire,
+            usage_count: credential.usage_count,
+            error_count: credential.error_count,
+            last_error: credential.last_error,
+        }
+    }
+}
+
+/// 执行子命令
+pub async fn run(command: CredentialsCommand) -> Result<()> {
+    provider::load_credentials().await?;
+
+    match command {
+        CredentialsCommand::Add { file, name } => {
+            let mut config = read_config(&file)?;
+            if let Some(name) = name {
+                config["name"] = Value::String(name);
+            }
+            let credential_id = provider::create_credential("oauth", config).await?;
+            print_json(&serde_json::json!({ "credential_id": credential_id }))?;
+        }
+        CredentialsCommand::List { format } => {
+            let summaries: Vec<_> = provider::list_credentials()
+                .await
+                .into_iter()
+                .map(|(id, c)| CredentialSummary::new(id, c))
+                .collect();
+            match format {
+                OutputFormat::Json => print_json(&summaries)?,
+                OutputFormat::Table => print!("{}", summary_table(&summaries)),
+            }
+        }
+        CredentialsCommand::Show { id, reveal } => {
+            let id = resolve_id(&id).await?;
+            let credential = provider::get_credential(&id)
+                .await
+                .ok_or_else(|| anyhow::anyhow!("凭证不存在: {}", id))?;
+            let mut value = serde_json::to_value(&credential)?;
+            if !reveal {
+                redact(&mut value);
+            }
+            print_json(&serde_json::json!({ "id": id, "credential": value }))?;
+        }
+        CredentialsCommand::Remove { id } => {
+            let id = resolve_id(&id).await?;
+            provider::remove_credential(&id).await?;
+            print_json(&serde_json::json!({ "removed": id }))?;
+        }
+        CredentialsCommand::Enable { id } => {
+            update(&id, |c| c.disabled = false).await?;
+        }
+        CredentialsCommand::Disable { id } => {
+            update(&id, |c| c.disabled = true).await?;
+        }
+        CredentialsCommand::Rename { id, name } => {
+            update(&id, |c| c.name = Some(name)).await?;
+        }
+        CredentialsCommand::Reset { id } => {
+            update(&id, |c| {
+                c.is_healthy = true;
+                c.last_error = None;
+                c.error_count = 0;
+                c.usage_count = 0;
+                // 运行中的进程合并时以此为准，不会用旧的统计覆盖
+                c.reset_at = Some(chrono::Utc::now().to_rfc3339());
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 修改凭证并输出修改后的摘要
+async fn update<F>(id: &str, f: F) -> Result<()>
+where
+    F: FnOnce(&mut KiroCredentials),
+{
+    let id = resolve_id(id).await?;
+    let credential = provider::update_credential(&id, f).await?;
+    print_json(&CredentialSummary::new(id, credential))
+}
+
+/// 按完整 ID 或唯一前缀查找凭证
+async fn resolve_id(input: &str) -> Result<String> {
+    let ids: Vec<String> = provider::list_credentials()
+        .await
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    match_id(&ids, input)
+}
+
+fn match_id(ids: &[String], input: &str) -> Result<String> {
+    if ids.iter().any(|id| id == input) {
+        return Ok(input.to_string());
+    }
+
+    let matches: Vec<_> = ids.iter().filter(|id| id.starts_with(input)).collect();
+    match matches.as_slice() {
+        [id] => Ok((*id).clone()),
+        [] => anyhow::bail!("凭证不存在: {}", input),
+        _ => anyhow::bail!("凭证 ID 前缀不唯一: {}（匹配 {} 个）", input, matches.len()),
+    }
+}
+
+/// 将敏感字段替换为长度提示
+fn redact(value: &mut Value) {
+    for field in SECRET_FIELDS {
+        if let Some(secret) = value.get(*field).and_then(Value::as_str) {
+            let hint = format!("<redacted {} chars>", secret.chars().count());
+            value[*field] = Value::String(hint);
+        }
+    }
+}
+
+fn read_config(path: &Path) -> Result<Value> {
+    let content = if path.as_os_str() == "-" {
+        let mut buf = String::new();
+        std::io::stdin()
+            .read_to_string(&mut buf)
+            .context("读取 stdin 失败")?;
+        buf
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("读取凭证文件失败: {}", path.display()))?
+    };
+    let value: Value = serde_json::from_str(&content).context("凭证内容不是有效的 JSON")?;
+    if !value.is_object() {
+        anyhow::bail!("凭证内容必须是 JSON 对象");
+    }
+    Ok(value)
+}
+
+fn summary_table(summaries: &[CredentialSummary]) -> String {
+    let header = [
+        "ID", "NAME", "AUTH", "REGION", "STATUS", "EXPIRES", "USES", "ERRORS",
+    ];
+    let rows: Vec<Vec<String>> = summaries
+        .iter()
+        .map(|s| {
+            let status = if s.disabled {
+                "disabled"
+            } else if s.healthy {
+                "healthy"
+            } else {
+                "unhealthy"
+            };
+            vec![
+                s.id.clone(),
+                s.name.clone().unwrap_or_else(|| "-".to_string()),
+                s.auth_method.clone().unwrap_or_else(|| "-".to_string()),
+                s.region.clone().unwrap_or_else(|| "-".to_string()),
+                status.to_string(),
+                s.expire.clone().unwrap_or_else(|| "-".to_string()),
+                s.usage_count.to_string(),
+                s.error_count.to_string(),
+            ]
+        })
+        .collect();
+    render_table(&header, &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_secrets() {
+        let credential = KiroCredentials {
+            access_token: Some("secret-access".to_string()),
+            refresh_token: Some("secret-refresh".to_string()),
+            profile_arn: Some("arn:aws:codewhisperer:us-east-1:1:profile/x".to_string()),
+            ..Default::default()
+        };
+        let mut value = serde_json::to_value(&credential).unwrap();
+        redact(&mut value);
+
+        let text = value.to_string();
+        assert!(!text.contains("secret-access"));
+        assert!(!text.contains("secret-refresh"));
+        assert_eq!(value["accessToken"], "<redacted 13 chars>");
+        assert_eq!(value["clientSecret"], Value::Null);
+        assert_eq!(value["profileArn"], "arn:aws:codewhisperer:us-east-1:1:profile/x");
+    }
+
+    #[test]
+    fn test_match_id_prefix() {
+        let ids = vec![
+            "3f1c0000-aaaa".to_string(),
+            "3f2d0000-bbbb".to_string(),
+            "3f".to_string(),
+        ];
+        assert_eq!(match_id(&ids, "3f1").unwrap(), "3f1c0000-aaaa");
+        // 完整 ID 优先于前缀匹配
+        assert_eq!(match_id(&ids, "3f").unwrap(), "3f");
+        assert!(match_id(&ids, "3").is_err());
+        assert!(match_id(&ids, "9").is_err());
+    }
+}
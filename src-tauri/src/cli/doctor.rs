@@ -0,0 +1,520 @@
+//! `doctor` 子命令
+//!
+//! 汇总排查凭证问题时需要手动检查的项目：版本伪装、运行时信息、Machine ID、
+//! 时钟偏差、配置文件、凭证存储权限以及上游域名解析。每个问题附带修复建议。
+
+use super::{print_json, render_table, OutputFormat};
+use crate::config;
+use crate::credentials::KiroCredentials;
+use crate::models;
+use crate::provider;
+use crate::risk_control::{detect_kiro_version, get_system_runtime_info};
+use crate::storage;
+use crate::token_refresh::is_token_expired;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// 网络检查超时
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+/// 时钟偏差告警阈值（秒）
+const SKEW_WARN_SECS: i64 = 30;
+/// 时钟偏差超过 Token 提前刷新窗口（5 分钟）时过期判断会出错
+const SKEW_ERROR_SECS: i64 = 300;
+
+/// 检查结果级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warn,
+    Error,
+}
+
+/// 单项检查结果
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: String,
+    pub status: Status,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &str, message: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            status: Status::Ok,
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+
+    fn warn(check: &str, message: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            status: Status::Warn,
+            message: message.into(),
+            suggestion: Some(suggestion.into()),
+        }
+    }
+
+    fn error(check: &str, message: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            status: Status::Error,
+            message: message.into(),
+            suggestion: Some(suggestion.into()),
+        }
+    }
+}
+
+/// 执行全部检查
+pub async fn run(format: OutputFormat) -> Result<ExitCode> {
+    let mut findings = vec![
+        check_kiro_version(),
+        check_runtime(),
+        check_config(),
+        check_models(),
+    ];
+
+    let (store_findings, credentials) = check_store();
+    findings.extend(store_findings);
+    findings.extend(check_machine_ids(&credentials));
+    findings.extend(check_token_expiry(&credentials));
+    findings.push(check_clock_skew(&credentials).await);
+    findings.extend(check_endpoints(&credentials).await);
+
+    match format {
+        OutputFormat::Json => print_json(&findings)?,
+        OutputFormat::Table => print!("{}", render_findings(&findings)),
+    }
+
+    Ok(if findings.iter().any(|f| f.status == Status::Error) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn check_kiro_version() -> Finding {
+    match detect_kiro_version() {
+        (version, Some(source)) => {
+            Finding::ok("kiro_version", format!("{}（来自 {}）", version, source))
+        }
+        (version, None) => Finding::warn(
+            "kiro_version",
+            format!("{}（未检测到 Kiro IDE，使用内置默认值）", version),
+            "在本机安装 Kiro IDE，或确认内置版本号与当前发布版本一致，过旧的版本号更容易触发风控",
+        ),
+    }
+}
+
+fn check_runtime() -> Finding {
+    let runtime = get_system_runtime_info();
+    Finding::ok(
+        "runtime",
+        format!(
+            "os={} os_version={} node={}",
+            runtime.os_name, runtime.os_version, runtime.node_version
+        ),
+    )
+}
+
+fn check_config() -> Finding {
+    let Some(path) = config::config_path() else {
+        let searched: Vec<String> = config::candidate_paths()
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        return Finding::ok(
+            "config",
+            format!(
+                "未找到配置文件，使用默认配置（已查找: {}）",
+                searched.join(", ")
+            ),
+        );
+    };
+
+    match config::load(&path) {
+        Ok(_) => Finding::ok("config", format!("{} 解析正常", path.display())),
+        Err(e) => Finding::error(
+            "config",
+            format!("{:#}", e),
+            format!(
+                "修正 {} 中的 JSON 语法或字段类型，可参考插件自带的 plugin/config.json",
+                path.display()
+            ),
+        ),
+    }
+}
+
+fn check_models() -> Finding {
+    let Some(path) = models::override_path() else {
+        let count = models::default_catalog().models.len();
+        return Finding::ok("models", format!("使用内置模型目录（{} 个模型）", count));
+    };
+
+    match models::load(&path) {
+        Ok(overrides) => Finding::ok(
+            "models",
+            format!(
+                "{} 解析正常，覆盖 {} 个模型",
+                path.display(),
+                overrides.models.len()
+            ),
+        ),
+        Err(e) => Finding::error(
+            "models",
+            format!("{:#}", e),
+            format!(
+                "修正 {} 中的 JSON 语法或字段类型，可参考插件自带的 plugin/models.json",
+                path.display()
+            ),
+        ),
+    }
+}
+
+/// 检查凭证存储，返回检查结果和加载到的凭证
+fn check_store() -> (Vec<Finding>, BTreeMap<String, KiroCredentials>) {
+    let path = storage::store_path();
+    let mut findings = Vec::new();
+
+    if !path.exists() {
+        findings.push(Finding::warn(
+            "store",
+            format!("凭证存储不存在: {}", path.display()),
+            "使用 `credentials add` 或 `login` 添加凭证",
+        ));
+        return (findings, BTreeMap::new());
+    }
+
+    let credentials = match storage::load(&path) {
+        Ok(creds) => {
+            findings.push(Finding::ok(
+                "store",
+                format!("{} 包含 {} 个凭证", path.display(), creds.len()),
+            ));
+            creds.into_iter().collect()
+        }
+        Err(e) => {
+            findings.push(Finding::error(
+                "store",
+                format!("{:#}", e),
+                format!("修复或移走 {}，再重新添加凭证", path.display()),
+            ));
+            BTreeMap::new()
+        }
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            let mode = metadata.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                findings.push(Finding::error(
+                    "store_permissions",
+                    format!("凭证存储权限为 {:o}，其他用户可读取 Token", mode),
+                    format!("chmod 600 {}", path.display()),
+                ));
+            } else {
+                findings.push(Finding::ok("store_permissions", format!("权限 {:o}", mode)));
+            }
+        }
+    }
+
+    (findings, credentials)
+}
+
+/// Machine ID 的生成依据，与 `generate_machine_id_from_credentials` 的优先级一致
+fn machine_id_key(credential: &KiroCredentials) -> &'static str {
+    if credential
+        .profile_arn
+        .as_deref()
+        .is_some_and(|s| !s.is_empty())
+    {
+        "profileArn"
+    } else if credential
+        .client_id
+        .as_deref()
+        .is_some_and(|s| !s.is_empty())
+    {
+        "clientId"
+    } else {
+        "默认值"
+    }
+}
+
+fn check_machine_ids(credentials: &BTreeMap<String, KiroCredentials>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut by_machine_id: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+
+    for (id, credential) in credentials {
+        let machine_id = provider::machine_id(credential);
+        findings.push(Finding::ok(
+            "machine_id",
+            format!(
+                "{}: {}…（依据 {}）",
+                id,
+                &machine_id[..12],
+                machine_id_key(credential)
+            ),
+        ));
+        by_machine_id.entry(machine_id).or_default().push(id);
+    }
+
+    for (machine_id, ids) in by_machine_id {
+        if ids.len() > 1 {
+            findings.push(Finding::error(
+                "machine_id_shared",
+                format!("{} 个凭证共用 Machine ID {}…: {}", ids.len(), &machine_id[..12], ids.join(", ")),
+                "为这些凭证补充各自的 profileArn（Social）或 clientId（IdC），多账号共用指纹容易被关联封禁",
+            ));
+        }
+    }
+
+    findings
+}
+
+fn check_token_expiry(credentials: &BTreeMap<String, KiroCredentials>) -> Vec<Finding> {
+    credentials
+        .iter()
+        .filter(|(_, c)| c.access_token.is_some() && is_token_expired(c.expire.as_deref()))
+        .map(|(id, c)| {
+            Finding::warn(
+                "token_expiry",
+                format!(
+                    "{}: access_token 已过期或即将过期（expire={}）",
+                    id,
+                    c.expire.as_deref().unwrap_or("未知")
+                ),
+                format!(
+                    "执行 `refresh --credential-id {}`，下次使用时也会自动刷新",
+                    id
+                ),
+            )
+        })
+        .collect()
+}
+
+/// 对比本机时间与上游 `Date` 响应头
+async fn check_clock_skew(credentials: &BTreeMap<String, KiroCredentials>) -> Finding {
+    let region = credentials
+        .values()
+        .find_map(|c| c.region.clone())
+        .unwrap_or_else(|| "us-east-1".to_string());
+    let url = format!("https://prod.{}.auth.desktop.kiro.dev", region);
+
+    let result = async {
+        let client = reqwest::Client::builder()
+            .timeout(NETWORK_TIMEOUT)
+            .build()?;
+        let sent = Utc::now();
+        let response = client.get(&url).send().await?;
+        let received = Utc::now();
+        let date = response
+            .headers()
+            .get(reqwest::header::DATE)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("响应中没有 Date 头"))?;
+        let server = DateTime::parse_from_rfc2822(date)?.with_timezone(&Utc);
+        // 以请求往返的中点作为本机时间，抵消网络延迟
+        let local = sent + (received - sent) / 2;
+        anyhow::Ok((local - server).num_seconds())
+    }
+    .await;
+
+    match result {
+        Ok(skew) if skew.abs() <= SKEW_WARN_SECS => {
+            Finding::ok("clock_skew", format!("本机时间与上游相差 {}s", skew))
+        }
+        Ok(skew) if skew.abs() <= SKEW_ERROR_SECS => Finding::warn(
+            "clock_skew",
+            format!("本机时间与上游相差 {}s", skew),
+            "开启 NTP 时间同步（如 `timedatectl set-ntp true`）",
+        ),
+        Ok(skew) => Finding::error(
+            "clock_skew",
+            format!(
+                "本机时间与上游相差 {}s，超过 Token 提前刷新窗口，过期判断会出错",
+                skew
+            ),
+            "立即同步系统时间（如 `timedatectl set-ntp true`），然后重新刷新凭证",
+        ),
+        Err(e) => Finding::warn(
+            "clock_skew",
+            format!("无法获取上游时间（{}）: {}", url, e),
+            "检查网络或代理设置后重试",
+        ),
+    }
+}
+
+/// 检查刷新与 API 域名能否解析
+async fn check_endpoints(credentials: &BTreeMap<String, KiroCredentials>) -> Vec<Finding> {
+    let mut hosts: BTreeSet<String> = BTreeSet::new();
+    let mut methods: HashMap<String, BTreeSet<&str>> = HashMap::new();
+    for credential in credentials.values() {
+        let region = credential
+            .region
+            .clone()
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let method = credential.auth_method.as_deref().unwrap_or("social");
+        methods
+            .entry(region)
+            .or_default()
+            .insert(if method == "idc" { "idc" } else { "social" });
+    }
+    if methods.is_empty() {
+        methods.insert("us-east-1".to_string(), BTreeSet::from(["social", "idc"]));
+    }
+
+    for (region, region_methods) in &methods {
+        hosts.insert(format!("codewhisperer.{}.amazonaws.com", region));
+        for method in region_methods {
+            hosts.insert(match *method {
+                "idc" => format!("oidc.{}.amazonaws.com", region),
+                _ => format!("prod.{}.auth.desktop.kiro.dev", region),
+            });
+        }
+    }
+
+    let mut findings = Vec::new();
+    for host in hosts {
+        let lookup = tokio::time::timeout(
+            NETWORK_TIMEOUT,
+            tokio::net::lookup_host((host.as_str(), 443)),
+        )
+        .await
+        .map(|result| result.map(|addrs| addrs.count()));
+        findings.push(match lookup {
+            Ok(Ok(count)) if count > 0 => Finding::ok("endpoint", format!("{} 可解析", host)),
+            Ok(Ok(_)) => Finding::error(
+                "endpoint",
+                format!("{} 没有解析结果", host),
+                "检查 DNS 设置，或确认该区域是否提供服务",
+            ),
+            Ok(Err(e)) => Finding::error(
+                "endpoint",
+                format!("{} 无法解析: {}", host, e),
+                "检查 DNS / 代理设置，或确认凭证的 region 是否正确",
+            ),
+            Err(_) => Finding::error(
+                "endpoint",
+                format!("{} 解析超时", host),
+                "检查 DNS 服务器是否可达",
+            ),
+        });
+    }
+    findings
+}
+
+fn render_findings(findings: &[Finding]) -> String {
+    let rows: Vec<Vec<String>> = findings
+        .iter()
+        .map(|f| {
+            vec![
+                match f.status {
+                    Status::Ok => "ok",
+                    Status::Warn => "WARN",
+                    Status::Error => "ERROR",
+                }
+                .to_string(),
+                f.check.clone(),
+                f.message.clone(),
+            ]
+        })
+        .collect();
+    let mut output = render_table(&["STATUS", "CHECK", "MESSAGE"], &rows);
+
+    // 同类问题（如多个域名解析失败）的建议相同，只列一次
+    let mut suggestions: Vec<String> = Vec::new();
+    for f in findings {
+        if let Some(ref s) = f.suggestion {
+            let line = format!("  [{}] {}\n", f.check, s);
+            if !suggestions.contains(&line) {
+                suggestions.push(line);
+            }
+        }
+    }
+    if !suggestions.is_empty() {
+        output.push_str("\n建议:\n");
+        output.push_str(&suggestions.concat());
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_machine_ids_are_reported() {
+        let mut credentials = BTreeMap::new();
+        // 两个凭证都没有 profileArn / clientId，会落到同一个默认 Machine ID
+        credentials.insert("a".to_string(), KiroCredentials::default());
+        credentials.insert("b".to_string(), KiroCredentials::default());
+        credentials.insert(
+            "c".to_string(),
+            KiroCredentials {
+                profile_arn: Some("arn:aws:codewhisperer:us-east-1:1:profile/c".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let findings = check_machine_ids(&credentials);
+        let shared: Vec<_> = findings
+            .iter()
+            .filter(|f| f.check == "machine_id_shared")
+            .collect();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].status, Status::Error);
+        assert!(shared[0].message.contains("a, b"));
+        assert!(shared[0].suggestion.is_some());
+        assert!(findings
+            .iter()
+            .any(|f| f.message.starts_with("c:") && f.message.contains("profileArn")));
+    }
+
+    #[test]
+    fn test_expired_tokens_are_reported() {
+        let mut credentials = BTreeMap::new();
+        credentials.insert(
+            "old".to_string(),
+            KiroCredentials {
+                access_token: Some("at".to_string()),
+                expire: Some("2020-01-01T00:00:00Z".to_string()),
+                ..Default::default()
+            },
+        );
+        credentials.insert(
+            "fresh".to_string(),
+            KiroCredentials {
+                access_token: Some("at".to_string()),
+                expire: Some("2999-01-01T00:00:00Z".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let findings = check_token_expiry(&credentials);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.starts_with("old:"));
+    }
+
+    #[test]
+    fn test_render_findings_lists_suggestions() {
+        let output = render_findings(&[
+            Finding::ok("runtime", "os=linux"),
+            Finding::warn("clock_skew", "相差 40s", "开启 NTP"),
+            Finding::error("endpoint", "a 无法解析", "检查 DNS"),
+            Finding::error("endpoint", "b 无法解析", "检查 DNS"),
+        ]);
+        assert!(output.starts_with("STATUS"));
+        assert!(output.contains("\n建议:\n  [clock_skew] 开启 NTP\n  [endpoint] 检查 DNS\n"));
+        assert_eq!(output.matches("检查 DNS").count(), 1);
+    }
+}
@@ -0,0 +1,246 @@
+//! `health` 子命令
+//!
+//! 对凭证执行一次真实的上游探测：必要时先刷新 Token，再调用 CodeWhisperer 的
+//! `getUsageLimits`（不消耗额度），记录延迟、HTTP 结果和指纹信息。
+
+use super::{print_json, OutputFormat};
+use crate::credentials::KiroCredentials;
+use crate::provider;
+use crate::risk_control::get_kiro_version;
+use crate::token_refresh::is_token_expired;
+use anyhow::Result;
+use serde::Serialize;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Machine ID 只输出前缀，足够区分凭证
+const MACHINE_ID_PREFIX_LEN: usize = 12;
+/// 错误响应体在报告中保留的最大长度
+const MAX_ERROR_BODY: usize = 300;
+
+/// 单个凭证的健康报告
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub credential_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// 探测是否成功
+    pub healthy: bool,
+    /// 上游 HTTP 状态码，请求未发出时为空
+    pub status: Option<u16>,
+    /// 探测请求耗时（毫秒）
+    pub latency_ms: Option<u64>,
+    /// Token 过期时间
+    pub token_expires_at: Option<String>,
+    /// 本次探测前是否刷新了 Token
+    pub refreshed: bool,
+    pub kiro_version: String,
+    pub machine_id_prefix: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 执行健康检查；不指定凭证 ID 时检查整个凭证池
+pub async fn run(
+    credential_id: Option<String>,
+    format: OutputFormat,
+    timeout: Duration,
+) -> Result<ExitCode> {
+    provider::load_credentials().await?;
+
+    let ids: Vec<String> = match credential_id {
+        Some(id) => {
+            if provider::get_credential(&id).await.is_none() {
+                anyhow::bail!("凭证不存在: {}", id);
+            }
+            vec![id]
+        }
+        None => provider::list_credentials()
+            .await
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect(),
+    };
+
+    let mut tasks = JoinSet::new();
+    for id in ids {
+        tasks.spawn(probe(id, timeout));
+    }
+    let mut reports = Vec::new();
+    while let Some(report) = tasks.join_next().await {
+        reports.push(report?);
+    }
+    reports.sort_by(|a, b| a.credential_id.cmp(&b.credential_id));
+
+    match format {
+        OutputFormat::Json => print_json(&reports)?,
+        OutputFormat::Table => print!("{}", render_table(&reports)),
+    }
+
+    Ok(if reports.iter().all(|r| r.healthy) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// 探测单个凭证
+async fn probe(credential_id: String, timeout: Duration) -> HealthReport {
+    let credential = provider::get_credential(&credential_id)
+        .await
+        .unwrap_or_default();
+    let mut report = HealthReport {
+        credential_id: credential_id.clone(),
+        name: credential.name.clone(),
+        healthy: false,
+        status: None,
+        latency_ms: None,
+        token_expires_at: credential.expire.clone(),
+        refreshed: false,
+        kiro_version: get_kiro_version(),
+        machine_id_prefix: provider::machine_id(&credential)
+            .chars()
+            .take(MACHINE_ID_PREFIX_LEN)
+            .collect(),
+        error: None,
+    };
+
+    // access_token 缺失或即将过期时先刷新，刷新结果会写回存储
+    let credential = if credential.access_token.is_none()
+        || is_token_expired(credential.expire.as_deref())
+    {
+        if let Err(e) = provider::refresh_token(&credential_id).await {
+            report.error = Some(format!("Token 刷新失败: {}", e));
+            return report;
+        }
+        report.refreshed = true;
+        let refreshed = provider::get_credential(&credential_id)
+            .await
+            .unwrap_or(credential);
+        report.token_expires_at = refreshed.expire.clone();
+        refreshed
+    } else {
+        credential
+    };
+
+    let started = Instant::now();
+    let outcome = send_probe(&credential, timeout).await;
+    report.latency_ms = Some(started.elapsed().as_millis() as u64);
+
+    match outcome {
+        Ok((status, body)) => {
+            report.status = Some(status);
+            report.healthy = (200..300).contains(&status);
+            if !report.healthy {
+                report.error = Some(describe_error(status, &body));
+            }
+        }
+        Err(e) => report.error = Some(e.to_string()),
+    }
+
+    report
+}
+
+/// 调用 `getUsageLimits`，返回状态码和响应体
+async fn send_probe(credential: &KiroCredentials, timeout: Duration) -> Result<(u16, String)> {
+    let headers = provider::build_request_headers(credential)?;
+    let url = format!("{}/getUsageLimits", provider::base_url(credential));
+
+    let mut query = vec![("origin", "AI_EDITOR"), ("resourceType", "AGENTIC_REQUEST")];
+    if let Some(ref arn) = credential.profile_arn {
+        query.push(("profileArn", arn));
+    }
+
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+    let mut request = client.get(&url).query(&query);
+    for (name, value) in &headers {
+        request = request.header(name, value);
+    }
+
+    let response = request.send().await?;
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+    Ok((status, body))
+}
+
+/// 组合 provider 的错误分类和截断后的响应体
+fn describe_error(status: u16, body: &str) -> String {
+    let body: String = body.chars().take(MAX_ERROR_BODY).collect();
+    match provider::parse_error(status, &body) {
+        Some(error) => format!("{} ({}): {}", error.message, error.error_type, body),
+        None => format!("HTTP {}: {}", status, body),
+    }
+}
+
+/// 渲染表格输出
+fn render_table(reports: &[HealthReport]) -> String {
+    let header = [
+        "CREDENTIAL",
+        "NAME",
+        "RESULT",
+        "HTTP",
+        "LATENCY",
+        "EXPIRES",
+        "REFRESHED",
+        "KIRO",
+        "MACHINE ID",
+    ];
+    let rows: Vec<Vec<String>> = reports
+        .iter()
+        .map(|r| {
+            vec![
+                r.credential_id.clone(),
+                r.name.clone().unwrap_or_else(|| "-".to_string()),
+                if r.healthy { "ok" } else { "FAIL" }.to_string(),
+                r.status.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+                r.latency_ms
+                    .map(|ms| format!("{}ms", ms))
+                    .unwrap_or_else(|| "-".to_string()),
+                r.token_expires_at.clone().unwrap_or_else(|| "-".to_string()),
+                if r.refreshed { "yes" } else { "no" }.to_string(),
+                r.kiro_version.clone(),
+                r.machine_id_prefix.clone(),
+            ]
+        })
+        .collect();
+
+    let mut table = super::render_table(&header, &rows);
+    let errors: Vec<String> = reports
+        .iter()
+        .filter_map(|r| r.error.as_ref().map(|e| format!("{}: {}\n", r.credential_id, e)))
+        .collect();
+    if !errors.is_empty() {
+        table.push('\n');
+        table.push_str(&errors.concat());
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table_includes_errors() {
+        let reports = vec![HealthReport {
+            credential_id: "cred-1".to_string(),
+            name: Some("work".to_string()),
+            healthy: false,
+            status: Some(403),
+            latency_ms: Some(120),
+            token_expires_at: None,
+            refreshed: true,
+            kiro_version: "0.1.25".to_string(),
+            machine_id_prefix: "abcdef012345".to_string(),
+            error: Some(describe_error(403, "{\"message\":\"denied\"}")),
+        }];
+
+        let table = render_table(&reports);
+        let mut lines = table.lines();
+        assert!(lines.next().unwrap().starts_with("CREDENTIAL"));
+        let row = lines.next().unwrap();
+        assert!(row.contains("FAIL") && row.contains("403") && row.contains("120ms"));
+        assert!(table.contains("cred-1: 权限不足 (authorization)"));
+    }
+}
@@ -0,0 +1,802 @@
+//! `login` 子命令
+//!
+//! - IdC：通过 AWS SSO OIDC 的设备授权流程获取 Token，
+//!   依次注册 OIDC 客户端、发起设备授权、轮询 Token；
+//! - Social：Kiro 桌面端认证服务的授权码 + PKCE 流程，
+//!   在本地回环端口接收浏览器重定向后换取 Token。
+//!
+//! 登录结果写入凭证存储。
+
+use super::print_json;
+use crate::credentials::KiroCredentials;
+use crate::fingerprint::generate_machine_id_from_credentials;
+use crate::provider;
+use crate::risk_control::{build_idc_auth_user_agent, get_kiro_version};
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration as ChronoDuration, Utc};
+use reqwest::{Client, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+/// 注册 OIDC 客户端时使用的名称
+const IDC_CLIENT_NAME: &str = "Kiro IDE";
+/// Kiro IDE 申请的 CodeWhisperer scope
+const IDC_SCOPES: &[&str] = &[
+    "codewhisperer:completions",
+    "codewhisperer:analysis",
+    "codewhisperer:conversations",
+    "codewhisperer:transformations",
+    "codewhisperer:taskassist",
+];
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// 服务端未返回轮询间隔时的默认值（秒）
+const DEFAULT_POLL_INTERVAL: u64 = 5;
+/// 收到 `slow_down` 时增加的轮询间隔（秒），参见 RFC 8628
+const SLOW_DOWN_INCREMENT: u64 = 5;
+
+/// IdC 登录参数
+#[derive(Debug, Clone)]
+pub struct IdcLoginOptions {
+    /// IAM Identity Center 的 start URL，如 `https://d-xxxxxxxxxx.awsapps.com/start`
+    pub start_url: String,
+    pub region: String,
+    /// OIDC 服务地址，默认 `https://oidc.<region>.amazonaws.com`
+    pub oidc_url: Option<String>,
+    pub name: Option<String>,
+}
+
+impl IdcLoginOptions {
+    fn oidc_base(&self) -> String {
+        self.oidc_url
+            .clone()
+            .unwrap_or_else(|| format!("https://oidc.{}.amazonaws.com", self.region))
+            .trim_end_matches('/')
+            .to_string()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterClientResponse {
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceAuthorizationResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    #[serde(default)]
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    #[serde(default)]
+    interval: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OidcTokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<i64>,
+}
+
+/// OIDC 错误响应（RFC 8628 / AWS SSO OIDC）
+#[derive(Debug, Deserialize)]
+struct OidcErrorResponse {
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// Kiro 桌面端认证服务默认地址
+const DEFAULT_SOCIAL_AUTH_URL: &str = "https://prod.us-east-1.auth.desktop.kiro.dev";
+/// 本地回环重定向路径
+const SOCIAL_CALLBACK_PATH: &str = "/oauth/callback";
+/// 读取单个回调请求的超时，防止空闲连接阻塞回调监听
+const CALLBACK_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Social 登录参数
+#[derive(Debug, Clone)]
+pub struct SocialLoginOptions {
+    /// 身份提供方：`Google` 或 `Github`
+    pub idp: String,
+    pub region: String,
+    /// 认证服务地址，默认 `https://prod.us-east-1.auth.desktop.kiro.dev`
+    pub auth_url: Option<String>,
+    /// 回环监听端口，0 表示随机端口
+    pub port: u16,
+    /// 等待浏览器重定向的最长时间
+    pub timeout: Duration,
+    pub name: Option<String>,
+}
+
+impl SocialLoginOptions {
+    fn auth_base(&self) -> String {
+        self.auth_url
+            .as_deref()
+            .unwrap_or(DEFAULT_SOCIAL_AUTH_URL)
+            .trim_end_matches('/')
+            .to_string()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SocialTokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    profile_arn: Option<String>,
+    #[serde(default)]
+    expires_in: Option<i64>,
+}
+
+/// 登录结果输出
+#[derive(Debug, Serialize)]
+struct LoginOutput {
+    credential_id: String,
+    auth_method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expire: Option<String>,
+}
+
+/// IdC 设备授权登录并保存凭证
+pub async fn login_idc(options: IdcLoginOptions) -> Result<()> {
+    provider::load_credentials().await?;
+
+    let credential = idc_device_flow(&options).await?;
+    let expire = credential.expire.clone();
+    let credential_id = provider::add_credential(credential).await?;
+
+    print_json(&LoginOutput {
+        credential_id,
+        auth_method: "idc".to_string(),
+        expire,
+    })
+}
+
+/// Social 登录并保存凭证
+pub async fn login_social(options: SocialLoginOptions) -> Result<()> {
+    provider::load_credentials().await?;
+
+    let credential = social_pkce_flow(&options, |url| {
+        eprintln!();
+        eprintln!("在浏览器中打开以下地址完成登录:");
+        eprintln!("  {}", url);
+        eprintln!();
+    })
+    .await?;
+    let expire = credential.expire.clone();
+    let credential_id = provider::add_credential(credential).await?;
+
+    print_json(&LoginOutput {
+        credential_id,
+        auth_method: "social".to_string(),
+        expire,
+    })
+}
+
+/// 执行设备授权流程，返回尚未保存的凭证
+async fn idc_device_flow(options: &IdcLoginOptions) -> Result<KiroCredentials> {
+    let base = options.oidc_base();
+    let client = Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .timeout(Duration::from_secs(60))
+        .build()?;
+    let kiro_version = get_kiro_version();
+
+    // 1. 注册 OIDC 客户端
+    let registration: RegisterClientResponse = post_json(
+        &client,
+        &format!("{}/client/register", base),
+        None,
+        &serde_json::json!({
+            "clientName": IDC_CLIENT_NAME,
+            "clientType": "public",
+            "scopes": IDC_SCOPES,
+            "grantTypes": [DEVICE_CODE_GRANT, "refresh_token"],
+            "issuerUrl": options.start_url,
+        }),
+    )
+    .await
+    .context("注册 OIDC 客户端失败")?;
+    debug!("OIDC 客户端已注册: {}", registration.client_id);
+
+    let machine_id = generate_machine_id_from_credentials(None, Some(&registration.client_id));
+    let user_agent = build_idc_auth_user_agent(&kiro_version, &machine_id);
+
+    // 2. 发起设备授权
+    let authorization: DeviceAuthorizationResponse = post_json(
+        &client,
+        &format!("{}/device_authorization", base),
+        Some(&user_agent),
+        &serde_json::json!({
+            "clientId": registration.client_id,
+            "clientSecret": registration.client_secret,
+            "startUrl": options.start_url,
+        }),
+    )
+    .await
+    .context("发起设备授权失败")?;
+
+    eprintln!();
+    eprintln!("在浏览器中打开以下地址完成登录:");
+    eprintln!(
+        "  {}",
+        authorization
+            .verification_uri_complete
+            .as_deref()
+            .unwrap_or(&authorization.verification_uri)
+    );
+    eprintln!("验证码: {}", authorization.user_code);
+    eprintln!();
+
+    // 3. 轮询 Token
+    let token =
+        poll_device_token(&client, &base, &user_agent, &registration, &authorization).await?;
+    info!("IdC 登录成功");
+
+    Ok(KiroCredentials {
+        name: options.name.clone(),
+        access_token: Some(token.access_token),
+        refresh_token: token.refresh_token,
+        client_id: Some(registration.client_id),
+        client_secret: Some(registration.client_secret),
+        region: Some(options.region.clone()),
+        auth_method: Some("idc".to_string()),
+        expire: token
+            .expires_in
+            .map(|secs| (Utc::now() + ChronoDuration::seconds(secs)).to_rfc3339()),
+        last_refresh: Some(Utc::now().to_rfc3339()),
+        ..Default::default()
+    })
+}
+
+/// 按服务端要求的间隔轮询，直到用户完成授权、拒绝或设备码过期
+async fn poll_device_token(
+    client: &Client,
+    base: &str,
+    user_agent: &str,
+    registration: &RegisterClientResponse,
+    authorization: &DeviceAuthorizationResponse,
+) -> Result<OidcTokenResponse> {
+    let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+    let mut interval = authorization.interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+    let url = format!("{}/token", base);
+    let body = serde_json::json!({
+        "clientId": registration.client_id,
+        "clientSecret": registration.client_secret,
+        "deviceCode": authorization.device_code,
+        "grantType": DEVICE_CODE_GRANT,
+    });
+
+    loop {
+        if Instant::now() >= deadline {
+            bail!("设备码已过期，请重新登录");
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let response = client
+            .post(&url)
+            .header("User-Agent", "node")
+            .header("x-amz-user-agent", user_agent)
+            .json(&body)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+
+        if status.is_success() {
+            return serde_json::from_str(&text).context("解析 Token 响应失败");
+        }
+
+        let error: OidcErrorResponse = serde_json::from_str(&text).unwrap_or(OidcErrorResponse {
+            error: String::new(),
+            error_description: None,
+        });
+        match error.error.as_str() {
+            "authorization_pending" => debug!("等待用户完成授权"),
+            "slow_down" => interval += SLOW_DOWN_INCREMENT,
+            "expired_token" => bail!("设备码已过期，请重新登录"),
+            "access_denied" => bail!("用户拒绝了授权"),
+            _ => bail!(
+                "获取 Token 失败: {} - {}",
+                status,
+                error.error_description.unwrap_or(text)
+            ),
+        }
+    }
+}
+
+/// PKCE code_verifier 与 S256 code_challenge
+fn pkce_pair() -> (String, String) {
+    let verifier = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    (verifier, challenge)
+}
+
+/// 执行授权码 + PKCE 流程，返回尚未保存的凭证
+///
+/// `on_authorize_url` 收到需要在浏览器中打开的授权地址。
+async fn social_pkce_flow<F>(
+    options: &SocialLoginOptions,
+    on_authorize_url: F,
+) -> Result<KiroCredentials>
+where
+    F: FnOnce(&str),
+{
+    let base = options.auth_base();
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], options.port)))
+        .await
+        .with_context(|| format!("无法监听本地端口 {}", options.port))?;
+    let redirect_uri = format!(
+        "http://localhost:{}{}",
+        listener.local_addr()?.port(),
+        SOCIAL_CALLBACK_PATH
+    );
+
+    let (verifier, challenge) = pkce_pair();
+    let state = uuid::Uuid::new_v4().simple().to_string();
+    let authorize_url = Url::parse_with_params(
+        &format!("{}/login", base),
+        &[
+            ("idp", options.idp.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+            ("state", state.as_str()),
+        ],
+    )?;
+    on_authorize_url(authorize_url.as_str());
+
+    let code = tokio::time::timeout(options.timeout, wait_for_callback(&listener, &state))
+        .await
+        .map_err(|_| anyhow::anyhow!("等待浏览器登录超时"))??;
+    debug!("收到授权码，开始换取 Token");
+
+    let client = Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .timeout(Duration::from_secs(60))
+        .build()?;
+    let token: SocialTokenResponse = post_json(
+        &client,
+        &format!("{}/oauth/token", base),
+        None,
+        &serde_json::json!({
+            "code": code,
+            "code_verifier": verifier,
+            "redirect_uri": redirect_uri,
+        }),
+    )
+    .await
+    .context("换取 Token 失败")?;
+    info!("Social 登录成功");
+
+    if token.profile_arn.is_none() {
+        warn!("Token 响应中没有 profileArn，调用 API 时可能需要手动补充");
+    }
+
+    Ok(KiroCredentials {
+        name: options.name.clone(),
+        access_token: Some(token.access_token),
+        refresh_token: token.refresh_token,
+        profile_arn: token.profile_arn,
+        region: Some(options.region.clone()),
+        auth_method: Some("social".to_string()),
+        expire: token
+            .expires_in
+            .map(|secs| (Utc::now() + ChronoDuration::seconds(secs)).to_rfc3339()),
+        last_refresh: Some(Utc::now().to_rfc3339()),
+        ..Default::default()
+    })
+}
+
+/// 等待浏览器重定向到回调地址，返回授权码
+///
+/// 回环端口上任何本地进程都能连接：与回调无关的请求（如 favicon）返回 404，
+/// 格式错误或 state 不匹配的请求返回 400，都继续等待。只有 state 正确的
+/// 回调才会结束登录（包括携带 `error` 的回调）。
+async fn wait_for_callback(listener: &TcpListener, state: &str) -> Result<String> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let (stream, request_line) =
+            match tokio::time::timeout(CALLBACK_READ_TIMEOUT, read_request(stream)).await {
+                Ok(Ok(request)) => request,
+                Ok(Err(e)) => {
+                    debug!("读取回调请求失败: {}", e);
+                    continue;
+                }
+                Err(_) => {
+                    debug!("读取回调请求超时");
+                    continue;
+                }
+            };
+
+        let target = request_line.split(' ').nth(1).unwrap_or("/");
+        let url = match Url::parse(&format!("http://localhost{}", target)) {
+            Ok(url) => url,
+            Err(_) => {
+                warn!("忽略格式错误的回调请求: {}", request_line.trim_end());
+                respond(stream, 400, "Bad Request").await;
+                continue;
+            }
+        };
+        if url.path() != SOCIAL_CALLBACK_PATH {
+            respond(stream, 404, "Not Found").await;
+            continue;
+        }
+
+        let param = |key: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.to_string())
+        };
+
+        if param("state").as_deref() != Some(state) {
+            warn!("忽略 state 不匹配的回调请求");
+            respond(stream, 400, "Bad Request").await;
+            continue;
+        }
+
+        let result = if let Some(error) = param("error") {
+            Err(anyhow::anyhow!(
+                "登录失败: {} {}",
+                error,
+                param("error_description").unwrap_or_default()
+            ))
+        } else {
+            param("code").ok_or_else(|| anyhow::anyhow!("回调中没有授权码"))
+        };
+
+        let page = match result {
+            Ok(_) => "登录成功，可以关闭此页面。",
+            Err(_) => "登录失败，请回到终端查看错误信息。",
+        };
+        respond(stream, 200, page).await;
+        return result;
+    }
+}
+
+/// 读取请求行并读完请求头，避免浏览器在响应前被重置连接
+async fn read_request(
+    stream: tokio::net::TcpStream,
+) -> std::io::Result<(tokio::net::TcpStream, String)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 || line == "\r\n" {
+            break;
+        }
+    }
+    Ok((reader.into_inner(), request_line))
+}
+
+async fn respond(mut stream: tokio::net::TcpStream, status: u16, message: &str) {
+    let body = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Kiro Provider</title></head><body><p>{}</p></body></html>",
+        message
+    );
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        _ => "Not Found",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!("写回调响应失败: {}", e);
+    }
+}
+
+async fn post_json<T: DeserializeOwned>(
+    client: &Client,
+    url: &str,
+    user_agent: Option<&str>,
+    body: &serde_json::Value,
+) -> Result<T> {
+    debug!("POST {}", url);
+    let mut request = client.post(url).header("User-Agent", "node").json(body);
+    // 注册客户端前还没有 client_id，无法生成 Machine ID
+    if let Some(user_agent) = user_agent {
+        request = request.header("x-amz-user-agent", user_agent);
+    }
+    let response = request.send().await?;
+
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("{} - {}", status, text);
+    }
+    serde_json::from_str(&text).with_context(|| format!("无法解析响应: {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::AsyncReadExt;
+    use tokio::task::JoinHandle;
+
+    type Handler = dyn Fn(&str, &Value) -> (u16, Value) + Send + Sync;
+
+    /// 最小 HTTP mock：每个连接处理一个 JSON 请求
+    async fn spawn_mock(handler: Arc<Handler>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).await.unwrap();
+                    let path = request_line.split(' ').nth(1).unwrap_or("/").to_string();
+
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).await.unwrap();
+                        if line == "\r\n" || line.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).await.unwrap();
+                    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+                    let (status, response) = handler(&path, &body);
+                    let response = response.to_string();
+                    let reply = format!(
+                        "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        response.len(),
+                        response
+                    );
+                    reader
+                        .into_inner()
+                        .write_all(reply.as_bytes())
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_idc_device_flow_against_mock() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let base = spawn_mock(Arc::new(move |path, body| match path {
+            "/client/register" => {
+                assert_eq!(body["issuerUrl"], "https://example.awsapps.com/start");
+                (200, json!({"clientId": "cid", "clientSecret": "csecret"}))
+            }
+            "/device_authorization" => {
+                assert_eq!(body["clientId"], "cid");
+                (
+                    200,
+                    json!({
+                        "deviceCode": "dc",
+                        "userCode": "ABCD-EFGH",
+                        "verificationUri": "https://device.example",
+                        "expiresIn": 60,
+                        "interval": 0
+                    }),
+                )
+            }
+            "/token" => {
+                assert_eq!(body["deviceCode"], "dc");
+                assert_eq!(body["grantType"], DEVICE_CODE_GRANT);
+                // 第一次轮询时用户尚未完成授权
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    (400, json!({"error": "authorization_pending"}))
+                } else {
+                    (
+                        200,
+                        json!({"accessToken": "at", "refreshToken": "rt", "expiresIn": 3600}),
+                    )
+                }
+            }
+            _ => (404, json!({})),
+        }))
+        .await;
+
+        let credential = idc_device_flow(&IdcLoginOptions {
+            start_url: "https://example.awsapps.com/start".to_string(),
+            region: "us-west-2".to_string(),
+            oidc_url: Some(base),
+            name: Some("sso".to_string()),
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+        assert_eq!(credential.auth_method.as_deref(), Some("idc"));
+        assert_eq!(credential.client_id.as_deref(), Some("cid"));
+        assert_eq!(credential.client_secret.as_deref(), Some("csecret"));
+        assert_eq!(credential.refresh_token.as_deref(), Some("rt"));
+        assert_eq!(credential.region.as_deref(), Some("us-west-2"));
+        assert!(credential.expire.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_idc_device_flow_access_denied() {
+        let base = spawn_mock(Arc::new(|path, _| match path {
+            "/client/register" => (200, json!({"clientId": "cid", "clientSecret": "cs"})),
+            "/device_authorization" => (
+                200,
+                json!({"deviceCode": "dc", "userCode": "X", "verificationUri": "u", "expiresIn": 60, "interval": 0}),
+            ),
+            _ => (400, json!({"error": "access_denied"})),
+        }))
+        .await;
+
+        let error = idc_device_flow(&IdcLoginOptions {
+            start_url: "https://example.awsapps.com/start".to_string(),
+            region: "us-east-1".to_string(),
+            oidc_url: Some(base),
+            name: None,
+        })
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("拒绝"));
+    }
+
+    #[test]
+    fn test_pkce_challenge_is_s256() {
+        let (verifier, challenge) = pkce_pair();
+        assert_eq!(verifier.len(), 64);
+        assert_eq!(
+            challenge,
+            URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+        );
+        assert!(!challenge.contains('='));
+    }
+
+    fn social_options(auth_url: String) -> SocialLoginOptions {
+        SocialLoginOptions {
+            idp: "Google".to_string(),
+            region: "us-east-1".to_string(),
+            auth_url: Some(auth_url),
+            port: 0,
+            timeout: Duration::from_secs(10),
+            name: None,
+        }
+    }
+
+    /// 模拟浏览器：先由其他本地进程发送无关、格式错误和伪造 state 的请求，
+    /// 再按授权地址中的 redirect_uri 回调；返回各请求的响应状态码
+    fn simulate_browser(authorize_url: &str, query: &str) -> JoinHandle<Vec<u16>> {
+        let url = Url::parse(authorize_url).unwrap();
+        let param = |key: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.to_string())
+                .unwrap()
+        };
+        assert_eq!(url.path(), "/login");
+        assert_eq!(param("code_challenge_method"), "S256");
+
+        let redirect_uri = param("redirect_uri");
+        let callback = format!("{}?{}&state={}", redirect_uri, query, param("state"));
+        tokio::spawn(async move {
+            let base = redirect_uri.split("/oauth").next().unwrap().to_string();
+            let mut statuses = Vec::new();
+            let status = |response: reqwest::Result<reqwest::Response>| {
+                response.map(|r| r.status().as_u16()).unwrap_or(0)
+            };
+            statuses.push(status(reqwest::get(format!("{}/favicon.ico", base)).await));
+
+            let mut stream = tokio::net::TcpStream::connect(base.trim_start_matches("http://"))
+                .await
+                .unwrap();
+            stream
+                .write_all(b"GET :bad HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response).await;
+            statuses.push(response[9..12].parse().unwrap_or(0));
+
+            for forged in ["error=access_denied&state=forged", "code=evil&state=forged"] {
+                let forged = format!("{}?{}", redirect_uri, forged);
+                statuses.push(status(reqwest::get(forged).await));
+            }
+            statuses.push(status(reqwest::get(callback).await));
+            statuses
+        })
+    }
+
+    /// 运行 Social 登录流程，同时返回模拟浏览器收到的响应状态码
+    async fn run_social_flow(base: String, query: &str) -> (Result<KiroCredentials>, Vec<u16>) {
+        let browser = std::sync::Mutex::new(None);
+        let result = social_pkce_flow(&social_options(base), |url| {
+            *browser.lock().unwrap() = Some(simulate_browser(url, query));
+        })
+        .await;
+        let handle = browser.lock().unwrap().take().unwrap();
+        (result, handle.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_social_pkce_flow_against_mock() {
+        let base = spawn_mock(Arc::new(|path, body| match path {
+            "/oauth/token" => {
+                assert_eq!(body["code"], "auth-code");
+                assert_eq!(body["code_verifier"].as_str().unwrap().len(), 64);
+                assert!(body["redirect_uri"]
+                    .as_str()
+                    .unwrap()
+                    .ends_with(SOCIAL_CALLBACK_PATH));
+                (
+                    200,
+                    json!({
+                        "accessToken": "at",
+                        "refreshToken": "rt",
+                        "profileArn": "arn:aws:codewhisperer:us-east-1:1:profile/p",
+                        "expiresIn": 3600
+                    }),
+                )
+            }
+            _ => (404, json!({})),
+        }))
+        .await;
+
+        // 无关、格式错误和伪造 state 的请求都不会中断登录
+        let (credential, statuses) = run_social_flow(base, "code=auth-code").await;
+        let credential = credential.unwrap();
+        assert_eq!(statuses, vec![404, 400, 400, 400, 200]);
+
+        assert_eq!(credential.auth_method.as_deref(), Some("social"));
+        assert_eq!(credential.refresh_token.as_deref(), Some("rt"));
+        assert_eq!(
+            credential.profile_arn.as_deref(),
+            Some("arn:aws:codewhisperer:us-east-1:1:profile/p")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_social_pkce_flow_reports_callback_error() {
+        let base = spawn_mock(Arc::new(|_, _| (500, json!({})))).await;
+
+        let (result, statuses) = run_social_flow(base, "error=access_denied").await;
+        assert!(result.unwrap_err().to_string().contains("access_denied"));
+        assert_eq!(statuses.last(), Some(&200));
+    }
+}
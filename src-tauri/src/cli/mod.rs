@@ -0,0 +1,182 @@
+//! CLI 子命令实现
+//!
+//! 子命令与 JSON-RPC 模式共用 provider 和持久化存储中的逻辑，
+//! 结果以 JSON 输出到 stdout，失败时以非零状态码退出。
+
+pub mod credentials;
+pub mod doctor;
+pub mod health;
+pub mod login;
+pub mod token;
+pub mod translate;
+
+use crate::credentials::KiroCredentials;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// 以格式化 JSON 输出到 stdout
+pub fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// 列表类命令的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Table,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "table" => Ok(OutputFormat::Table),
+            other => Err(format!("未知的输出格式: {}（可选 json、table）", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Table => write!(f, "table"),
+        }
+    }
+}
+
+/// 渲染左对齐的纯文本表格
+pub fn render_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", line.join("  ").trim_end())
+    };
+
+    let mut table = format_row(header.to_vec());
+    for row in rows {
+        table.push_str(&format_row(row.iter().map(String::as_str).collect()));
+    }
+    table
+}
+
+/// 凭证来源
+#[derive(Debug, Clone)]
+pub enum CredentialSource {
+    /// 凭证存储中的凭证 ID
+    Store(String),
+    /// 凭证 JSON 文件（兼容 Kiro IDE 的 `kiro-auth-token.json`）
+    File(PathBuf),
+}
+
+impl CredentialSource {
+    pub fn from_args(credential_id: Option<String>, file: Option<PathBuf>) -> Result<Self> {
+        match (credential_id, file) {
+            (Some(id), None) => Ok(CredentialSource::Store(id)),
+            (None, Some(path)) => Ok(CredentialSource::File(path)),
+            (Some(_), Some(_)) => anyhow::bail!("--credential-id 与 --file 只能指定一个"),
+            (None, None) => anyhow::bail!("需要指定 --credential-id 或 --file"),
+        }
+    }
+}
+
+/// 读取凭证文件，同时返回原始 JSON 以便写回时保留未知字段
+pub fn read_credential_file(path: &Path) -> Result<(serde_json::Value, KiroCredentials)> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("读取凭证文件失败: {}", path.display()))?;
+    let raw: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("解析凭证文件失败: {}", path.display()))?;
+    let credential: KiroCredentials = serde_json::from_value(raw.clone())
+        .with_context(|| format!("凭证文件格式无效: {}", path.display()))?;
+    Ok((raw, credential))
+}
+
+/// 将刷新后的 token 写回凭证文件，保留文件中的其他字段和过期时间字段名
+pub fn write_credential_file(
+    path: &Path,
+    raw: &mut serde_json::Value,
+    credential: &KiroCredentials,
+) -> Result<()> {
+    let obj = raw
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("凭证文件不是 JSON 对象: {}", path.display()))?;
+
+    obj.insert("accessToken".to_string(), serde_json::json!(credential.access_token));
+    obj.insert("refreshToken".to_string(), serde_json::json!(credential.refresh_token));
+
+    // Kiro IDE 的 token 文件使用 expiresAt，本插件的存储格式使用 expire
+    let expire_key = if obj.contains_key("expiresAt") {
+        "expiresAt"
+    } else {
+        "expire"
+    };
+    obj.insert(expire_key.to_string(), serde_json::json!(credential.expire));
+    if obj.contains_key("lastRefresh") {
+        obj.insert("lastRefresh".to_string(), serde_json::json!(credential.last_refresh));
+    }
+
+    let content = serde_json::to_string_pretty(raw)?;
+    crate::storage::write_atomic(path, content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table_aligns_columns() {
+        let table = render_table(
+            &["ID", "NAME"],
+            &[
+                vec!["a".to_string(), "first".to_string()],
+                vec!["long-id".to_string(), "-".to_string()],
+            ],
+        );
+        assert_eq!(table, "ID       NAME\na        first\nlong-id  -\n");
+    }
+
+    #[test]
+    fn test_write_credential_file_keeps_format() {
+        let dir = std::env::temp_dir().join(format!("kiro-provider-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kiro-auth-token.json");
+        std::fs::write(
+            &path,
+            r#"{"accessToken":"old","refreshToken":"old-rt","expiresAt":"2020-01-01T00:00:00Z","provider":"Google"}"#,
+        )
+        .unwrap();
+
+        let (mut raw, mut credential) = read_credential_file(&path).unwrap();
+        assert_eq!(credential.expire.as_deref(), Some("2020-01-01T00:00:00Z"));
+
+        credential.access_token = Some("new".to_string());
+        credential.refresh_token = Some("new-rt".to_string());
+        credential.expire = Some("2030-01-01T00:00:00+00:00".to_string());
+        write_credential_file(&path, &mut raw, &credential).unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["accessToken"], "new");
+        assert_eq!(written["refreshToken"], "new-rt");
+        assert_eq!(written["expiresAt"], "2030-01-01T00:00:00+00:00");
+        assert_eq!(written["provider"], "Google");
+        assert!(written.get("expire").is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
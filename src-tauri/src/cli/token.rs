@@ -0,0 +1,137 @@
+//! `validate` / `refresh` 子命令
+
+use super::{print_json, read_credential_file, write_credential_file, CredentialSource};
+use crate::provider;
+use crate::token_refresh;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::process::ExitCode;
+use tracing::warn;
+
+/// `refresh` 输出
+#[derive(Debug, Serialize)]
+struct RefreshOutput {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    credential_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+    /// 服务端是否下发了新的 refresh_token
+    refresh_token_rotated: bool,
+    /// 新 token 是否已保存（存储或 `--write-back`）
+    persisted: bool,
+    /// 未保存时输出新 token，避免轮换后的 refresh_token 丢失
+    #[serde(skip_serializing_if = "Option::is_none")]
+    access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 验证凭证
+pub async fn validate(source: CredentialSource) -> Result<ExitCode> {
+    let result = match source {
+        CredentialSource::Store(id) => {
+            provider::load_credentials().await?;
+            provider::validate_credential(&id).await?
+        }
+        CredentialSource::File(path) => {
+            let (_, credential) = read_credential_file(&path)?;
+            provider::check_credential(&credential)
+        }
+    };
+
+    print_json(&result)?;
+    Ok(if result.valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// 刷新 Token
+///
+/// 存储中的凭证刷新后立即写回存储；凭证文件仅在 `write_back` 时写回。
+/// 新 token 未能保存时（未指定 `write_back` 或写回失败）输出到结果中，
+/// 写回失败时同时带上错误并以非零状态退出。
+pub async fn refresh(source: CredentialSource, write_back: bool) -> Result<ExitCode> {
+    if write_back && matches!(source, CredentialSource::Store(_)) {
+        anyhow::bail!("--write-back 需要配合 --file 使用");
+    }
+
+    let mut output = RefreshOutput {
+        success: false,
+        credential_id: None,
+        file: None,
+        expires_at: None,
+        refresh_token_rotated: false,
+        persisted: false,
+        access_token: None,
+        refresh_token: None,
+        error: None,
+    };
+
+    match source {
+        CredentialSource::Store(id) => {
+            output.credential_id = Some(id.clone());
+            provider::load_credentials().await?;
+            match provider::refresh_token(&id).await {
+                Ok(refreshed) => {
+                    let result = refreshed.result;
+                    output.success = true;
+                    output.expires_at = result.expires_at;
+                    output.refresh_token_rotated = result.refresh_token.is_some();
+                    output.persisted = refreshed.persisted;
+                    if !refreshed.persisted {
+                        if output.refresh_token_rotated {
+                            warn!("refresh_token 已轮换但未写回存储，请保存输出中的新 token");
+                        }
+                        output.error = Some("新 token 未写回凭证存储".to_string());
+                        output.access_token = Some(result.access_token);
+                        output.refresh_token = result.refresh_token;
+                    }
+                }
+                Err(e) => output.error = Some(e.to_string()),
+            }
+        }
+        CredentialSource::File(path) => {
+            output.file = Some(path.display().to_string());
+            let (mut raw, mut credential) = read_credential_file(&path)?;
+            match token_refresh::refresh_token(&mut credential).await {
+                Ok(result) => {
+                    provider::apply_refresh_result(&mut credential, &result);
+                    output.success = true;
+                    output.expires_at = result.expires_at;
+                    output.refresh_token_rotated = result.refresh_token.is_some();
+
+                    if write_back {
+                        // 旧 refresh_token 可能已失效，写回失败时也要输出新 token
+                        match write_credential_file(&path, &mut raw, &credential) {
+                            Ok(()) => output.persisted = true,
+                            Err(e) => output.error = Some(format!("写回文件失败: {:#}", e)),
+                        }
+                    }
+                    if !output.persisted {
+                        if output.refresh_token_rotated {
+                            warn!("refresh_token 已轮换但未写回文件，请保存输出中的新 token");
+                        }
+                        output.access_token = credential.access_token.clone();
+                        output.refresh_token = credential.refresh_token.clone();
+                    }
+                }
+                Err(e) => output.error = Some(e.to_string()),
+            }
+        }
+    }
+
+    print_json(&output)?;
+    Ok(if output.success && output.error.is_none() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,185 @@
+//! `translate` 子命令
+//!
+//! 正向：Anthropic / OpenAI 请求 → CodeWhisperer 请求体；
+//! 反向：抓取的 CodeWhisperer Event Stream → Anthropic SSE 或最终 Message。
+
+use crate::translator::cw_to_anthropic::{collect_message, CwToAnthropicTranslator};
+use crate::translator::{self, event_stream, RequestFormat};
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// 反向转换的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReverseOutput {
+    /// Anthropic SSE 事件流
+    #[default]
+    Sse,
+    /// 合并后的 Anthropic Message JSON
+    Message,
+}
+
+impl std::str::FromStr for ReverseOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sse" => Ok(ReverseOutput::Sse),
+            "message" => Ok(ReverseOutput::Message),
+            other => Err(format!("未知的输出格式: {}（可选 sse、message）", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for ReverseOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReverseOutput::Sse => write!(f, "sse"),
+            ReverseOutput::Message => write!(f, "message"),
+        }
+    }
+}
+
+/// `translate` 参数
+#[derive(Debug, Clone)]
+pub struct TranslateOptions {
+    /// 输入文件，`-` 表示 stdin
+    pub input: PathBuf,
+    /// 输出文件，不指定时写到 stdout
+    pub output: Option<PathBuf>,
+    /// 请求格式，不指定时自动检测
+    pub from: Option<RequestFormat>,
+    pub profile_arn: Option<String>,
+    pub reverse: bool,
+    pub to: ReverseOutput,
+    /// 反向转换时写入响应的模型名
+    pub model: String,
+    /// 反向转换时拆分 `<thinking>` 段落
+    pub thinking: bool,
+}
+
+/// 执行转换
+pub fn run(options: TranslateOptions) -> Result<()> {
+    let input = read_input(&options.input)?;
+    let output = if options.reverse {
+        reverse(&input, options.to, &options.model, options.thinking)?
+    } else {
+        forward(&input, options.from, options.profile_arn)?
+    };
+    write_output(options.output.as_deref(), output.as_bytes())
+}
+
+/// Anthropic / OpenAI 请求 → CodeWhisperer 请求
+fn forward(
+    input: &[u8],
+    from: Option<RequestFormat>,
+    profile_arn: Option<String>,
+) -> Result<String> {
+    let request: serde_json::Value =
+        serde_json::from_slice(input).context("输入不是有效的 JSON 请求")?;
+
+    let format = from.unwrap_or_else(|| {
+        let detected = translator::detect_format(&request);
+        info!("自动检测请求格式: {}", detected);
+        detected
+    });
+
+    let converted = translator::convert_request(&request, format, profile_arn)?;
+    Ok(format!("{}\n", serde_json::to_string_pretty(&converted)?))
+}
+
+/// CodeWhisperer Event Stream → Anthropic SSE / Message
+fn reverse(input: &[u8], to: ReverseOutput, model: &str, thinking: bool) -> Result<String> {
+    let events = event_stream::decode_all(input).context("解析 Event Stream 失败")?;
+    info!("解析到 {} 个 CodeWhisperer 事件", events.len());
+
+    let mut translator = CwToAnthropicTranslator::new(model).with_thinking(thinking);
+    let mut sse_events = Vec::new();
+    for event in events {
+        sse_events.extend(translator.translate_cw_event(event));
+    }
+    sse_events.extend(translator.finish());
+
+    Ok(match to {
+        ReverseOutput::Sse => sse_events
+            .iter()
+            .map(CwToAnthropicTranslator::format_sse)
+            .collect(),
+        ReverseOutput::Message => format!(
+            "{}\n",
+            serde_json::to_string_pretty(&collect_message(&sse_events))?
+        ),
+    })
+}
+
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    if path.as_os_str() == "-" {
+        let mut buf = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut buf)
+            .context("读取 stdin 失败")?;
+        return Ok(buf);
+    }
+    std::fs::read(path).with_context(|| format!("读取输入文件失败: {}", path.display()))
+}
+
+fn write_output(path: Option<&Path>, content: &[u8]) -> Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, content)
+            .with_context(|| format!("写入输出文件失败: {}", path.display())),
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(content)?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::event_stream::encode_event;
+    use serde_json::json;
+
+    #[test]
+    fn test_forward_detects_openai() {
+        let input = json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"}
+            ]
+        })
+        .to_string();
+
+        let output: serde_json::Value =
+            serde_json::from_str(&forward(input.as_bytes(), None, None).unwrap()).unwrap();
+        assert_eq!(
+            output["conversationState"]["currentMessage"]["userInputMessage"]["content"],
+            "Hi"
+        );
+    }
+
+    #[test]
+    fn test_reverse_outputs() {
+        let dump = [
+            encode_event("assistantResponseEvent", &json!({"content": "Hello"})),
+            encode_event("meteringEvent", &json!({"unit": "credit", "usage": 0.01})),
+        ]
+        .concat();
+
+        let sse = reverse(&dump, ReverseOutput::Sse, "claude-sonnet-4.5", false).unwrap();
+        assert!(sse.starts_with("event: message_start\n"));
+        assert!(sse.contains("\"text\":\"Hello\""));
+        assert!(sse.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+
+        let message: serde_json::Value = serde_json::from_str(
+            &reverse(&dump, ReverseOutput::Message, "claude-sonnet-4.5", false).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(message["content"][0]["text"], "Hello");
+        assert_eq!(message["stop_reason"], "end_turn");
+    }
+}
@@ -0,0 +1,233 @@
+//! 插件配置
+//!
+//! 对应插件目录中的 `config.json`（见 `plugin/config.json`），所有字段都有默认值，
+//! 配置文件缺失时使用默认配置。
+
+use crate::translator::anthropic_to_cw::SystemPromptStrategy;
+use crate::translator::context::ContextOverflow;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+
+/// 覆盖配置文件路径的环境变量
+pub const CONFIG_ENV: &str = "KIRO_PROVIDER_CONFIG";
+
+/// 配置文件名
+const CONFIG_FILE: &str = "config.json";
+
+lazy_static::lazy_static! {
+    static ref CONFIG_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+    /// 首次使用时加载，此后不再重新读取
+    static ref CURRENT: PluginConfig = load_current();
+}
+
+/// 插件配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginConfig {
+    pub enabled: bool,
+    pub timeout_ms: u64,
+    pub settings: Settings,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub risk_control: RiskControlSettings,
+    pub token_refresh: TokenRefreshSettings,
+    pub health_check: HealthCheckSettings,
+    pub retry: RetrySettings,
+    pub translation: TranslationSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskControlSettings {
+    pub machine_id_rotation: bool,
+    pub version_spoofing: bool,
+    pub fingerprint_isolation: bool,
+    pub hour_slot_variation: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenRefreshSettings {
+    pub auto_refresh: bool,
+    pub refresh_threshold_minutes: u64,
+    pub max_retry: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthCheckSettings {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    pub unhealthy_threshold: u32,
+}
+
+/// 插件直接调用上游时（`execute`、`serve`）的跨凭证重试
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrySettings {
+    /// 最多尝试的凭证数（含首次），1 表示不重试
+    pub max_attempts: u32,
+    /// 从首次尝试起算的截止时间，超过后不再换凭证重试
+    pub deadline_ms: u64,
+}
+
+/// 请求转换
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranslationSettings {
+    /// 系统提示的注入方式：history、prepend 或 response_style
+    pub system_prompt: SystemPromptStrategy,
+    /// 超出模型上下文时的处理方式：placeholder、truncate 或 off
+    pub context_overflow: ContextOverflow,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_ms: 60000,
+            settings: Settings::default(),
+        }
+    }
+}
+
+impl Default for RiskControlSettings {
+    fn default() -> Self {
+        Self {
+            machine_id_rotation: true,
+            version_spoofing: true,
+            fingerprint_isolation: true,
+            hour_slot_variation: true,
+        }
+    }
+}
+
+impl Default for TokenRefreshSettings {
+    fn default() -> Self {
+        Self {
+            auto_refresh: true,
+            refresh_threshold_minutes: 5,
+            max_retry: 3,
+        }
+    }
+}
+
+impl Default for HealthCheckSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_seconds: 300,
+            unhealthy_threshold: 3,
+        }
+    }
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            deadline_ms: 60000,
+        }
+    }
+}
+
+/// 设置配置文件路径（命令行 `--config` 参数）
+pub fn set_config_path(path: PathBuf) {
+    *CONFIG_PATH.write().unwrap() = Some(path);
+}
+
+/// 候选配置文件路径，按优先级排列
+///
+/// `--config` 参数、`KIRO_PROVIDER_CONFIG` 环境变量、可执行文件所在的插件目录、
+/// `<config_dir>/kiro-provider/config.json`。
+pub fn candidate_paths() -> Vec<PathBuf> {
+    if let Some(path) = CONFIG_PATH.read().unwrap().clone() {
+        return vec![path];
+    }
+    if let Ok(path) = std::env::var(CONFIG_ENV) {
+        if !path.is_empty() {
+            return vec![PathBuf::from(path)];
+        }
+    }
+
+    let mut paths = Vec::new();
+    if let Some(dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        paths.push(dir.join(CONFIG_FILE));
+    }
+    if let Some(dir) = dirs::config_dir() {
+        paths.push(dir.join("kiro-provider").join(CONFIG_FILE));
+    }
+    paths
+}
+
+/// 第一个存在的配置文件
+pub fn config_path() -> Option<PathBuf> {
+    candidate_paths().into_iter().find(|p| p.exists())
+}
+
+/// 解析配置文件
+pub fn load(path: &Path) -> Result<PluginConfig> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("读取配置文件失败: {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("解析配置文件失败: {}", path.display()))
+}
+
+/// 当前生效的配置
+///
+/// 配置文件缺失或无效时使用默认配置（`doctor` 会报告具体错误）。
+pub fn current() -> &'static PluginConfig {
+    &CURRENT
+}
+
+fn load_current() -> PluginConfig {
+    let Some(path) = config_path() else {
+        return PluginConfig::default();
+    };
+    load(&path).unwrap_or_else(|e| {
+        warn!("{:#}，使用默认配置", e);
+        PluginConfig::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_config_parses() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../plugin/config.json");
+        let config = load(&path).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.settings.token_refresh.refresh_threshold_minutes, 5);
+    }
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: PluginConfig =
+            serde_json::from_str(r#"{"settings": {"health_check": {"enabled": false}}}"#).unwrap();
+        assert!(!config.settings.health_check.enabled);
+        assert_eq!(config.settings.health_check.interval_seconds, 300);
+        assert_eq!(config.timeout_ms, 60000);
+        assert_eq!(config.settings.retry.max_attempts, 3);
+        assert_eq!(
+            config.settings.translation.system_prompt,
+            SystemPromptStrategy::History
+        );
+        assert_eq!(
+            config.settings.translation.context_overflow,
+            ContextOverflow::Placeholder
+        );
+
+        let invalid = serde_json::from_str::<PluginConfig>(r#"{"timeout_ms": "soon"}"#);
+        assert!(invalid.is_err());
+    }
+}
@@ -1,10 +1,11 @@
 //! 凭证数据结构
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Kiro OAuth 凭证
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct KiroCredentials {
     /// 凭证名称
@@ -29,12 +30,16 @@ pub struct KiroCredentials {
     /// Client ID Hash
     pub client_id_hash: Option<String>,
     /// 过期时间 (RFC3339 格式)
+    #[serde(alias = "expiresAt")]
     pub expire: Option<String>,
     /// 最后刷新时间
     pub last_refresh: Option<String>,
     /// 是否健康
     #[serde(default = "default_true")]
     pub is_healthy: bool,
+    /// 是否被手动停用（与健康状态独立，刷新成功不会自动恢复）
+    #[serde(default)]
+    pub disabled: bool,
     /// 使用次数
     #[serde(default)]
     pub usage_count: u64,
@@ -44,6 +49,9 @@ pub struct KiroCredentials {
     /// 最后错误信息
     #[serde(default)]
     pub last_error: Option<String>,
+    /// 最近一次重置健康状态和统计的时间 (RFC3339 格式)
+    #[serde(default)]
+    pub reset_at: Option<String>,
 }
 
 fn default_region() -> Option<String> {
@@ -73,15 +81,17 @@ impl Default for KiroCredentials {
             expire: None,
             last_refresh: None,
             is_healthy: true,
+            disabled: false,
             usage_count: 0,
             error_count: 0,
             last_error: None,
+            reset_at: None,
         }
     }
 }
 
 /// 获取的凭证
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AcquiredCredential {
     /// 凭证 ID
     pub id: String,
@@ -102,7 +112,7 @@ pub struct AcquiredCredential {
 }
 
 /// 凭证验证结果
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ValidationResult {
     /// 是否有效
     pub valid: bool,
@@ -3,18 +3,30 @@
 //! 这是一个独立的 CLI 工具，通过 JSON-RPC 与 ProxyCast 通信。
 //! 实现 CredentialProviderPlugin 接口的所有方法。
 
+mod cli;
 mod commands;
+mod config;
 mod credentials;
 mod fingerprint;
+mod models;
 mod provider;
 mod risk_control;
+mod rpc;
+mod server;
+mod shutdown;
+mod storage;
 mod token_refresh;
 mod translator;
+mod upstream;
 
 use clap::{Parser, Subcommand};
-use serde::{Deserialize, Serialize};
-use std::io::{self, BufRead, Write};
-use tracing::{debug, error, info};
+use rpc::framing::{Framing, DEFAULT_MAX_MESSAGE_SIZE};
+use rpc::transport::{ListenAddr, ServeOptions, AUTH_TOKEN_ENV};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::info;
 
 /// Kiro Provider CLI
 #[derive(Parser)]
@@ -28,6 +40,30 @@ struct Cli {
     /// Run in JSON-RPC mode (stdin/stdout)
     #[arg(long)]
     json_rpc: bool,
+
+    /// Serve JSON-RPC on stdio, unix:/path/to/socket or tcp:127.0.0.1:PORT
+    #[arg(long, value_name = "ADDR")]
+    listen: Option<ListenAddr>,
+
+    /// Shared secret clients must send via `authenticate` (socket/TCP only, or $KIRO_PROVIDER_AUTH_TOKEN)
+    #[arg(long)]
+    auth_token: Option<String>,
+
+    /// JSON-RPC message framing: newline or content-length (clients may also negotiate via `initialize`)
+    #[arg(long, value_name = "MODE", default_value_t = Framing::Newline)]
+    framing: Framing,
+
+    /// Maximum size in bytes of a content-length framed message, and of `serve` request bodies
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_MESSAGE_SIZE)]
+    max_message_size: usize,
+
+    /// Credential store path (default: $KIRO_PROVIDER_STORE or <config_dir>/kiro-provider/credentials.json)
+    #[arg(long, global = true)]
+    store: Option<PathBuf>,
+
+    /// Plugin config file (default: $KIRO_PROVIDER_CONFIG, config.json next to the binary, or <config_dir>/kiro-provider/config.json)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -36,70 +72,124 @@ enum Commands {
     Info,
     /// List supported models
     Models,
-    /// Validate a credential
+    /// Validate a credential from the store or a token file
     Validate {
+        /// Credential ID in the store
+        #[arg(long, required_unless_present = "file", conflicts_with = "file")]
+        credential_id: Option<String>,
+        /// Credential JSON file (e.g. Kiro IDE kiro-auth-token.json)
         #[arg(long)]
-        credential_id: String,
+        file: Option<PathBuf>,
     },
-    /// Refresh token
+    /// Refresh the access token of a credential
     Refresh {
+        /// Credential ID in the store (refreshed tokens are saved to the store)
+        #[arg(long, required_unless_present = "file", conflicts_with = "file")]
+        credential_id: Option<String>,
+        /// Credential JSON file (e.g. Kiro IDE kiro-auth-token.json)
         #[arg(long)]
-        credential_id: String,
+        file: Option<PathBuf>,
+        /// Write the refreshed tokens back to --file
+        #[arg(long, requires = "file")]
+        write_back: bool,
+    },
+    /// Manage the credential store
+    Credentials {
+        #[command(subcommand)]
+        command: cli::credentials::CredentialsCommand,
+    },
+    /// Log in and add the resulting credential to the store
+    #[command(group(clap::ArgGroup::new("method").required(true).args(["idc", "social"])))]
+    Login {
+        /// AWS IAM Identity Center (SSO OIDC) device authorization
+        #[arg(long, requires = "start_url")]
+        idc: bool,
+        /// Kiro social login (Google/GitHub) via authorization code + PKCE
+        #[arg(long)]
+        social: bool,
+        /// IAM Identity Center start URL, e.g. https://d-xxxxxxxxxx.awsapps.com/start
+        #[arg(long)]
+        start_url: Option<String>,
+        /// Region of the Identity Center instance
+        #[arg(long, default_value = "us-east-1")]
+        region: String,
+        /// SSO OIDC base URL (default: https://oidc.<region>.amazonaws.com)
+        #[arg(long, value_name = "URL")]
+        oidc_url: Option<String>,
+        /// Social identity provider: Google or Github
+        #[arg(long, default_value = "Google")]
+        idp: String,
+        /// Kiro desktop auth base URL (default: https://prod.us-east-1.auth.desktop.kiro.dev)
+        #[arg(long, value_name = "URL")]
+        auth_url: Option<String>,
+        /// Loopback port for the social login redirect (0 picks a free port)
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+        /// Seconds to wait for the browser to complete social login
+        #[arg(long, value_name = "SECS", default_value_t = 300)]
+        timeout: u64,
+        /// Display name for the new credential
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Probe credentials against the upstream API (refreshing tokens if needed)
+    Health {
+        /// Credential ID to check (default: the whole pool)
+        #[arg(long)]
+        credential_id: Option<String>,
+        /// Output format: json or table
+        #[arg(long, value_name = "FORMAT", default_value_t = cli::OutputFormat::Json)]
+        format: cli::OutputFormat,
+        /// Probe timeout in seconds
+        #[arg(long, value_name = "SECS", default_value_t = 15)]
+        timeout: u64,
+    },
+    /// Serve an Anthropic-compatible HTTP API backed by the credential pool
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+        /// API key clients must send via x-api-key or Authorization: Bearer (or $KIRO_PROVIDER_AUTH_TOKEN)
+        #[arg(long)]
+        auth_token: Option<String>,
+    },
+    /// Diagnose fingerprint, clock, config, store and network problems
+    Doctor {
+        /// Output format: json or table
+        #[arg(long, value_name = "FORMAT", default_value_t = cli::OutputFormat::Table)]
+        format: cli::OutputFormat,
+    },
+    /// Translate requests to CodeWhisperer, or CodeWhisperer event streams back to Anthropic
+    Translate {
+        /// Input file (`-` for stdin): a request JSON, or an event-stream dump with --reverse
+        #[arg(long, default_value = "-")]
+        input: PathBuf,
+        /// Output file (default: stdout)
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Request format: anthropic or openai (default: auto-detect)
+        #[arg(long, value_name = "FORMAT", conflicts_with = "reverse")]
+        from: Option<translator::RequestFormat>,
+        /// Profile ARN to include in the CodeWhisperer request
+        #[arg(long)]
+        profile_arn: Option<String>,
+        /// Convert a CodeWhisperer event-stream dump into an Anthropic response
+        #[arg(long)]
+        reverse: bool,
+        /// Reverse output: sse or message
+        #[arg(long, value_name = "FORMAT", default_value_t = cli::translate::ReverseOutput::Sse)]
+        to: cli::translate::ReverseOutput,
+        /// Model name reported in the reversed response
+        #[arg(long, default_value = "claude-sonnet-4-5")]
+        model: String,
+        /// Split inline <thinking> segments into thinking blocks when reversing
+        #[arg(long, requires = "reverse")]
+        thinking: bool,
     },
-}
-
-/// JSON-RPC Request
-#[derive(Debug, Deserialize)]
-struct JsonRpcRequest {
-    jsonrpc: String,
-    method: String,
-    params: serde_json::Value,
-    id: serde_json::Value,
-}
-
-/// JSON-RPC Response
-#[derive(Debug, Serialize)]
-struct JsonRpcResponse {
-    jsonrpc: String,
-    result: Option<serde_json::Value>,
-    error: Option<JsonRpcError>,
-    id: serde_json::Value,
-}
-
-/// JSON-RPC Error
-#[derive(Debug, Serialize)]
-struct JsonRpcError {
-    code: i32,
-    message: String,
-    data: Option<serde_json::Value>,
-}
-
-impl JsonRpcResponse {
-    fn success(id: serde_json::Value, result: serde_json::Value) -> Self {
-        Self {
-            jsonrpc: "2.0".to_string(),
-            result: Some(result),
-            error: None,
-            id,
-        }
-    }
-
-    fn error(id: serde_json::Value, code: i32, message: String) -> Self {
-        Self {
-            jsonrpc: "2.0".to_string(),
-            result: None,
-            error: Some(JsonRpcError {
-                code,
-                message,
-                data: None,
-            }),
-            id,
-        }
-    }
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -111,210 +201,130 @@ async fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
 
-    if cli.json_rpc {
-        run_json_rpc_mode().await?;
+    if let Some(store) = cli.store {
+        storage::set_store_path(store);
+    }
+    if let Some(config) = cli.config {
+        config::set_config_path(config);
+    }
+
+    if cli.json_rpc || cli.listen.is_some() {
+        info!("Starting Kiro Provider in JSON-RPC mode");
+        let addr = cli.listen.unwrap_or(ListenAddr::Stdio);
+        let options = ServeOptions {
+            auth_token: cli
+                .auth_token
+                .or_else(|| std::env::var(AUTH_TOKEN_ENV).ok()),
+            framing: cli.framing,
+            max_message_size: cli.max_message_size,
+        };
+        rpc::transport::serve(addr, options).await?;
     } else if let Some(command) = cli.command {
         match command {
             Commands::Info => {
-                let info = get_plugin_info();
+                let info = provider::get_plugin_info();
                 println!("{}", serde_json::to_string_pretty(&info)?);
             }
             Commands::Models => {
                 let models = provider::list_models();
                 println!("{}", serde_json::to_string_pretty(&models)?);
             }
-            Commands::Validate { credential_id } => {
-                info!("Validating credential: {}", credential_id);
-                // TODO: Implement validation
-                println!("{{\"valid\": true}}");
+            Commands::Validate {
+                credential_id,
+                file,
+            } => {
+                let source = cli::CredentialSource::from_args(credential_id, file)?;
+                return cli::token::validate(source).await;
             }
-            Commands::Refresh { credential_id } => {
-                info!("Refreshing token for: {}", credential_id);
-                // TODO: Implement refresh
-                println!("{{\"success\": true}}");
+            Commands::Refresh {
+                credential_id,
+                file,
+                write_back,
+            } => {
+                let source = cli::CredentialSource::from_args(credential_id, file)?;
+                return cli::token::refresh(source, write_back).await;
             }
-        }
-    } else {
-        // Default: print info
-        let info = get_plugin_info();
-        println!("{}", serde_json::to_string_pretty(&info)?);
-    }
-
-    Ok(())
-}
-
-/// Run in JSON-RPC mode
-async fn run_json_rpc_mode() -> anyhow::Result<()> {
-    info!("Starting Kiro Provider in JSON-RPC mode");
-
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-
-    for line in stdin.lock().lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            continue;
-        }
-
-        debug!("Received: {}", line);
-
-        let response = match serde_json::from_str::<JsonRpcRequest>(&line) {
-            Ok(request) => handle_request(request).await,
-            Err(e) => JsonRpcResponse::error(
-                serde_json::Value::Null,
-                -32700,
-                format!("Parse error: {}", e),
-            ),
-        };
-
-        let response_str = serde_json::to_string(&response)?;
-        debug!("Sending: {}", response_str);
-
-        writeln!(stdout, "{}", response_str)?;
-        stdout.flush()?;
-    }
-
-    Ok(())
-}
-
-/// Handle a JSON-RPC request
-async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
-    let id = request.id.clone();
-
-    match request.method.as_str() {
-        "get_info" => {
-            let info = get_plugin_info();
-            JsonRpcResponse::success(id, serde_json::to_value(info).unwrap())
-        }
-        "list_models" => {
-            let models = provider::list_models();
-            JsonRpcResponse::success(id, serde_json::to_value(models).unwrap())
-        }
-        "supports_model" => {
-            let model = request.params["model"].as_str().unwrap_or("");
-            let supports = provider::supports_model(model);
-            JsonRpcResponse::success(id, serde_json::json!({ "supports": supports }))
-        }
-        "acquire_credential" => {
-            let model = request.params["model"].as_str().unwrap_or("");
-            match provider::acquire_credential(model).await {
-                Ok(credential) => {
-                    JsonRpcResponse::success(id, serde_json::to_value(credential).unwrap())
-                }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            Commands::Credentials { command } => {
+                cli::credentials::run(command).await?;
             }
-        }
-        "release_credential" => {
-            let credential_id = request.params["credential_id"].as_str().unwrap_or("");
-            let result = &request.params["result"];
-            match provider::release_credential(credential_id, result.clone()).await {
-                Ok(_) => JsonRpcResponse::success(id, serde_json::json!({})),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
-            }
-        }
-        "validate_credential" => {
-            let credential_id = request.params["credential_id"].as_str().unwrap_or("");
-            match provider::validate_credential(credential_id).await {
-                Ok(result) => JsonRpcResponse::success(id, serde_json::to_value(result).unwrap()),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
-            }
-        }
-        "refresh_token" => {
-            let credential_id = request.params["credential_id"].as_str().unwrap_or("");
-            match provider::refresh_token(credential_id).await {
-                Ok(result) => JsonRpcResponse::success(id, serde_json::to_value(result).unwrap()),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
-            }
-        }
-        "create_credential" => {
-            let auth_type = request.params["auth_type"].as_str().unwrap_or("oauth");
-            let config = request.params["config"].clone();
-            match provider::create_credential(auth_type, config).await {
-                Ok(credential_id) => {
-                    JsonRpcResponse::success(id, serde_json::json!({ "credential_id": credential_id }))
+            Commands::Login {
+                idc: _,
+                social,
+                start_url,
+                region,
+                oidc_url,
+                idp,
+                auth_url,
+                port,
+                timeout,
+                name,
+            } => {
+                if social {
+                    cli::login::login_social(cli::login::SocialLoginOptions {
+                        idp,
+                        region,
+                        auth_url,
+                        port,
+                        timeout: Duration::from_secs(timeout),
+                        name,
+                    })
+                    .await?;
+                } else {
+                    cli::login::login_idc(cli::login::IdcLoginOptions {
+                        start_url: start_url.unwrap_or_default(),
+                        region,
+                        oidc_url,
+                        name,
+                    })
+                    .await?;
                 }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
             }
-        }
-        "transform_request" => {
-            let request_body = request.params["request"].clone();
-            match provider::transform_request(request_body).await {
-                Ok(transformed) => {
-                    JsonRpcResponse::success(id, serde_json::json!({ "request": transformed }))
-                }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            Commands::Health {
+                credential_id,
+                format,
+                timeout,
+            } => {
+                return cli::health::run(credential_id, format, Duration::from_secs(timeout)).await;
             }
-        }
-        "transform_response" => {
-            let response_body = request.params["response"].clone();
-            match provider::transform_response(response_body).await {
-                Ok(transformed) => {
-                    JsonRpcResponse::success(id, serde_json::json!({ "response": transformed }))
-                }
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            Commands::Serve { bind, auth_token } => {
+                info!("Starting Kiro Provider in HTTP server mode");
+                server::serve(server::ServerOptions {
+                    bind,
+                    auth_token: auth_token.or_else(|| std::env::var(AUTH_TOKEN_ENV).ok()),
+                    max_body_size: cli.max_message_size,
+                })
+                .await?;
             }
-        }
-        "apply_risk_control" => {
-            let mut request_body = request.params["request"].clone();
-            let credential_id = request.params["credential_id"].as_str().unwrap_or("");
-            match provider::apply_risk_control(&mut request_body, credential_id).await {
-                Ok(_) => JsonRpcResponse::success(id, serde_json::json!({ "request": request_body })),
-                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            Commands::Doctor { format } => {
+                return cli::doctor::run(format).await;
+            }
+            Commands::Translate {
+                input,
+                output,
+                from,
+                profile_arn,
+                reverse,
+                to,
+                model,
+                thinking,
+            } => {
+                cli::translate::run(cli::translate::TranslateOptions {
+                    input,
+                    output,
+                    from,
+                    profile_arn,
+                    reverse,
+                    to,
+                    model,
+                    thinking,
+                })?;
             }
         }
-        "parse_error" => {
-            let status = request.params["status"].as_u64().unwrap_or(0) as u16;
-            let body = request.params["body"].as_str().unwrap_or("");
-            let error = provider::parse_error(status, body);
-            JsonRpcResponse::success(id, serde_json::to_value(error).unwrap_or_default())
-        }
-        _ => JsonRpcResponse::error(id, -32601, format!("Method not found: {}", request.method)),
+    } else {
+        // Default: print info
+        let info = provider::get_plugin_info();
+        println!("{}", serde_json::to_string_pretty(&info)?);
     }
-}
 
-/// Get plugin info
-fn get_plugin_info() -> serde_json::Value {
-    serde_json::json!({
-        "id": "kiro",
-        "display_name": "Kiro (CodeWhisperer)",
-        "version": env!("CARGO_PKG_VERSION"),
-        "description": "AWS Kiro / CodeWhisperer OAuth 凭证提供商，支持 Social 和 IdC 认证",
-        "target_protocol": "anthropic",
-        "category": "oauth",
-        "auth_types": [
-            {
-                "id": "oauth",
-                "display_name": "OAuth 登录",
-                "description": "使用 Kiro IDE OAuth 授权，支持 Social 和 IdC 登录",
-                "category": "oauth",
-                "icon": "Key"
-            }
-        ],
-        "model_families": [
-            {
-                "name": "opus",
-                "pattern": "claude-opus-*",
-                "tier": 3,
-                "description": "Claude Opus - 最强能力"
-            },
-            {
-                "name": "sonnet",
-                "pattern": "claude-*-sonnet*",
-                "tier": 2,
-                "description": "Claude Sonnet - 均衡选择"
-            },
-            {
-                "name": "haiku",
-                "pattern": "claude-*-haiku*",
-                "tier": 1,
-                "description": "Claude Haiku - 快速响应"
-            },
-            {
-                "name": "all-claude",
-                "pattern": "claude-*",
-                "tier": null,
-                "description": "所有 Claude 模型"
-            }
-        ]
-    })
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,318 @@
+//! 模型目录
+//!
+//! 支持的模型及其 CodeWhisperer modelId、别名、上下文长度等信息统一由
+//! `plugin/models.json` 定义，编译时内嵌为默认目录。配置文件所在目录中的
+//! `models.json` 会覆盖默认目录：同 `id` 的模型、同 `name` 的模型族整体替换，
+//! 其余追加到末尾。
+
+use crate::config;
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// 内嵌的默认目录
+const DEFAULT_CATALOG: &str = include_str!("../../plugin/models.json");
+
+/// 用户覆盖文件名，与配置文件放在同一目录
+const MODELS_FILE: &str = "models.json";
+
+lazy_static::lazy_static! {
+    /// 首次使用时加载，此后不再重新读取
+    static ref CATALOG: ModelCatalog = load_current();
+}
+
+/// 模型目录
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelCatalog {
+    pub families: Vec<ModelFamily>,
+    pub models: Vec<ModelSpec>,
+}
+
+/// 模型族
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ModelFamily {
+    pub name: String,
+    /// 模型名 glob 模式
+    pub pattern: String,
+    /// 能力等级，越大越强；不区分等级的族为 None
+    #[serde(default)]
+    pub tier: Option<u8>,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// 单个模型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSpec {
+    /// 对外公开的模型 ID
+    pub id: String,
+    pub display_name: String,
+    /// 同样接受的其他名称（不带日期的别名等）
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// 发往 CodeWhisperer 的 modelId
+    pub cw_model_id: String,
+    #[serde(default)]
+    pub family: Option<String>,
+    #[serde(default)]
+    pub tier: Option<u8>,
+    #[serde(default)]
+    pub context_length: Option<u32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub supports_vision: bool,
+    #[serde(default)]
+    pub supports_tools: bool,
+}
+
+/// 模型名在目录中的解析结果
+#[derive(Debug, Clone, Copy)]
+pub struct ModelMatch<'a> {
+    /// 目录中的模型，为 None 时不支持该模型
+    pub spec: Option<&'a ModelSpec>,
+    /// 所属模型族
+    pub family: Option<&'a ModelFamily>,
+    /// 不支持时建议使用的最接近模型
+    pub suggestion: Option<&'a ModelSpec>,
+}
+
+impl ModelMatch<'_> {
+    /// 能力等级，模型未声明时取模型族的等级
+    pub fn tier(&self) -> Option<u8> {
+        self.spec
+            .and_then(|s| s.tier)
+            .or_else(|| self.family.and_then(|f| f.tier))
+    }
+}
+
+/// 不属于任何模型族时，相似度达到该值才给出建议（避免为无关模型给出建议）
+const MIN_SUGGESTION_SIMILARITY: f64 = 0.6;
+
+impl ModelCatalog {
+    /// 解析模型名：目录中的模型、所属模型族，以及不支持时的建议
+    ///
+    /// 只有目录中的 ID 或别名才受支持；模型族按声明顺序用 glob 模式匹配，
+    /// 目录中的模型优先使用自身声明的族。
+    pub fn resolve(&self, model: &str) -> ModelMatch<'_> {
+        if let Some(spec) = self.find(model) {
+            let family = spec
+                .family
+                .as_deref()
+                .and_then(|name| self.families.iter().find(|f| f.name == name))
+                .or_else(|| self.match_family(&spec.id));
+            return ModelMatch {
+                spec: Some(spec),
+                family,
+                suggestion: None,
+            };
+        }
+
+        let family = self.match_family(model);
+        ModelMatch {
+            spec: None,
+            family,
+            suggestion: self.suggest(model, family),
+        }
+    }
+
+    /// 第一个 glob 模式匹配模型名的模型族，无效的模式视为不匹配
+    pub fn match_family(&self, model: &str) -> Option<&ModelFamily> {
+        self.families
+            .iter()
+            .find(|f| glob::Pattern::new(&f.pattern).is_ok_and(|pattern| pattern.matches(model)))
+    }
+
+    /// 与模型名最接近的模型，优先在同一模型族中查找
+    fn suggest(&self, model: &str, family: Option<&ModelFamily>) -> Option<&ModelSpec> {
+        let closest = |same_family: bool| {
+            self.models
+                .iter()
+                .filter(|m| {
+                    !same_family
+                        || family.is_some_and(|f| m.family.as_deref() == Some(f.name.as_str()))
+                })
+                .map(|spec| (spec, similarity(model, spec)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+        };
+
+        if let Some((spec, _)) = closest(true) {
+            return Some(spec);
+        }
+        let (spec, score) = closest(false)?;
+        (family.is_some() || score >= MIN_SUGGESTION_SIMILARITY).then_some(spec)
+    }
+
+    /// 按 ID 或别名查找模型（完整匹配）
+    pub fn find(&self, model: &str) -> Option<&ModelSpec> {
+        self.models
+            .iter()
+            .find(|m| m.id == model || m.aliases.iter().any(|a| a == model))
+    }
+
+    /// 用覆盖目录中的条目替换或追加
+    fn merge(&mut self, overrides: ModelCatalog) {
+        for family in overrides.families {
+            match self.families.iter_mut().find(|f| f.name == family.name) {
+                Some(existing) => *existing = family,
+                None => self.families.push(family),
+            }
+        }
+        for model in overrides.models {
+            match self.models.iter_mut().find(|m| m.id == model.id) {
+                Some(existing) => *existing = model,
+                None => self.models.push(model),
+            }
+        }
+    }
+}
+
+/// 模型名与模型 ID 及别名的最高相似度（0 到 1）
+fn similarity(model: &str, spec: &ModelSpec) -> f64 {
+    std::iter::once(&spec.id)
+        .chain(&spec.aliases)
+        .map(|name| strsim::normalized_levenshtein(model, name))
+        .fold(0.0, f64::max)
+}
+
+/// 当前生效的模型目录
+///
+/// 覆盖文件无效时只使用默认目录（`doctor` 会报告具体错误）。
+pub fn catalog() -> &'static ModelCatalog {
+    &CATALOG
+}
+
+/// 内嵌的默认目录
+pub fn default_catalog() -> ModelCatalog {
+    serde_json::from_str(DEFAULT_CATALOG).expect("内嵌的 models.json 无效")
+}
+
+/// 第一个存在的覆盖文件
+///
+/// 与配置文件使用相同的查找目录（见 `config::candidate_paths`）。
+pub fn override_path() -> Option<PathBuf> {
+    config::candidate_paths()
+        .into_iter()
+        .map(|p| p.with_file_name(MODELS_FILE))
+        .find(|p| p.exists())
+}
+
+/// 解析覆盖文件
+pub fn load(path: &Path) -> Result<ModelCatalog> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("读取模型目录失败: {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("解析模型目录失败: {}", path.display()))
+}
+
+fn load_current() -> ModelCatalog {
+    let mut catalog = default_catalog();
+    if let Some(path) = override_path() {
+        match load(&path) {
+            Ok(overrides) => catalog.merge(overrides),
+            Err(e) => warn!("{:#}，使用默认模型目录", e),
+        }
+    }
+    catalog
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_catalog() {
+        let catalog = default_catalog();
+        for id in ["claude-sonnet-4-20250514", "claude-haiku-4-5-20251001"] {
+            assert!(catalog.find(id).is_some(), "{}", id);
+        }
+        // 每个模型的族都已声明
+        for model in &catalog.models {
+            let family = model.family.as_deref().unwrap();
+            assert!(catalog.families.iter().any(|f| f.name == family));
+        }
+    }
+
+    #[test]
+    fn test_find_exact_id_or_alias() {
+        let catalog = default_catalog();
+        assert_eq!(
+            catalog.find("claude-sonnet-4-5").unwrap().cw_model_id,
+            "CLAUDE_SONNET_4_5_20250929_V1_0"
+        );
+        assert_eq!(
+            catalog
+                .find("claude-sonnet-4-5-20250514")
+                .unwrap()
+                .cw_model_id,
+            "CLAUDE_SONNET_4_5_20250514_V1_0"
+        );
+        // 不做子串匹配，旧模型不会被映射到其他模型
+        assert!(catalog.find("claude-3-5-sonnet-20241022").is_none());
+        assert!(catalog.find("claude-sonnet-4-5-thinking").is_none());
+    }
+
+    #[test]
+    fn test_merge_overrides() {
+        let mut catalog = default_catalog();
+        let count = catalog.models.len();
+        let overrides: ModelCatalog = serde_json::from_str(
+            r#"{"models": [
+                {"id": "claude-sonnet-4-20250514", "display_name": "Sonnet 4",
+                 "cw_model_id": "CUSTOM_SONNET_4"},
+                {"id": "claude-custom", "display_name": "Custom",
+                 "cw_model_id": "CUSTOM"}
+            ]}"#,
+        )
+        .unwrap();
+        catalog.merge(overrides);
+
+        assert_eq!(catalog.models.len(), count + 1);
+        assert_eq!(
+            catalog
+                .find("claude-sonnet-4-20250514")
+                .unwrap()
+                .cw_model_id,
+            "CUSTOM_SONNET_4"
+        );
+        assert_eq!(catalog.find("claude-custom").unwrap().cw_model_id, "CUSTOM");
+        assert_eq!(catalog.families.len(), default_catalog().families.len());
+    }
+
+    #[test]
+    fn test_resolve_family_and_tier() {
+        let catalog = default_catalog();
+        let matched = catalog.resolve("claude-opus-4-5");
+        assert_eq!(matched.spec.unwrap().id, "claude-opus-4-5-20251101");
+        assert_eq!(matched.family.unwrap().name, "opus");
+        assert_eq!(matched.tier(), Some(3));
+        assert!(matched.suggestion.is_none());
+
+        let matched = catalog.resolve("claude-haiku-4-5-20251001");
+        assert_eq!(matched.family.unwrap().name, "haiku");
+        assert_eq!(matched.tier(), Some(1));
+    }
+
+    #[test]
+    fn test_resolve_unsupported_suggests_nearest() {
+        let catalog = default_catalog();
+
+        // 已下线的模型：匹配模型族但不在目录中，建议同族模型
+        let retired = catalog.resolve("claude-3-5-sonnet-20241022");
+        assert!(retired.spec.is_none());
+        assert_eq!(retired.family.unwrap().name, "sonnet");
+        assert_eq!(retired.suggestion.unwrap().id, "claude-3-7-sonnet-20250219");
+
+        // 拼写错误：不匹配任何族，按相似度给出建议
+        let typo = catalog.resolve("cluade-opus-4-5");
+        assert!(typo.spec.is_none() && typo.family.is_none());
+        assert_eq!(typo.suggestion.unwrap().id, "claude-opus-4-5-20251101");
+
+        // 无关模型不给建议
+        let other = catalog.resolve("gpt-4o");
+        assert!(other.spec.is_none() && other.family.is_none());
+        assert!(other.suggestion.is_none());
+    }
+}
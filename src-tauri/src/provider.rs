@@ -4,28 +4,60 @@
 
 use crate::credentials::{AcquiredCredential, KiroCredentials, ValidationResult};
 use crate::fingerprint::generate_machine_id_from_credentials;
+use crate::models::{self, ModelFamily, ModelSpec};
 use crate::risk_control::get_kiro_version;
-use crate::token_refresh::TokenRefreshResult;
+use crate::storage;
+use crate::token_refresh::{is_token_expired, TokenRefreshResult};
+use crate::translator::{self, anthropic_to_cw::CodeWhispererRequest};
 use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
 /// 模型信息
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ModelInfo {
     pub id: String,
     pub display_name: String,
     pub family: Option<String>,
+    pub tier: Option<u8>,
     pub context_length: Option<u32>,
+    pub max_output_tokens: Option<u32>,
     pub supports_vision: bool,
     pub supports_tools: bool,
 }
 
+impl From<&ModelSpec> for ModelInfo {
+    fn from(spec: &ModelSpec) -> Self {
+        Self {
+            id: spec.id.clone(),
+            display_name: spec.display_name.clone(),
+            family: spec.family.clone(),
+            tier: spec.tier,
+            context_length: spec.context_length,
+            max_output_tokens: spec.max_output_tokens,
+            supports_vision: spec.supports_vision,
+            supports_tools: spec.supports_tools,
+        }
+    }
+}
+
+/// 存储中凭证的刷新结果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct StoredRefreshResult {
+    #[serde(flatten)]
+    pub result: TokenRefreshResult,
+    /// 新 token 是否已写回存储；为 false 时轮换后的 refresh_token 只在内存中
+    pub persisted: bool,
+}
+
 /// Provider 错误
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProviderError {
     pub error_type: String,
     pub message: String,
@@ -38,118 +70,223 @@ pub struct ProviderError {
 lazy_static::lazy_static! {
     static ref CREDENTIALS: Arc<RwLock<HashMap<String, KiroCredentials>>> =
         Arc::new(RwLock::new(HashMap::new()));
+    /// 凭证冷却截止时间（限流、服务器错误后暂不分配），只在本进程内有效
+    static ref COOLDOWNS: RwLock<HashMap<String, Instant>> = RwLock::new(HashMap::new());
+    /// 每个凭证的刷新锁，同一凭证的刷新依次进行
+    static ref REFRESH_LOCKS: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+/// 插件信息（`get_info`）
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PluginInfo {
+    pub id: String,
+    pub display_name: String,
+    pub version: String,
+    pub description: String,
+    /// 上游使用的协议
+    pub target_protocol: String,
+    pub category: String,
+    pub auth_types: Vec<AuthTypeInfo>,
+    pub model_families: Vec<ModelFamily>,
+}
+
+/// 支持的认证方式
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct AuthTypeInfo {
+    pub id: String,
+    pub display_name: String,
+    pub description: String,
+    pub category: String,
+    pub icon: String,
+}
+
+/// Get plugin info
+pub fn get_plugin_info() -> PluginInfo {
+    PluginInfo {
+        id: "kiro".to_string(),
+        display_name: "Kiro (CodeWhisperer)".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        description: "AWS Kiro / CodeWhisperer OAuth 凭证提供商，支持 Social 和 IdC 认证"
+            .to_string(),
+        target_protocol: "anthropic".to_string(),
+        category: "oauth".to_string(),
+        auth_types: vec![AuthTypeInfo {
+            id: "oauth".to_string(),
+            display_name: "OAuth 登录".to_string(),
+            description: "使用 Kiro IDE OAuth 授权，支持 Social 和 IdC 登录".to_string(),
+            category: "oauth".to_string(),
+            icon: "Key".to_string(),
+        }],
+        model_families: models::catalog().families.clone(),
+    }
+}
+
+/// 从持久化存储加载凭证池，返回加载的凭证数
+pub async fn load_credentials() -> Result<usize> {
+    let loaded = storage::load(&storage::store_path())?;
+    let count = loaded.len();
+
+    let mut creds = CREDENTIALS.write().await;
+    creds.extend(loaded);
+
+    info!("已加载 {} 个凭证", count);
+    Ok(count)
 }
 
-/// 列出支持的模型
+/// 将凭证池写回持久化存储
+pub async fn flush_credentials() -> Result<()> {
+    let mut creds = CREDENTIALS.write().await;
+    save_merged(&mut creds, |_| Ok(()))
+}
+
+/// 重新读取存储，合并本进程的运行状态并应用 `edit` 后写回
+///
+/// 写回的结果同时替换内存中的凭证池，其他进程（`credentials` 子命令）对存储的
+/// 修改由此生效而不会被覆盖（见 `storage::merge_runtime`）。
+fn save_merged<T>(
+    creds: &mut HashMap<String, KiroCredentials>,
+    edit: impl FnOnce(&mut HashMap<String, KiroCredentials>) -> Result<T>,
+) -> Result<T> {
+    let path = storage::store_path();
+    let mut latest = storage::load(&path)?;
+    storage::merge_runtime(&mut latest, creds);
+    let value = edit(&mut latest)?;
+    storage::save(&path, &latest)?;
+    *creds = latest;
+    Ok(value)
+}
+
+/// 列出支持的模型（见 `models` 模型目录）
 pub fn list_models() -> Vec<ModelInfo> {
-    vec![
-        ModelInfo {
-            id: "claude-sonnet-4-5-20250514".to_string(),
-            display_name: "Claude Sonnet 4.5".to_string(),
-            family: Some("sonnet".to_string()),
-            context_length: Some(200000),
-            supports_vision: true,
-            supports_tools: true,
-        },
-        ModelInfo {
-            id: "claude-opus-4-5-20251101".to_string(),
-            display_name: "Claude Opus 4.5".to_string(),
-            family: Some("opus".to_string()),
-            context_length: Some(200000),
-            supports_vision: true,
-            supports_tools: true,
-        },
-        ModelInfo {
-            id: "claude-3-5-sonnet-20241022".to_string(),
-            display_name: "Claude 3.5 Sonnet".to_string(),
-            family: Some("sonnet".to_string()),
-            context_length: Some(200000),
-            supports_vision: true,
-            supports_tools: true,
-        },
-        ModelInfo {
-            id: "claude-3-5-haiku-20241022".to_string(),
-            display_name: "Claude 3.5 Haiku".to_string(),
-            family: Some("haiku".to_string()),
-            context_length: Some(200000),
-            supports_vision: true,
-            supports_tools: true,
-        },
-        ModelInfo {
-            id: "claude-3-7-sonnet-20250219".to_string(),
-            display_name: "Claude 3.7 Sonnet".to_string(),
-            family: Some("sonnet".to_string()),
-            context_length: Some(200000),
-            supports_vision: true,
-            supports_tools: true,
-        },
-    ]
-}
-
-/// 检查是否支持某个模型
+    models::catalog()
+        .models
+        .iter()
+        .map(ModelInfo::from)
+        .collect()
+}
+
+/// 检查是否支持某个模型（模型目录中的 ID 或别名）
 pub fn supports_model(model: &str) -> bool {
-    model.starts_with("claude-")
+    models::catalog().find(model).is_some()
 }
 
-/// 获取凭证
-pub async fn acquire_credential(model: &str) -> Result<AcquiredCredential> {
+/// 不支持模型时的错误信息，带上最接近的可用模型
+pub fn unsupported_model_message(model: &str) -> String {
+    match models::catalog().resolve(model).suggestion {
+        Some(suggestion) => format!("不支持的模型: {}，是否要使用 {}？", model, suggestion.id),
+        None => format!("不支持的模型: {}", model),
+    }
+}
+
+/// 为模型选择一个可用凭证，返回凭证 ID
+///
+/// `exclude` 中的凭证不参与选择（同一请求中已经失败的凭证），
+/// 冷却中的凭证（见 `release_credential`）也不参与选择。
+pub async fn select_credential(model: &str, exclude: &[String]) -> Result<String> {
     if !supports_model(model) {
-        anyhow::bail!("不支持的模型: {}", model);
+        anyhow::bail!(unsupported_model_message(model));
     }
 
     let creds = CREDENTIALS.read().await;
+    let cooldowns = COOLDOWNS.read().await;
+    let now = Instant::now();
 
-    // 查找健康的凭证
+    // 查找健康且未停用的凭证
     let healthy_creds: Vec<_> = creds
         .iter()
-        .filter(|(_, c)| c.is_healthy)
+        .filter(|(id, c)| c.is_healthy && !c.disabled && !exclude.contains(id))
         .collect();
 
-    if healthy_creds.is_empty() {
-        anyhow::bail!("没有可用的健康凭证");
+    // 选择第一个不在冷却中的健康凭证
+    let available = healthy_creds
+        .iter()
+        .find(|(id, _)| cooldowns.get(*id).is_none_or(|until| *until <= now));
+    match available {
+        Some((id, _)) => Ok((*id).clone()),
+        None if !healthy_creds.is_empty() => anyhow::bail!("可用凭证都在冷却中，请稍后重试"),
+        None => anyhow::bail!("没有可用的健康凭证"),
     }
+}
+
+/// 获取凭证
+pub async fn acquire_credential(model: &str) -> Result<AcquiredCredential> {
+    let id = select_credential(model, &[]).await?;
+    let credential = get_credential(&id)
+        .await
+        .ok_or_else(|| anyhow::anyhow!("凭证不存在: {}", id))?;
+
+    let headers = build_request_headers(&credential)?;
+    let base_url = base_url(&credential);
+
+    Ok(AcquiredCredential {
+        id,
+        name: credential.name.clone(),
+        auth_type: "oauth".to_string(),
+        base_url: Some(base_url),
+        headers,
+        metadata: HashMap::new(),
+    })
+}
 
-    // 选择第一个健康凭证
-    let (id, credential) = healthy_creds.first().unwrap();
+/// 凭证对应的 Machine ID
+pub fn machine_id(credential: &KiroCredentials) -> String {
+    generate_machine_id_from_credentials(
+        credential.profile_arn.as_deref(),
+        credential.client_id.as_deref(),
+    )
+}
 
+/// 凭证所在区域的 CodeWhisperer base URL
+pub fn base_url(credential: &KiroCredentials) -> String {
+    let region = credential.region.as_deref().unwrap_or("us-east-1");
+    format!("https://codewhisperer.{}.amazonaws.com", region)
+}
+
+/// 构建调用 CodeWhisperer API 所需的请求头
+pub fn build_request_headers(credential: &KiroCredentials) -> Result<HashMap<String, String>> {
     let token = credential
         .access_token
         .as_ref()
         .ok_or_else(|| anyhow::anyhow!("凭证没有有效的 access_token"))?;
 
-    // 构建请求头
     let mut headers = HashMap::new();
     headers.insert("Authorization".to_string(), format!("Bearer {}", token));
     headers.insert("Content-Type".to_string(), "application/json".to_string());
 
     // 添加 Kiro 特有的头部
-    let machine_id = generate_machine_id_from_credentials(
-        credential.profile_arn.as_deref(),
-        credential.client_id.as_deref(),
-    );
     let kiro_version = get_kiro_version();
     headers.insert(
         "x-amz-user-agent".to_string(),
-        format!("aws-sdk-js/1.0.0 KiroIDE-{}-{}", kiro_version, machine_id),
+        format!("aws-sdk-js/1.0.0 KiroIDE-{}-{}", kiro_version, machine_id(credential)),
     );
 
-    // 构建 base URL
-    let region = credential.region.as_deref().unwrap_or("us-east-1");
-    let base_url = format!("https://codewhisperer.{}.amazonaws.com", region);
+    Ok(headers)
+}
 
-    Ok(AcquiredCredential {
-        id: (*id).clone(),
-        name: credential.name.clone(),
-        auth_type: "oauth".to_string(),
-        base_url: Some(base_url),
-        headers,
-        metadata: HashMap::new(),
-    })
+/// 获取凭证副本
+pub async fn get_credential(credential_id: &str) -> Option<KiroCredentials> {
+    CREDENTIALS.read().await.get(credential_id).cloned()
+}
+
+/// 按 ID 排序列出凭证池
+pub async fn list_credentials() -> Vec<(String, KiroCredentials)> {
+    let creds = CREDENTIALS.read().await;
+    let mut list: Vec<_> = creds
+        .iter()
+        .map(|(id, c)| (id.clone(), c.clone()))
+        .collect();
+    list.sort_by(|a, b| a.0.cmp(&b.0));
+    list
 }
 
 /// 释放凭证
+///
+/// 失败结果中的 `cooldown_seconds`（见 `parse_error`）让凭证在这段时间内不被选择，
+/// 成功结果解除冷却。
 pub async fn release_credential(credential_id: &str, result: serde_json::Value) -> Result<()> {
     let mut creds = CREDENTIALS.write().await;
+    let mut cooldowns = COOLDOWNS.write().await;
 
     if let Some(credential) = creds.get_mut(credential_id) {
         credential.usage_count += 1;
@@ -162,7 +299,17 @@ pub async fn release_credential(credential_id: &str, result: serde_json::Value)
                 credential.is_healthy = false;
                 warn!("凭证标记为不健康: {}", credential_id);
             }
+
+            let cooldown = error.get("cooldown_seconds").and_then(|v| v.as_u64());
+            if let Some(seconds) = cooldown.filter(|s| *s > 0) {
+                cooldowns.insert(
+                    credential_id.to_string(),
+                    Instant::now() + Duration::from_secs(seconds),
+                );
+                info!("凭证进入冷却: {} {}s", credential_id, seconds);
+            }
         } else {
+            cooldowns.remove(credential_id);
             credential.is_healthy = true;
             credential.last_error = None;
             debug!("凭证使用成功: {}", credential_id);
@@ -172,22 +319,58 @@ pub async fn release_credential(credential_id: &str, result: serde_json::Value)
     Ok(())
 }
 
+/// 检查凭证内容是否可用
+pub fn check_credential(credential: &KiroCredentials) -> ValidationResult {
+    let has_token = credential.access_token.is_some() || credential.refresh_token.is_some();
+
+    let mut details = HashMap::new();
+    details.insert(
+        "auth_method".to_string(),
+        serde_json::json!(credential.auth_method.as_deref().unwrap_or("social")),
+    );
+    details.insert(
+        "has_access_token".to_string(),
+        serde_json::json!(credential.access_token.is_some()),
+    );
+    details.insert(
+        "has_refresh_token".to_string(),
+        serde_json::json!(credential.refresh_token.is_some()),
+    );
+    details.insert(
+        "token_expired".to_string(),
+        serde_json::json!(is_token_expired(credential.expire.as_deref())),
+    );
+    details.insert("disabled".to_string(), serde_json::json!(credential.disabled));
+    if let Some(ref expire) = credential.expire {
+        details.insert("expire".to_string(), serde_json::json!(expire));
+    }
+
+    let message = if !has_token {
+        "缺少有效的 token".to_string()
+    } else if credential.disabled {
+        "凭证已停用".to_string()
+    } else if !credential.is_healthy {
+        format!(
+            "凭证已标记为不健康: {}",
+            credential.last_error.as_deref().unwrap_or("未知错误")
+        )
+    } else {
+        "凭证有效".to_string()
+    };
+
+    ValidationResult {
+        valid: has_token && credential.is_healthy && !credential.disabled,
+        message: Some(message),
+        details,
+    }
+}
+
 /// 验证凭证
 pub async fn validate_credential(credential_id: &str) -> Result<ValidationResult> {
     let creds = CREDENTIALS.read().await;
 
     if let Some(credential) = creds.get(credential_id) {
-        let has_token = credential.access_token.is_some() || credential.refresh_token.is_some();
-
-        Ok(ValidationResult {
-            valid: has_token && credential.is_healthy,
-            message: if has_token {
-                Some("凭证有效".to_string())
-            } else {
-                Some("缺少有效的 token".to_string())
-            },
-            details: HashMap::new(),
-        })
+        Ok(check_credential(credential))
     } else {
         Ok(ValidationResult {
             valid: false,
@@ -197,28 +380,75 @@ pub async fn validate_credential(credential_id: &str) -> Result<ValidationResult
     }
 }
 
-/// 刷新 Token
-pub async fn refresh_token(credential_id: &str) -> Result<TokenRefreshResult> {
-    let mut creds = CREDENTIALS.write().await;
+/// 将刷新结果写入凭证
+pub fn apply_refresh_result(credential: &mut KiroCredentials, result: &TokenRefreshResult) {
+    credential.access_token = Some(result.access_token.clone());
+    if let Some(ref rt) = result.refresh_token {
+        credential.refresh_token = Some(rt.clone());
+    }
+    credential.expire = result.expires_at.map(|dt| dt.to_rfc3339());
+    credential.last_refresh = Some(chrono::Utc::now().to_rfc3339());
+    credential.is_healthy = true;
+    credential.last_error = None;
+}
 
-    if let Some(credential) = creds.get_mut(credential_id) {
-        // 调用 token_refresh 模块
-        let result = crate::token_refresh::refresh_token(credential).await?;
+/// 刷新 Token
+pub async fn refresh_token(credential_id: &str) -> Result<StoredRefreshResult> {
+    refresh_with(credential_id, |mut credential| async move {
+        crate::token_refresh::refresh_token(&mut credential).await
+    })
+    .await
+}
 
-        // 更新凭证
-        credential.access_token = Some(result.access_token.clone());
-        if let Some(ref rt) = result.refresh_token {
-            credential.refresh_token = Some(rt.clone());
-        }
-        credential.expire = result.expires_at.map(|dt| dt.to_rfc3339());
-        credential.is_healthy = true;
-        credential.last_error = None;
+/// 用 `refresh` 刷新凭证并写回存储
+///
+/// 刷新和写回在独立任务中完成：调用方被取消（客户端断开、`cancel`）时服务端
+/// 可能已经轮换了 refresh_token，新 token 仍会写入存储。刷新请求期间不持有
+/// 凭证池的锁，同一凭证的刷新依次进行，后一次使用前一次轮换后的 token。
+async fn refresh_with<F, Fut>(credential_id: &str, refresh: F) -> Result<StoredRefreshResult>
+where
+    F: FnOnce(KiroCredentials) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<TokenRefreshResult>> + Send + 'static,
+{
+    let credential_id = credential_id.to_string();
+    let lock = REFRESH_LOCKS
+        .lock()
+        .unwrap()
+        .entry(credential_id.clone())
+        .or_default()
+        .clone();
+
+    let task = tokio::spawn(async move {
+        let _guard = lock.lock().await;
+        let credential = get_credential(&credential_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("凭证不存在: {}", credential_id))?;
+        let result = refresh(credential).await?;
+
+        let mut creds = CREDENTIALS.write().await;
+        let Some(credential) = creds.get_mut(&credential_id) else {
+            warn!("刷新期间凭证已被删除，新 token 未保存: {}", credential_id);
+            return Ok(StoredRefreshResult {
+                result,
+                persisted: false,
+            });
+        };
+        apply_refresh_result(credential, &result);
+
+        // 服务端已轮换 refresh_token，立即落盘，避免进程退出后丢失账号
+        let persisted = match save_merged(&mut creds, |_| Ok(())) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("刷新后写回凭证存储失败: {}", e);
+                false
+            }
+        };
 
         info!("Token 刷新成功: {}", credential_id);
-        Ok(result)
-    } else {
-        anyhow::bail!("凭证不存在: {}", credential_id)
-    }
+        Ok(StoredRefreshResult { result, persisted })
+    });
+    task.await
+        .map_err(|e| anyhow::anyhow!("Token 刷新任务异常退出: {}", e))?
 }
 
 /// 创建凭证
@@ -228,9 +458,13 @@ pub async fn create_credential(auth_type: &str, config: serde_json::Value) -> Re
     }
 
     let kiro_config: KiroCredentials = serde_json::from_value(config)?;
+    add_credential(kiro_config).await
+}
 
+/// 将凭证加入凭证池并写回存储，返回新凭证 ID
+pub async fn add_credential(credential: KiroCredentials) -> Result<String> {
     // 验证必要字段
-    if kiro_config.refresh_token.is_none() {
+    if credential.refresh_token.is_none() {
         anyhow::bail!("缺少必要的 refresh_token");
     }
 
@@ -239,17 +473,53 @@ pub async fn create_credential(auth_type: &str, config: serde_json::Value) -> Re
 
     // 存储凭证
     let mut creds = CREDENTIALS.write().await;
-    creds.insert(credential_id.clone(), kiro_config);
+    save_merged(&mut creds, |latest| {
+        latest.insert(credential_id.clone(), credential);
+        Ok(())
+    })?;
 
     info!("创建凭证成功: {}", credential_id);
     Ok(credential_id)
 }
 
-/// 转换请求
-pub async fn transform_request(request: serde_json::Value) -> Result<serde_json::Value> {
-    // Kiro 使用特殊的 CodeWhisperer 格式
-    // 这里可以调用 translator 模块进行转换
-    Ok(request)
+/// 修改凭证并写回存储，返回修改后的凭证
+pub async fn update_credential<F>(credential_id: &str, update: F) -> Result<KiroCredentials>
+where
+    F: FnOnce(&mut KiroCredentials),
+{
+    let mut creds = CREDENTIALS.write().await;
+    let updated = save_merged(&mut creds, |latest| {
+        let credential = latest
+            .get_mut(credential_id)
+            .ok_or_else(|| anyhow::anyhow!("凭证不存在: {}", credential_id))?;
+        update(credential);
+        Ok(credential.clone())
+    })?;
+
+    info!("凭证已更新: {}", credential_id);
+    Ok(updated)
+}
+
+/// 删除凭证并写回存储
+pub async fn remove_credential(credential_id: &str) -> Result<KiroCredentials> {
+    let mut creds = CREDENTIALS.write().await;
+    let removed = save_merged(&mut creds, |latest| {
+        latest
+            .remove(credential_id)
+            .ok_or_else(|| anyhow::anyhow!("凭证不存在: {}", credential_id))
+    })?;
+
+    info!("凭证已删除: {}", credential_id);
+    Ok(removed)
+}
+
+/// 转换请求为 CodeWhisperer 格式
+///
+/// 自动识别 Anthropic / OpenAI 格式；模型没有对应的 modelId 时返回错误。
+/// 结果中的 `context` 为上下文预算的估算与裁剪情况。
+pub async fn transform_request(request: serde_json::Value) -> Result<CodeWhispererRequest> {
+    let format = translator::detect_format(&request);
+    Ok(translator::convert_request(&request, format, None)?)
 }
 
 /// 转换响应
@@ -301,3 +571,91 @@ pub fn parse_error(status: u16, body: &str) -> Option<ProviderError> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    lazy_static::lazy_static! {
+        /// 读写全局凭证池的测试依次运行（写回存储会替换整个凭证池）
+        static ref POOL_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_skips_credential_until_success() {
+        let _pool = POOL_LOCK.lock().await;
+        let id = "test-cooldown".to_string();
+        CREDENTIALS
+            .write()
+            .await
+            .insert(id.clone(), KiroCredentials::default());
+        // 只看本测试的凭证
+        let others: Vec<String> = CREDENTIALS
+            .read()
+            .await
+            .keys()
+            .filter(|k| **k != id)
+            .cloned()
+            .collect();
+
+        let throttled = serde_json::json!({
+            "error": { "message": "请求过于频繁", "mark_unhealthy": false, "cooldown_seconds": 60 }
+        });
+        release_credential(&id, throttled).await.unwrap();
+        let error = select_credential("claude-sonnet-4-5", &others)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("冷却"), "{}", error);
+
+        release_credential(&id, serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(
+            select_credential("claude-sonnet-4-5", &others)
+                .await
+                .unwrap(),
+            id
+        );
+        CREDENTIALS.write().await.remove(&id);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_persists_when_caller_cancelled() {
+        let _pool = POOL_LOCK.lock().await;
+        let dir = std::env::temp_dir().join(format!("kiro-provider-test-{}", uuid::Uuid::new_v4()));
+        storage::set_store_path(dir.join("credentials.json"));
+        let id = add_credential(KiroCredentials {
+            refresh_token: Some("old-rt".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // 上游已轮换 refresh_token，但响应较慢
+        let refresh = refresh_with(&id, |_| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(TokenRefreshResult {
+                access_token: "new-at".to_string(),
+                refresh_token: Some("rotated-rt".to_string()),
+                expires_at: None,
+            })
+        });
+        // 调用方在刷新途中被取消
+        assert!(tokio::time::timeout(Duration::from_millis(20), refresh)
+            .await
+            .is_err());
+        // 刷新期间凭证池可读写
+        assert!(get_credential(&id).await.is_some());
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let stored = storage::load(&storage::store_path()).unwrap();
+        assert_eq!(stored[&id].refresh_token.as_deref(), Some("rotated-rt"));
+        assert_eq!(
+            get_credential(&id).await.unwrap().access_token.as_deref(),
+            Some("new-at")
+        );
+
+        CREDENTIALS.write().await.remove(&id);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
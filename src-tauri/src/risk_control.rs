@@ -68,15 +68,23 @@ fn get_os_version() -> String {
     }
 }
 
+/// 未检测到 Kiro IDE 时使用的版本号
+pub const DEFAULT_KIRO_VERSION: &str = "0.1.25";
+
 /// 获取 Kiro IDE 版本号
 ///
 /// 尝试从 Kiro.app 的 Info.plist 读取实际版本，失败时使用默认值
 pub fn get_kiro_version() -> String {
+    detect_kiro_version().0
+}
+
+/// 获取 Kiro IDE 版本号及其来源（Info.plist 路径），来源为空表示使用默认值
+pub fn detect_kiro_version() -> (String, Option<String>) {
     #[cfg(target_os = "macos")]
     {
         let kiro_paths = [
-            "/Applications/Kiro.app/Contents/Info.plist",
-            &format!(
+            "/Applications/Kiro.app/Contents/Info.plist".to_string(),
+            format!(
                 "{}/Applications/Kiro.app/Contents/Info.plist",
                 dirs::home_dir()
                     .map(|p| p.to_string_lossy().to_string())
@@ -92,7 +100,7 @@ pub fn get_kiro_version() -> String {
                 if let Ok(version) = String::from_utf8(output.stdout) {
                     let version = version.trim();
                     if !version.is_empty() {
-                        return version.to_string();
+                        return (version.to_string(), Some(plist_path.clone()));
                     }
                 }
             }
@@ -100,7 +108,7 @@ pub fn get_kiro_version() -> String {
     }
 
     // 默认版本号
-    "0.1.25".to_string()
+    (DEFAULT_KIRO_VERSION.to_string(), None)
 }
 
 /// 构建 Social Auth Token 刷新 User-Agent
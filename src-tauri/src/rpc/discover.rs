@@ -0,0 +1,355 @@
+//! `rpc.discover`：生成 OpenRPC 文档
+//!
+//! 参数和结果的 schema 由方法处理实际使用的 Rust 类型派生，
+//! 方法列表取自 [`METHODS`]，新增或修改方法时同步更新 [`method_spec`] 中的一行。
+
+use super::framing::Framing;
+use super::types::*;
+use super::METHODS;
+use crate::credentials::{AcquiredCredential, ValidationResult};
+use crate::provider::{ModelInfo, PluginInfo, ProviderError, StoredRefreshResult};
+use crate::shutdown::ShutdownReport;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// OpenRPC 规范版本
+const OPENRPC_VERSION: &str = "1.2.6";
+
+/// OpenRPC 文档
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OpenRpcDocument {
+    pub openrpc: String,
+    pub info: OpenRpcInfo,
+    pub methods: Vec<MethodSpec>,
+    pub components: OpenRpcComponents,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OpenRpcInfo {
+    pub title: String,
+    pub version: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OpenRpcComponents {
+    /// 各方法参数和结果引用的类型定义
+    pub schemas: BTreeMap<String, Value>,
+}
+
+/// 单个方法的描述（OpenRPC Method Object）
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodSpec {
+    pub name: String,
+    pub summary: String,
+    pub param_structure: String,
+    pub params: Vec<ContentDescriptor>,
+    pub result: ContentDescriptor,
+}
+
+/// 参数或结果的描述（OpenRPC Content Descriptor）
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ContentDescriptor {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// 只用于参数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+    /// JSON Schema
+    pub schema: Value,
+}
+
+impl MethodSpec {
+    fn new<P: JsonSchema, R: JsonSchema>(
+        gen: &mut SchemaGenerator,
+        name: &str,
+        summary: &str,
+    ) -> Self {
+        let schema = gen.subschema_for::<R>();
+        Self {
+            name: name.to_string(),
+            summary: summary.to_string(),
+            param_structure: "by-name".to_string(),
+            params: params_of::<P>(gen),
+            result: ContentDescriptor {
+                name: format!("{}_result", name.replace('.', "_")),
+                description: None,
+                required: None,
+                schema: serde_json::to_value(&schema).unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// 无参数
+#[derive(JsonSchema)]
+struct NoParams {}
+
+/// 将参数结构体的每个字段展开为 OpenRPC ContentDescriptor
+fn params_of<P: JsonSchema>(gen: &mut SchemaGenerator) -> Vec<ContentDescriptor> {
+    let schema = P::json_schema(gen).into_object();
+    let Some(object) = schema.object else {
+        return Vec::new();
+    };
+
+    object
+        .properties
+        .into_iter()
+        .map(|(name, schema)| {
+            let schema = serde_json::to_value(&schema).unwrap_or_default();
+            ContentDescriptor {
+                required: Some(object.required.contains(&name)),
+                description: schema["description"].as_str().map(String::from),
+                name,
+                schema,
+            }
+        })
+        .collect()
+}
+
+/// 单个 JSON-RPC 方法的描述，不在 [`METHODS`] 中的方法返回 `None`
+fn method_spec(gen: &mut SchemaGenerator, name: &str) -> Option<MethodSpec> {
+    let spec = match name {
+        "rpc.discover" => {
+            MethodSpec::new::<NoParams, OpenRpcDocument>(gen, name, "返回本 OpenRPC 文档")
+        }
+        "get_info" => {
+            MethodSpec::new::<NoParams, PluginInfo>(gen, name, "插件信息、认证方式和模型族")
+        }
+        "list_models" => MethodSpec::new::<NoParams, Vec<ModelInfo>>(gen, name, "列出支持的模型"),
+        "supports_model" => {
+            MethodSpec::new::<ModelParams, SupportsModelResult>(gen, name, "检查是否支持某个模型")
+        }
+        "acquire_credential" => MethodSpec::new::<ModelParams, AcquiredCredential>(
+            gen,
+            name,
+            "为模型选取一个健康凭证，返回调用所需的请求头和 base URL",
+        ),
+        "release_credential" => MethodSpec::new::<ReleaseCredentialParams, EmptyResult>(
+            gen,
+            name,
+            "归还凭证并记录请求结果",
+        ),
+        "validate_credential" => {
+            MethodSpec::new::<CredentialIdParams, ValidationResult>(gen, name, "验证凭证")
+        }
+        "refresh_token" => MethodSpec::new::<CredentialIdParams, StoredRefreshResult>(
+            gen,
+            name,
+            "刷新凭证的 access_token",
+        ),
+        "create_credential" => MethodSpec::new::<CreateCredentialParams, CreateCredentialResult>(
+            gen,
+            name,
+            "创建凭证并写入存储",
+        ),
+        "transform_request" => MethodSpec::new::<TransformRequestParams, TransformRequestResult>(
+            gen,
+            name,
+            "转换请求体",
+        ),
+        "transform_response" => {
+            MethodSpec::new::<TransformResponseParams, TransformResponseResult>(
+                gen,
+                name,
+                "转换响应体",
+            )
+        }
+        "apply_risk_control" => {
+            MethodSpec::new::<RiskControlParams, RiskControlResult>(gen, name, "对请求体应用风控")
+        }
+        "parse_error" => MethodSpec::new::<ParseErrorParams, Option<ProviderError>>(
+            gen,
+            name,
+            "解析上游错误，未识别的状态码返回 null",
+        ),
+        "authenticate" => MethodSpec::new::<AuthenticateParams, AuthenticateResult>(
+            gen,
+            name,
+            "共享密钥认证（socket/TCP 连接的第一个请求）",
+        ),
+        "initialize" => MethodSpec::new::<InitializeParams, InitializeResult>(
+            gen,
+            name,
+            "协商分帧方式，新方式从本响应之后生效",
+        ),
+        "execute" => MethodSpec::new::<ExecuteParams, ExecuteResult>(
+            gen,
+            name,
+            "在插件内完成一次 Anthropic 请求，流式事件以 execute/chunk 通知推送",
+        ),
+        "cancel" => MethodSpec::new::<CancelParams, CancelResult>(
+            gen,
+            name,
+            "取消同一连接上进行中的 execute 请求",
+        ),
+        "shutdown" => MethodSpec::new::<ShutdownParams, ShutdownReport>(
+            gen,
+            name,
+            "优雅关闭（仅 stdio）：停止接收请求、等待进行中请求并写回凭证存储",
+        ),
+        _ => return None,
+    };
+    Some(spec)
+}
+
+/// 生成完整的 OpenRPC 文档
+pub fn openrpc_document() -> OpenRpcDocument {
+    let mut gen = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.definitions_path = "#/components/schemas/".to_string();
+            settings.inline_subschemas = false;
+        })
+        .into_generator();
+
+    // 确保所有分帧方式的取值出现在文档中
+    gen.subschema_for::<Framing>();
+    // execute/chunk 是服务端通知，不属于 methods，只在 components 中给出 schema
+    gen.subschema_for::<ExecuteChunkParams>();
+
+    let methods = METHODS
+        .iter()
+        .filter_map(|name| method_spec(&mut gen, name))
+        .collect();
+    let schemas = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or_default()))
+        .collect();
+
+    OpenRpcDocument {
+        openrpc: OPENRPC_VERSION.to_string(),
+        info: OpenRpcInfo {
+            title: "Kiro Provider".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            description: env!("CARGO_PKG_DESCRIPTION").to_string(),
+        },
+        methods,
+        components: OpenRpcComponents { schemas },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{handle_request, JsonRpcRequest};
+    use serde_json::json;
+
+    /// 由传输层直接处理、不经过 handle_request 的方法
+    const TRANSPORT_METHODS: &[&str] = &[
+        "authenticate",
+        "initialize",
+        "execute",
+        "cancel",
+        "shutdown",
+    ];
+
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(r)) = map.get("$ref") {
+                    refs.push(r.clone());
+                }
+                map.values().for_each(|v| collect_refs(v, refs));
+            }
+            Value::Array(arr) => arr.iter().for_each(|v| collect_refs(v, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_document_refs_resolve() {
+        let doc = serde_json::to_value(openrpc_document()).unwrap();
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        assert!(schemas.contains_key("AcquiredCredential"));
+        assert!(schemas.contains_key("StoredRefreshResult"));
+        assert!(schemas.contains_key("ModelInfo"));
+
+        let mut refs = Vec::new();
+        collect_refs(&doc, &mut refs);
+        assert!(!refs.is_empty());
+        for r in refs {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            assert!(schemas.contains_key(name), "未定义的 schema: {}", r);
+        }
+    }
+
+    #[test]
+    fn test_params_from_types() {
+        let doc = serde_json::to_value(openrpc_document()).unwrap();
+        let method = doc["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|m| m["name"] == "release_credential")
+            .unwrap();
+        let names: Vec<_> = method["params"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["credential_id", "result"]);
+    }
+
+    #[tokio::test]
+    async fn test_every_listed_method_is_dispatched() {
+        for &name in METHODS {
+            if TRANSPORT_METHODS.contains(&name) {
+                continue;
+            }
+            let response = handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                method: name.to_string(),
+                params: Value::Null,
+                id: json!(1),
+            })
+            .await;
+            let code = response.error.as_ref().map(|e| e.code);
+            assert_ne!(code, Some(-32601), "列表中的方法未实现: {}", name);
+        }
+    }
+
+    #[test]
+    fn test_documented_methods_match_method_list() {
+        let doc = serde_json::to_value(openrpc_document()).unwrap();
+        let documented: Vec<&str> = doc["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(documented, METHODS);
+    }
+
+    #[tokio::test]
+    async fn test_unlisted_method_is_not_dispatched() {
+        let response = handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "get_infos".to_string(),
+            params: Value::Null,
+            id: json!(1),
+        })
+        .await;
+        assert_eq!(response.error.map(|e| e.code), Some(-32601));
+    }
+
+    #[test]
+    fn test_results_have_typed_schemas() {
+        let doc = serde_json::to_value(openrpc_document()).unwrap();
+        for method in doc["methods"].as_array().unwrap() {
+            // serde_json::Value 的 schema 为 true（任意值）
+            let schema = &method["result"]["schema"];
+            assert!(
+                *schema != json!(true) && *schema != json!({}),
+                "结果没有类型化的 schema: {}",
+                method["name"]
+            );
+        }
+    }
+}
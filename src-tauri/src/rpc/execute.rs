@@ -0,0 +1,213 @@
+//! `execute` / `cancel`：在插件内完成一次完整的模型调用
+//!
+//! 宿主只需提交 Anthropic 请求，选择凭证、刷新 Token、转换请求、调用
+//! CodeWhisperer、翻译事件流和记录凭证使用结果都由插件完成。
+//!
+//! 流式请求（`request.stream: true`）的每个 SSE 事件以 `execute/chunk` 通知推送，
+//! `params.request_id` 为对应 `execute` 请求的 ID；事件推送完毕后再返回
+//! `execute` 的响应。通知和取消都与连接绑定，这两个方法由传输层处理。
+
+use super::types::{ExecuteChunkParams, ExecuteParams, ExecuteResult};
+use super::JsonRpcError;
+use crate::provider;
+use crate::translator::anthropic_to_cw::thinking_budget;
+use crate::translator::cw_to_anthropic::collect_message;
+use crate::translator::{CwToAnthropicTranslator, RequestFormat};
+use crate::upstream::{self, SendError, UpstreamError};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// 流式事件通知的方法名
+pub const CHUNK_METHOD: &str = "execute/chunk";
+
+/// 请求被 `cancel` 取消时的错误码
+pub const REQUEST_CANCELLED: i32 = -32800;
+
+/// 单个连接上进行中的 `execute` 请求，按请求 ID 登记
+#[derive(Debug, Default, Clone)]
+pub struct Executions {
+    pending: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
+}
+
+impl Executions {
+    /// 登记请求，返回取消信号；同一 ID 已在执行时返回 None
+    pub fn register(&self, request_id: &Value) -> Option<oneshot::Receiver<()>> {
+        let mut pending = self.pending.lock().unwrap();
+        let key = request_id.to_string();
+        if pending.contains_key(&key) {
+            return None;
+        }
+        let (tx, rx) = oneshot::channel();
+        pending.insert(key, tx);
+        Some(rx)
+    }
+
+    /// 清理已结束的请求（取消信号的接收端已丢弃）
+    ///
+    /// 不按 ID 删除：请求被取消后，同一 ID 可能已被新的请求重新登记。
+    pub fn finish(&self) {
+        self.pending.lock().unwrap().retain(|_, tx| !tx.is_closed());
+    }
+
+    /// 取消进行中的请求，返回是否找到该请求
+    pub fn cancel(&self, request_id: &Value) -> bool {
+        let sender = self.pending.lock().unwrap().remove(&request_id.to_string());
+        match sender {
+            Some(tx) => tx.send(()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// 执行请求，流式事件通过 `notify` 推送
+pub async fn execute(
+    params: ExecuteParams,
+    request_id: &Value,
+    mut notify: impl FnMut(ExecuteChunkParams),
+) -> Result<ExecuteResult, JsonRpcError> {
+    let request = params.request;
+    let model = validate_request(&request)?;
+    let stream = request["stream"].as_bool().unwrap_or(false);
+    let thinking = thinking_budget(&request).is_some();
+
+    let response = upstream::send(&request, RequestFormat::Anthropic, &model)
+        .await
+        .map_err(upstream_error)?;
+    let credential_id = response.credential_id.clone();
+    let tried_credentials = response.tried_credentials.clone();
+    let context = response.context.clone();
+    let normalization = response.normalization.clone();
+
+    let mut events = response.into_events(&model, thinking);
+    let mut collected = Vec::new();
+    while let Some(event) = events.recv().await {
+        if stream {
+            notify(ExecuteChunkParams {
+                request_id: request_id.clone(),
+                chunk: CwToAnthropicTranslator::format_sse(&event),
+            });
+        }
+        collected.push(event);
+    }
+
+    let message = collect_message(&collected);
+    if message["type"] == "error" {
+        return Err(JsonRpcError {
+            code: -32000,
+            message: message["error"]["message"]
+                .as_str()
+                .unwrap_or("上游返回错误")
+                .to_string(),
+            data: Some(json!({
+                "credential_id": credential_id,
+                "tried_credentials": tried_credentials,
+            })),
+        });
+    }
+
+    Ok(ExecuteResult {
+        credential_id,
+        tried_credentials,
+        message,
+        context,
+        normalization,
+    })
+}
+
+/// 校验请求体，返回模型 ID
+fn validate_request(request: &Value) -> Result<String, JsonRpcError> {
+    let invalid = |message: String| JsonRpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", message),
+        data: None,
+    };
+
+    let model = request["model"]
+        .as_str()
+        .ok_or_else(|| invalid("request 缺少 model 字段".to_string()))?;
+    if !request["messages"].is_array() {
+        return Err(invalid("request.messages 必须是数组".to_string()));
+    }
+    if !provider::supports_model(model) {
+        return Err(invalid(provider::unsupported_model_message(model)));
+    }
+    Ok(model.to_string())
+}
+
+/// 上游错误 → JSON-RPC 错误
+///
+/// `data.tried_credentials` 为尝试过的凭证，上游返回错误时状态码放在 `data.status`。
+fn upstream_error(error: SendError) -> JsonRpcError {
+    let mut data = json!({ "tried_credentials": error.tried_credentials });
+    if let UpstreamError::Status { status, .. } = error.error {
+        data["status"] = json!(status);
+    }
+    JsonRpcError {
+        code: -32000,
+        message: error.to_string(),
+        data: Some(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_request() {
+        let valid = json!({"model": "claude-sonnet-4-5", "messages": []});
+        assert_eq!(validate_request(&valid).unwrap(), "claude-sonnet-4-5");
+
+        for request in [
+            json!({"messages": []}),
+            json!({"model": "claude-sonnet-4-5", "messages": "hi"}),
+            json!({"model": "gpt-4o", "messages": []}),
+        ] {
+            let error = validate_request(&request).unwrap_err();
+            assert_eq!(error.code, -32602, "{}", request);
+        }
+    }
+
+    #[test]
+    fn test_upstream_error_data() {
+        let error = upstream_error(SendError {
+            error: UpstreamError::Status {
+                status: 429,
+                message: "slow down".to_string(),
+            },
+            tried_credentials: vec!["a".to_string(), "b".to_string()],
+        });
+        assert_eq!(error.code, -32000);
+        assert_eq!(
+            error.data,
+            Some(json!({ "status": 429, "tried_credentials": ["a", "b"] }))
+        );
+
+        let error = upstream_error(SendError {
+            error: UpstreamError::Network("timeout".into()),
+            tried_credentials: Vec::new(),
+        });
+        assert!(error.data.unwrap().get("status").is_none());
+    }
+
+    #[test]
+    fn test_executions_cancel() {
+        let executions = Executions::default();
+        let mut rx = executions.register(&json!(1)).unwrap();
+        assert!(executions.register(&json!(1)).is_none());
+        // 数字 ID 与字符串 ID 不冲突
+        assert!(executions.register(&json!("1")).is_some());
+
+        assert!(executions.cancel(&json!(1)));
+        assert!(rx.try_recv().is_ok());
+        assert!(!executions.cancel(&json!(1)));
+
+        // 取消后同一 ID 可重新登记，旧请求收尾不会误删新登记
+        let _rx = executions.register(&json!(1)).unwrap();
+        drop(rx);
+        executions.finish();
+        assert!(executions.cancel(&json!(1)));
+    }
+}
@@ -0,0 +1,286 @@
+//! JSON-RPC 消息分帧
+//!
+//! - `newline`：每行一个 JSON 消息（默认，兼容现有宿主）
+//! - `content-length`：LSP 风格的 `Content-Length: N\r\n\r\n<body>`，
+//!   允许消息跨行（如格式化输出的 JSON），并限制单条消息大小
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// 默认单条消息大小上限（32 MiB，足够容纳多张 base64 图片）
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
+/// content-length 分帧下单行消息头的长度上限
+const MAX_HEADER_LINE: usize = 8 * 1024;
+/// content-length 分帧下一条消息所有消息头的总长度上限
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// 分帧方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Framing {
+    #[default]
+    Newline,
+    ContentLength,
+}
+
+impl FromStr for Framing {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "newline" => Ok(Framing::Newline),
+            "content-length" => Ok(Framing::ContentLength),
+            _ => Err(format!(
+                "无效的分帧方式 {}（可选: newline、content-length）",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Framing::Newline => write!(f, "newline"),
+            Framing::ContentLength => write!(f, "content-length"),
+        }
+    }
+}
+
+/// 读取到的一条消息
+#[derive(Debug, PartialEq)]
+pub enum Incoming {
+    /// 完整的消息体
+    Message(String),
+    /// 消息超过大小上限，消息体已被丢弃
+    TooLarge(usize),
+}
+
+/// 按当前分帧方式读取消息，分帧方式可在连接中途切换
+pub struct MessageReader<R> {
+    reader: R,
+    framing: Framing,
+    max_message_size: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> MessageReader<R> {
+    pub fn new(reader: R, framing: Framing, max_message_size: usize) -> Self {
+        Self {
+            reader,
+            framing,
+            max_message_size,
+        }
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    /// 读取下一条消息，对端关闭时返回 None
+    pub async fn read_message(&mut self) -> Result<Option<Incoming>> {
+        match self.framing {
+            Framing::Newline => {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line).await? == 0 {
+                    return Ok(None);
+                }
+                Ok(Some(Incoming::Message(line)))
+            }
+            Framing::ContentLength => self.read_framed().await,
+        }
+    }
+
+    async fn read_framed(&mut self) -> Result<Option<Incoming>> {
+        let mut content_length: Option<usize> = None;
+        let mut header_seen = false;
+        let mut header_size = 0;
+
+        loop {
+            // 限制每行读取的长度，对端不发送换行时不会无限占用内存
+            let mut line = String::new();
+            let read = (&mut self.reader)
+                .take(MAX_HEADER_LINE as u64 + 1)
+                .read_line(&mut line)
+                .await?;
+            if read == 0 {
+                if header_seen {
+                    anyhow::bail!("消息头未结束连接就已关闭");
+                }
+                return Ok(None);
+            }
+            if read > MAX_HEADER_LINE && !line.ends_with('\n') {
+                anyhow::bail!("消息头过长（单行超过 {} 字节）", MAX_HEADER_LINE);
+            }
+            header_size += read;
+            if header_size > MAX_HEADER_SIZE {
+                anyhow::bail!("消息头过长（超过 {} 字节）", MAX_HEADER_SIZE);
+            }
+
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                // 消息之间多余的空行
+                if !header_seen {
+                    continue;
+                }
+                break;
+            }
+            header_seen = true;
+
+            let Some((name, value)) = line.split_once(':') else {
+                anyhow::bail!("无效的消息头: {}", line);
+            };
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("无效的 Content-Length: {}", value.trim()))?,
+                );
+            }
+        }
+
+        let length = content_length.ok_or_else(|| anyhow::anyhow!("缺少 Content-Length 头"))?;
+
+        if length > self.max_message_size {
+            // 丢弃消息体，保持流同步，连接可以继续使用
+            let discarded = tokio::io::copy(
+                &mut (&mut self.reader).take(length as u64),
+                &mut tokio::io::sink(),
+            )
+            .await?;
+            if discarded < length as u64 {
+                anyhow::bail!("消息体未读完连接就已关闭");
+            }
+            return Ok(Some(Incoming::TooLarge(length)));
+        }
+
+        let mut body = vec![0u8; length];
+        self.reader.read_exact(&mut body).await?;
+        Ok(Some(Incoming::Message(String::from_utf8(body)?)))
+    }
+}
+
+/// 按指定分帧方式写出一条消息
+pub async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    framing: Framing,
+    message: &str,
+) -> std::io::Result<()> {
+    match framing {
+        Framing::Newline => {
+            writer.write_all(message.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+        Framing::ContentLength => {
+            let header = format!("Content-Length: {}\r\n\r\n", message.len());
+            writer.write_all(header.as_bytes()).await?;
+            writer.write_all(message.as_bytes()).await?;
+        }
+    }
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    fn reader(input: &str, framing: Framing, max: usize) -> MessageReader<BufReader<&[u8]>> {
+        MessageReader::new(BufReader::new(input.as_bytes()), framing, max)
+    }
+
+    #[tokio::test]
+    async fn test_read_content_length_messages() {
+        let pretty = "{\n  \"id\": 1\n}";
+        let input = format!(
+            "Content-Length: {}\r\nContent-Type: application/json\r\n\r\n{}Content-Length: 2\r\n\r\n{{}}",
+            pretty.len(),
+            pretty
+        );
+        let mut reader = reader(&input, Framing::ContentLength, 1024);
+
+        assert_eq!(
+            reader.read_message().await.unwrap(),
+            Some(Incoming::Message(pretty.to_string()))
+        );
+        assert_eq!(
+            reader.read_message().await.unwrap(),
+            Some(Incoming::Message("{}".to_string()))
+        );
+        assert_eq!(reader.read_message().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_is_skipped() {
+        let input = "Content-Length: 10\r\n\r\n0123456789Content-Length: 2\r\n\r\n{}";
+        let mut reader = reader(input, Framing::ContentLength, 5);
+
+        assert_eq!(
+            reader.read_message().await.unwrap(),
+            Some(Incoming::TooLarge(10))
+        );
+        assert_eq!(
+            reader.read_message().await.unwrap(),
+            Some(Incoming::Message("{}".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_content_length() {
+        let mut reader = reader("Content-Type: x\r\n\r\n{}", Framing::ContentLength, 1024);
+        assert!(reader.read_message().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_header_is_rejected() {
+        // 不带换行的超长消息头
+        let input = "X".repeat(MAX_HEADER_LINE * 4);
+        let error = reader(&input, Framing::ContentLength, 1024)
+            .read_message()
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("消息头过长"), "{}", error);
+
+        // 每行都不长，但消息头总长超过上限
+        let line = format!("X-Padding: {}\r\n", "x".repeat(1000));
+        let input = line.repeat(MAX_HEADER_SIZE / 1000 + 1);
+        let error = reader(&input, Framing::ContentLength, 1024)
+            .read_message()
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("消息头过长"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_switch_framing_mid_stream() {
+        let input = "{\"id\":1}\nContent-Length: 8\r\n\r\n{\"id\":2}";
+        let mut reader = reader(input, Framing::Newline, 1024);
+
+        assert_eq!(
+            reader.read_message().await.unwrap(),
+            Some(Incoming::Message("{\"id\":1}\n".to_string()))
+        );
+        reader.set_framing(Framing::ContentLength);
+        assert_eq!(
+            reader.read_message().await.unwrap(),
+            Some(Incoming::Message("{\"id\":2}".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_content_length() {
+        let mut out = Vec::new();
+        write_message(&mut out, Framing::ContentLength, "{}")
+            .await
+            .unwrap();
+        assert_eq!(out, b"Content-Length: 2\r\n\r\n{}");
+    }
+}
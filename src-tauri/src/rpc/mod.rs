@@ -0,0 +1,267 @@
+//! JSON-RPC 协议层
+//!
+//! 定义 JSON-RPC 消息结构和方法分发表，所有传输方式（stdio、Unix socket、TCP）
+//! 和分帧方式（newline、content-length）共用。
+
+pub mod discover;
+pub mod execute;
+pub mod framing;
+pub mod transport;
+pub mod types;
+
+use crate::{models, provider};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use types::*;
+
+/// 所有 JSON-RPC 方法名
+///
+/// 分发表（本模块和传输层）只处理列表中的方法，OpenRPC 文档也按此列表生成；
+/// 新增方法时先加到这里。
+pub const METHODS: &[&str] = &[
+    "rpc.discover",
+    "get_info",
+    "list_models",
+    "supports_model",
+    "acquire_credential",
+    "release_credential",
+    "validate_credential",
+    "refresh_token",
+    "create_credential",
+    "transform_request",
+    "transform_response",
+    "apply_risk_control",
+    "parse_error",
+    "authenticate",
+    "initialize",
+    "execute",
+    "cancel",
+    "shutdown",
+];
+
+/// JSON-RPC Request
+#[derive(Debug, Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+    #[serde(default)]
+    pub id: serde_json::Value,
+}
+
+/// JSON-RPC Response
+#[derive(Debug, Serialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<JsonRpcError>,
+    pub id: serde_json::Value,
+}
+
+/// JSON-RPC Notification（服务端主动推送，没有 ID）
+#[derive(Debug, Serialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: serde_json::Value,
+}
+
+impl JsonRpcNotification {
+    pub fn new(method: &str, params: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// JSON-RPC Error
+#[derive(Debug, Serialize)]
+pub struct JsonRpcError {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<serde_json::Value>,
+}
+
+impl JsonRpcResponse {
+    pub fn success(id: serde_json::Value, result: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    pub fn error(id: serde_json::Value, code: i32, message: String) -> Self {
+        Self::from_error(
+            id,
+            JsonRpcError {
+                code,
+                message,
+                data: None,
+            },
+        )
+    }
+
+    /// 方法不存在（-32601）
+    pub fn method_not_found(request: &JsonRpcRequest) -> Self {
+        Self::error(
+            request.id.clone(),
+            -32601,
+            format!("Method not found: {}", request.method),
+        )
+    }
+
+    pub fn from_error(id: serde_json::Value, error: JsonRpcError) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(error),
+            id,
+        }
+    }
+}
+
+/// 解析请求参数，参数缺省（null）时使用默认值
+pub(crate) fn parse_params<T>(request: &JsonRpcRequest) -> Result<T, JsonRpcError>
+where
+    T: DeserializeOwned + Default,
+{
+    if request.params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(request.params.clone()).map_err(|e| JsonRpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", e),
+        data: None,
+    })
+}
+
+fn to_result<T: Serialize>(id: serde_json::Value, result: T) -> JsonRpcResponse {
+    JsonRpcResponse::success(id, serde_json::to_value(result).unwrap_or_default())
+}
+
+/// Handle a JSON-RPC request
+pub async fn handle_request(request: JsonRpcRequest) -> JsonRpcResponse {
+    match dispatch(&request).await {
+        Ok(response) => response,
+        Err(error) => JsonRpcResponse::from_error(request.id, error),
+    }
+}
+
+async fn dispatch(request: &JsonRpcRequest) -> Result<JsonRpcResponse, JsonRpcError> {
+    let id = request.id.clone();
+    if !METHODS.contains(&request.method.as_str()) {
+        return Ok(JsonRpcResponse::method_not_found(request));
+    }
+
+    let response = match request.method.as_str() {
+        "rpc.discover" => to_result(id, discover::openrpc_document()),
+        "get_info" => to_result(id, provider::get_plugin_info()),
+        "list_models" => to_result(id, provider::list_models()),
+        "supports_model" => {
+            let params: ModelParams = parse_params(request)?;
+            let matched = models::catalog().resolve(&params.model);
+            to_result(id, SupportsModelResult::from(matched))
+        }
+        "acquire_credential" => {
+            let params: ModelParams = parse_params(request)?;
+            match provider::acquire_credential(&params.model).await {
+                Ok(credential) => to_result(id, credential),
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "release_credential" => {
+            let params: ReleaseCredentialParams = parse_params(request)?;
+            match provider::release_credential(&params.credential_id, params.result).await {
+                Ok(_) => to_result(id, EmptyResult {}),
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "validate_credential" => {
+            let params: CredentialIdParams = parse_params(request)?;
+            match provider::validate_credential(&params.credential_id).await {
+                Ok(result) => to_result(id, result),
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "refresh_token" => {
+            let params: CredentialIdParams = parse_params(request)?;
+            match provider::refresh_token(&params.credential_id).await {
+                Ok(result) => to_result(id, result),
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "create_credential" => {
+            let params: CreateCredentialParams = parse_params(request)?;
+            match provider::create_credential(&params.auth_type, params.config).await {
+                Ok(credential_id) => to_result(id, CreateCredentialResult { credential_id }),
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "transform_request" => {
+            let params: TransformRequestParams = parse_params(request)?;
+            match provider::transform_request(params.request).await {
+                Ok(mut cw_request) => {
+                    let context = cw_request.context.take();
+                    let normalization = std::mem::take(&mut cw_request.normalization);
+                    let model_id = cw_request
+                        .conversation_state
+                        .current_message
+                        .user_input_message
+                        .model_id
+                        .clone()
+                        .unwrap_or_default();
+                    match serde_json::to_value(cw_request) {
+                        Ok(transformed) => to_result(
+                            id,
+                            TransformRequestResult {
+                                request: transformed,
+                                model_id,
+                                context,
+                                normalization,
+                            },
+                        ),
+                        Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+                    }
+                }
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "transform_response" => {
+            let params: TransformResponseParams = parse_params(request)?;
+            match provider::transform_response(params.response).await {
+                Ok(transformed) => to_result(
+                    id,
+                    TransformResponseResult {
+                        response: transformed,
+                    },
+                ),
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "apply_risk_control" => {
+            let params: RiskControlParams = parse_params(request)?;
+            let mut request_body = params.request;
+            match provider::apply_risk_control(&mut request_body, &params.credential_id).await {
+                Ok(_) => to_result(
+                    id,
+                    RiskControlResult {
+                        request: request_body,
+                    },
+                ),
+                Err(e) => JsonRpcResponse::error(id, -32000, e.to_string()),
+            }
+        }
+        "parse_error" => {
+            let params: ParseErrorParams = parse_params(request)?;
+            to_result(id, provider::parse_error(params.status, &params.body))
+        }
+        _ => JsonRpcResponse::method_not_found(request),
+    };
+
+    Ok(response)
+}
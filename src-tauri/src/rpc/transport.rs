@@ -0,0 +1,779 @@
+//! JSON-RPC 传输层
+//!
+//! 三种监听方式共用同一张方法分发表：
+//! - `stdio`：ProxyCast 启动插件时的默认方式，stdin 关闭即进入关闭流程
+//! - `unix:/path/to/socket`：Unix domain socket
+//! - `tcp:127.0.0.1:PORT`：仅允许本地回环地址
+//!
+//! socket/TCP 方式可同时服务多个客户端。配置了共享密钥时，客户端连接后
+//! 必须先发送 `authenticate` 请求，否则连接会被关闭。`shutdown` 请求只在
+//! stdio 方式下可用，避免一个客户端关掉其他客户端共用的服务；socket/TCP
+//! 方式由 SIGTERM/SIGINT 关闭。
+//!
+//! 分帧方式由 `--framing` 指定，也可以由客户端通过 `initialize` 请求协商，
+//! 协商结果从 `initialize` 的响应之后生效。
+//!
+//! `execute` 的流式通知写回发起请求的连接，`cancel` 只能取消同一连接上的请求。
+
+use super::execute::{self, Executions};
+use super::framing::{write_message, Framing, Incoming, MessageReader};
+use super::types::{
+    AuthenticateParams, AuthenticateResult, CancelParams, CancelResult, ExecuteParams,
+    InitializeParams, InitializeResult, ShutdownParams,
+};
+use super::{
+    handle_request, parse_params, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse, METHODS,
+};
+use crate::{provider, shutdown};
+use anyhow::Result;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+/// 共享密钥环境变量（避免密钥出现在进程参数中）
+pub const AUTH_TOKEN_ENV: &str = "KIRO_PROVIDER_AUTH_TOKEN";
+
+/// 关闭后等待剩余响应写出的时间
+const WRITER_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 监听地址
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddr {
+    Stdio,
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s == "stdio" {
+            return Ok(ListenAddr::Stdio);
+        }
+
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("unix: 后需要 socket 路径".to_string());
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+
+        if let Some(addr) = s.strip_prefix("tcp:") {
+            let addr: SocketAddr = addr
+                .parse()
+                .map_err(|e| format!("无效的 TCP 地址 {}: {}", addr, e))?;
+            if !addr.ip().is_loopback() {
+                return Err(format!("TCP 仅允许监听本地回环地址: {}", addr.ip()));
+            }
+            return Ok(ListenAddr::Tcp(addr));
+        }
+
+        Err(format!(
+            "无效的监听地址 {}（可选: stdio、unix:/path、tcp:127.0.0.1:PORT）",
+            s
+        ))
+    }
+}
+
+/// 服务选项
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    /// 共享密钥（仅 socket/TCP 生效）
+    pub auth_token: Option<String>,
+    /// 初始分帧方式
+    pub framing: Framing,
+    /// content-length 分帧下单条消息的大小上限
+    pub max_message_size: usize,
+}
+
+/// 单个连接的配置
+#[derive(Debug)]
+struct ConnectionOptions {
+    auth_token: Option<String>,
+    framing: Framing,
+    max_message_size: usize,
+    /// 连接独占进程（stdio）：对端关闭或 `shutdown` 请求触发整个进程的关闭流程
+    owns_process: bool,
+}
+
+/// 写出通道中的消息
+enum Outbound {
+    Message(String),
+    /// 此后的消息改用新的分帧方式
+    SwitchFraming(Framing),
+}
+
+/// 启动 JSON-RPC 服务，直到关闭流程完成
+pub async fn serve(addr: ListenAddr, options: ServeOptions) -> Result<()> {
+    if let Err(e) = provider::load_credentials().await {
+        warn!("加载凭证存储失败: {}", e);
+    }
+
+    tokio::spawn(async {
+        shutdown::wait_for_signal().await;
+        shutdown::shutdown_once(shutdown::DEFAULT_DRAIN_TIMEOUT).await;
+    });
+
+    let max_message_size = if options.max_message_size == 0 {
+        super::framing::DEFAULT_MAX_MESSAGE_SIZE
+    } else {
+        options.max_message_size
+    };
+
+    // stdio 由宿主进程独占，不需要认证
+    let is_stdio = addr == ListenAddr::Stdio;
+    let connection = Arc::new(ConnectionOptions {
+        auth_token: options.auth_token.filter(|t| !t.is_empty() && !is_stdio),
+        framing: options.framing,
+        max_message_size,
+        owns_process: is_stdio,
+    });
+
+    match addr {
+        ListenAddr::Stdio => {
+            serve_connection(tokio::io::stdin(), tokio::io::stdout(), connection).await?;
+        }
+        ListenAddr::Unix(path) => serve_unix(path, connection).await?,
+        ListenAddr::Tcp(addr) => serve_tcp(addr, connection).await?,
+    }
+
+    shutdown::shutdown_once(shutdown::DEFAULT_DRAIN_TIMEOUT).await;
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_unix(path: PathBuf, connection: Arc<ConnectionOptions>) -> Result<()> {
+    use tokio::net::UnixStream;
+
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            anyhow::bail!("已有实例在监听: {}", path.display());
+        }
+        // 上次异常退出残留的 socket 文件
+        std::fs::remove_file(&path)?;
+    }
+
+    let listener = bind_private(&path)?;
+    info!("JSON-RPC 监听 unix:{}", path.display());
+
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                debug!("新连接: unix");
+                let (reader, writer) = stream.into_split();
+                connections.spawn(serve_connection(reader, writer, connection.clone()));
+            }
+            _ = shutdown::stopping() => break,
+        }
+    }
+
+    finish_connections(connections).await;
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+/// 创建只有属主能连接的 socket
+///
+/// socket 先在 0700 的临时目录中创建并设为 0600，再移动到目标路径，
+/// 避免 bind 之后、chmod 之前的窗口内被其他本地用户连接。
+#[cfg(unix)]
+fn bind_private(path: &std::path::Path) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => std::path::Path::new("."),
+    };
+    let staging = parent.join(format!(".kiro-provider-{}.tmp", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+
+    let staged = staging.join("socket");
+    let bind = || -> Result<tokio::net::UnixListener> {
+        let listener = tokio::net::UnixListener::bind(&staged)?;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    };
+    let result = bind();
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&staging);
+    result
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_path: PathBuf, _connection: Arc<ConnectionOptions>) -> Result<()> {
+    anyhow::bail!("当前平台不支持 Unix socket，请使用 tcp:127.0.0.1:PORT")
+}
+
+async fn serve_tcp(addr: SocketAddr, connection: Arc<ConnectionOptions>) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("JSON-RPC 监听 tcp:{}", listener.local_addr()?);
+
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, peer) = accepted?;
+                debug!("新连接: {}", peer);
+                let (reader, writer) = stream.into_split();
+                connections.spawn(serve_connection(reader, writer, connection.clone()));
+            }
+            _ = shutdown::stopping() => break,
+        }
+    }
+
+    finish_connections(connections).await;
+    Ok(())
+}
+
+/// 关闭流程完成后，给各连接留出写完剩余响应的时间
+async fn finish_connections(mut connections: JoinSet<Result<()>>) {
+    shutdown::shutdown_once(shutdown::DEFAULT_DRAIN_TIMEOUT).await;
+
+    let wait_all = async {
+        while let Some(result) = connections.join_next().await {
+            match result {
+                Ok(Err(e)) => debug!("连接异常结束: {}", e),
+                Err(e) => debug!("连接任务异常: {}", e),
+                Ok(Ok(())) => {}
+            }
+        }
+    };
+    if tokio::time::timeout(WRITER_FLUSH_TIMEOUT * 2, wait_all).await.is_err() {
+        connections.abort_all();
+    }
+}
+
+/// 处理单个连接
+///
+/// 请求在独立任务中并发处理，响应经由通道串行写回。
+async fn serve_connection<R, W>(
+    reader: R,
+    mut writer: W,
+    options: Arc<ConnectionOptions>,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<Outbound>();
+    let initial_framing = options.framing;
+    let writer_task = tokio::spawn(async move {
+        let mut framing = initial_framing;
+        while let Some(outbound) = rx.recv().await {
+            match outbound {
+                Outbound::Message(message) => {
+                    debug!("Sending: {}", message);
+                    write_message(&mut writer, framing, &message).await?;
+                }
+                Outbound::SwitchFraming(new_framing) => framing = new_framing,
+            }
+        }
+        anyhow::Ok(())
+    });
+
+    let mut reader = MessageReader::new(
+        BufReader::new(reader),
+        options.framing,
+        options.max_message_size,
+    );
+    let mut authenticated = options.auth_token.is_none();
+    let mut stopped = false;
+    let executions = Executions::default();
+
+    loop {
+        let incoming = tokio::select! {
+            incoming = reader.read_message() => incoming,
+            _ = shutdown::stopping() => {
+                stopped = true;
+                break;
+            }
+        };
+
+        let message = match incoming {
+            Ok(Some(Incoming::Message(message))) => message,
+            Ok(Some(Incoming::TooLarge(size))) => {
+                warn!("消息过大（{} 字节），已丢弃", size);
+                send(
+                    &tx,
+                    JsonRpcResponse::error(
+                        serde_json::Value::Null,
+                        -32600,
+                        format!(
+                            "消息过大: {} 字节，上限 {} 字节",
+                            size, options.max_message_size
+                        ),
+                    ),
+                );
+                continue;
+            }
+            Ok(None) => {
+                debug!("连接已关闭");
+                stopped = options.owns_process;
+                break;
+            }
+            Err(e) => {
+                warn!("读取请求失败: {}", e);
+                stopped = options.owns_process;
+                break;
+            }
+        };
+        if message.trim().is_empty() {
+            continue;
+        }
+
+        debug!("Received: {}", message.trim_end());
+
+        let request = match serde_json::from_str::<JsonRpcRequest>(&message) {
+            Ok(request) => request,
+            Err(e) => {
+                send(
+                    &tx,
+                    JsonRpcResponse::error(
+                        serde_json::Value::Null,
+                        -32700,
+                        format!("Parse error: {}", e),
+                    ),
+                );
+                continue;
+            }
+        };
+
+        if !authenticated {
+            let params: AuthenticateParams = parse_params(&request).unwrap_or_default();
+            let expected = options.auth_token.as_deref().unwrap_or("");
+            if request.method == "authenticate" && constant_time_eq(&params.token, expected) {
+                authenticated = true;
+                send_result(&tx, request.id, AuthenticateResult { authenticated });
+                continue;
+            }
+
+            warn!("客户端认证失败，关闭连接");
+            send(
+                &tx,
+                JsonRpcResponse::error(request.id, -32002, "认证失败".to_string()),
+            );
+            break;
+        }
+
+        match request.method.as_str() {
+            method if !METHODS.contains(&method) => {
+                send(&tx, JsonRpcResponse::method_not_found(&request));
+            }
+            "authenticate" => send_result(&tx, request.id, AuthenticateResult { authenticated }),
+            "initialize" => {
+                let params: InitializeParams = match parse_params(&request) {
+                    Ok(params) => params,
+                    Err(error) => {
+                        send(&tx, JsonRpcResponse::from_error(request.id, error));
+                        continue;
+                    }
+                };
+                let framing = params.framing.unwrap_or(reader.framing());
+
+                // 响应仍使用当前分帧方式，之后的读写切换为协商结果
+                send_result(
+                    &tx,
+                    request.id,
+                    InitializeResult {
+                        name: env!("CARGO_PKG_NAME").to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                        framing,
+                        max_message_size: options.max_message_size,
+                    },
+                );
+                let _ = tx.send(Outbound::SwitchFraming(framing));
+                reader.set_framing(framing);
+            }
+            "shutdown" if !options.owns_process => {
+                send(
+                    &tx,
+                    JsonRpcResponse::error(
+                        request.id,
+                        -32601,
+                        "shutdown 只在 stdio 方式下可用，请向进程发送 SIGTERM".to_string(),
+                    ),
+                );
+            }
+            "shutdown" => {
+                let params: ShutdownParams = parse_params(&request).unwrap_or_default();
+                let timeout = params
+                    .timeout_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(shutdown::DEFAULT_DRAIN_TIMEOUT);
+                let report = shutdown::shutdown_once(timeout).await;
+                send_result(&tx, request.id, report);
+                stopped = true;
+                break;
+            }
+            "execute" => spawn_execute(request, tx.clone(), executions.clone()),
+            "cancel" => {
+                let params: CancelParams = parse_params(&request).unwrap_or_default();
+                let cancelled = executions.cancel(&params.request_id);
+                send_result(&tx, request.id, CancelResult { cancelled });
+            }
+            _ => spawn_request(request, tx.clone()),
+        }
+    }
+
+    drop(tx);
+
+    if stopped {
+        // 等关闭流程完成（进行中的请求已写出响应）后再收尾；
+        // 超时未完成的请求仍持有发送端，不再等待
+        shutdown::shutdown_once(shutdown::DEFAULT_DRAIN_TIMEOUT).await;
+        match tokio::time::timeout(WRITER_FLUSH_TIMEOUT, writer_task).await {
+            Ok(Ok(result)) => result?,
+            Ok(Err(e)) => warn!("写出任务异常退出: {}", e),
+            Err(_) => {}
+        }
+    } else {
+        writer_task.await??;
+    }
+
+    Ok(())
+}
+
+/// 在独立任务中处理请求，处理期间登记为进行中请求
+fn spawn_request(request: JsonRpcRequest, tx: mpsc::UnboundedSender<Outbound>) {
+    let Some(guard) = shutdown::track() else {
+        send(
+            &tx,
+            JsonRpcResponse::error(
+                request.id,
+                -32001,
+                "正在关闭，不再接收新请求".to_string(),
+            ),
+        );
+        return;
+    };
+
+    tokio::spawn(async move {
+        let response = handle_request(request).await;
+        send(&tx, response);
+        drop(guard);
+    });
+}
+
+/// 在独立任务中执行 `execute`，收到 `cancel` 时中止并返回取消错误
+///
+/// 中止时丢弃事件接收端，后台读取上游的任务随之停止并记录凭证使用结果。
+fn spawn_execute(
+    request: JsonRpcRequest,
+    tx: mpsc::UnboundedSender<Outbound>,
+    executions: Executions,
+) {
+    let params: ExecuteParams = match parse_params(&request) {
+        Ok(params) => params,
+        Err(error) => {
+            send(&tx, JsonRpcResponse::from_error(request.id, error));
+            return;
+        }
+    };
+    let Some(guard) = shutdown::track() else {
+        send(
+            &tx,
+            JsonRpcResponse::error(
+                request.id,
+                -32001,
+                "正在关闭，不再接收新请求".to_string(),
+            ),
+        );
+        return;
+    };
+    let Some(cancelled) = executions.register(&request.id) else {
+        send(
+            &tx,
+            JsonRpcResponse::error(
+                request.id.clone(),
+                -32600,
+                format!("请求 ID 已在执行中: {}", request.id),
+            ),
+        );
+        return;
+    };
+
+    tokio::spawn(async move {
+        let id = request.id;
+        let notify_tx = tx.clone();
+        let on_chunk = |chunk| {
+            let params = serde_json::to_value(chunk).unwrap_or_default();
+            notify(&notify_tx, JsonRpcNotification::new(execute::CHUNK_METHOD, params));
+        };
+
+        let response = tokio::select! {
+            result = execute::execute(params, &id, on_chunk) => match result {
+                Ok(result) => JsonRpcResponse::success(
+                    id,
+                    serde_json::to_value(result).unwrap_or_default(),
+                ),
+                Err(error) => JsonRpcResponse::from_error(id, error),
+            },
+            _ = cancelled => {
+                debug!("execute 已取消: {}", id);
+                JsonRpcResponse::error(id, execute::REQUEST_CANCELLED, "请求已取消".to_string())
+            }
+        };
+        executions.finish();
+        send(&tx, response);
+        drop(guard);
+    });
+}
+
+fn notify(tx: &mpsc::UnboundedSender<Outbound>, notification: JsonRpcNotification) {
+    if let Ok(notification_str) = serde_json::to_string(&notification) {
+        let _ = tx.send(Outbound::Message(notification_str));
+    }
+}
+
+fn send(tx: &mpsc::UnboundedSender<Outbound>, response: JsonRpcResponse) {
+    if let Ok(response_str) = serde_json::to_string(&response) {
+        let _ = tx.send(Outbound::Message(response_str));
+    }
+}
+
+fn send_result<T: Serialize>(tx: &mpsc::UnboundedSender<Outbound>, id: serde_json::Value, result: T) {
+    send(
+        tx,
+        JsonRpcResponse::success(id, serde_json::to_value(result).unwrap_or_default()),
+    );
+}
+
+/// 常量时间比较，避免通过响应时间推测密钥
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!("stdio".parse::<ListenAddr>(), Ok(ListenAddr::Stdio));
+        assert_eq!(
+            "unix:/tmp/kiro.sock".parse::<ListenAddr>(),
+            Ok(ListenAddr::Unix(PathBuf::from("/tmp/kiro.sock")))
+        );
+        assert_eq!(
+            "tcp:127.0.0.1:9000".parse::<ListenAddr>(),
+            Ok(ListenAddr::Tcp("127.0.0.1:9000".parse().unwrap()))
+        );
+        assert!("tcp:0.0.0.0:9000".parse::<ListenAddr>().is_err());
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert!("http://localhost".parse::<ListenAddr>().is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("secret", "secret"));
+        assert!(!constant_time_eq("secret", "secreT"));
+        assert!(!constant_time_eq("secret", "secret1"));
+    }
+
+    async fn roundtrip(auth_token: Option<&str>, input: &str) -> String {
+        roundtrip_with(auth_token, Framing::Newline, input).await
+    }
+
+    async fn roundtrip_with(auth_token: Option<&str>, framing: Framing, input: &str) -> String {
+        let (client, server) = tokio::io::duplex(4096);
+        let (server_read, server_write) = tokio::io::split(server);
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+
+        let options = Arc::new(ConnectionOptions {
+            auth_token: auth_token.map(String::from),
+            framing,
+            max_message_size: 1024,
+            owns_process: false,
+        });
+        let conn = tokio::spawn(serve_connection(server_read, server_write, options));
+
+        client_write.write_all(input.as_bytes()).await.unwrap();
+        client_write.shutdown().await.unwrap();
+        conn.await.unwrap().unwrap();
+
+        let mut output = String::new();
+        client_read.read_to_string(&mut output).await.unwrap();
+        output
+    }
+
+    #[tokio::test]
+    async fn test_connection_requires_authentication() {
+        let output = roundtrip(
+            Some("s3cret"),
+            "{\"jsonrpc\":\"2.0\",\"method\":\"get_info\",\"params\":{},\"id\":1}\n\
+             {\"jsonrpc\":\"2.0\",\"method\":\"get_info\",\"params\":{},\"id\":2}\n",
+        )
+        .await;
+
+        let responses: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        // 认证失败后连接立即关闭，第二个请求不会被处理
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0]["error"]["code"], -32002);
+    }
+
+    #[tokio::test]
+    async fn test_connection_authenticated_dispatch() {
+        let output = roundtrip(
+            Some("s3cret"),
+            "{\"jsonrpc\":\"2.0\",\"method\":\"authenticate\",\"params\":{\"token\":\"s3cret\"},\"id\":1}\n\
+             {\"jsonrpc\":\"2.0\",\"method\":\"supports_model\",\"params\":{\"model\":\"claude-haiku-4-5-20251001\"},\"id\":2}\n",
+        )
+        .await;
+
+        let responses: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["result"]["authenticated"], true);
+        assert_eq!(responses[1]["id"], 2);
+        assert_eq!(responses[1]["result"]["supports"], true);
+        assert_eq!(responses[1]["result"]["family"], "haiku");
+        assert_eq!(responses[1]["result"]["tier"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_transform_request_reports_normalization() {
+        let output = roundtrip(
+            None,
+            "{\"jsonrpc\":\"2.0\",\"method\":\"transform_request\",\"params\":{\"request\":{\"model\":\"claude-sonnet-4-5\",\"messages\":[{\"role\":\"user\",\"content\":\"A\"},{\"role\":\"user\",\"content\":\"B\"},{\"role\":\"user\",\"content\":\"C\"}]}},\"id\":1}\n",
+        )
+        .await;
+
+        let response: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        let rewrites = &response["result"]["normalization"]["rewrites"];
+        assert_eq!(
+            rewrites[0],
+            serde_json::json!({"type": "placeholder_inserted", "role": "assistant", "index": 1})
+        );
+        assert_eq!(rewrites[1]["type"], "merged");
+        assert!(response["result"]["request"].get("normalization").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_execute_and_cancel_handled_per_connection() {
+        let output = roundtrip(
+            None,
+            "{\"jsonrpc\":\"2.0\",\"method\":\"execute\",\"params\":{\"request\":{\"model\":\"gpt-4o\",\"messages\":[]}},\"id\":1}\n\
+             {\"jsonrpc\":\"2.0\",\"method\":\"cancel\",\"params\":{\"request_id\":42},\"id\":2}\n",
+        )
+        .await;
+
+        let responses: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        let execute = responses.iter().find(|r| r["id"] == 1).unwrap();
+        assert_eq!(execute["error"]["code"], -32602);
+        let cancel = responses.iter().find(|r| r["id"] == 2).unwrap();
+        assert_eq!(cancel["result"]["cancelled"], false);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_refused_on_shared_connection() {
+        let output = roundtrip(
+            None,
+            "{\"jsonrpc\":\"2.0\",\"method\":\"shutdown\",\"params\":{},\"id\":1}\n\
+             {\"jsonrpc\":\"2.0\",\"method\":\"supports_model\",\"params\":{\"model\":\"claude-opus-4-5\"},\"id\":2}\n",
+        )
+        .await;
+
+        let responses: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        // socket/TCP 连接不能关闭整个服务，之后的请求照常处理
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["error"]["code"], -32601);
+        assert_eq!(responses[1]["result"]["supports"], true);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_private_socket_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("kiro-transport-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rpc.sock");
+
+        let listener = bind_private(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // 临时目录已清理
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let client = tokio::net::UnixStream::connect(&path);
+        let (accepted, connected) = tokio::join!(listener.accept(), client);
+        assert!(accepted.is_ok() && connected.is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_initialize_negotiates_content_length() {
+        let framed = "{\"jsonrpc\":\"2.0\",\"method\":\"supports_model\",\"params\":{\"model\":\"gpt-4\"},\"id\":2}";
+        let input = format!(
+            "{{\"jsonrpc\":\"2.0\",\"method\":\"initialize\",\"params\":{{\"framing\":\"content-length\"}},\"id\":1}}\nContent-Length: {}\r\n\r\n{}",
+            framed.len(),
+            framed
+        );
+        let output = roundtrip(None, &input).await;
+
+        // initialize 的响应仍按行输出，之后改为 Content-Length 分帧
+        let (first, rest) = output.split_once('\n').unwrap();
+        let first: serde_json::Value = serde_json::from_str(first).unwrap();
+        assert_eq!(first["result"]["framing"], "content-length");
+
+        let body = rest.strip_prefix("Content-Length: ").unwrap();
+        let (length, body) = body.split_once("\r\n\r\n").unwrap();
+        assert_eq!(length.parse::<usize>().unwrap(), body.len());
+        let second: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(second["id"], 2);
+        assert_eq!(second["result"]["supports"], false);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_framed_message_rejected() {
+        let big = format!("{{\"pad\":\"{}\"}}", "x".repeat(2000));
+        let ok = "{\"jsonrpc\":\"2.0\",\"method\":\"supports_model\",\"params\":{\"model\":\"claude-opus-4-5\"},\"id\":3}";
+        let input = format!(
+            "Content-Length: {}\r\n\r\n{}Content-Length: {}\r\n\r\n{}",
+            big.len(),
+            big,
+            ok.len(),
+            ok
+        );
+        let output = roundtrip_with(None, Framing::ContentLength, &input).await;
+
+        let bodies: Vec<serde_json::Value> = output
+            .split("Content-Length: ")
+            .filter(|s| !s.is_empty())
+            .map(|s| serde_json::from_str(s.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0]["error"]["code"], -32600);
+        assert_eq!(bodies[1]["id"], 3);
+    }
+}
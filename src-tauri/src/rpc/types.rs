@@ -0,0 +1,258 @@
+//! JSON-RPC 方法的参数与结果类型
+//!
+//! 方法处理和 `rpc.discover` 生成的 OpenRPC 文档共用这些类型，文档不会与实现脱节。
+//! 字段均带默认值，缺省参数的行为与此前按字段逐个读取时一致。
+
+use super::framing::Framing;
+use crate::credentials::KiroCredentials;
+use crate::models::ModelMatch;
+use crate::translator::context::ContextReport;
+use crate::translator::normalize::NormalizeReport;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 按模型查询的参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ModelParams {
+    /// 模型 ID，如 `claude-sonnet-4-5-20250514`
+    #[serde(default)]
+    pub model: String,
+}
+
+/// 按凭证 ID 操作的参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct CredentialIdParams {
+    /// 凭证 ID
+    #[serde(default)]
+    pub credential_id: String,
+}
+
+/// `release_credential` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ReleaseCredentialParams {
+    /// 凭证 ID
+    #[serde(default)]
+    pub credential_id: String,
+    /// 请求结果；失败时包含 `error: { message, mark_unhealthy, cooldown_seconds }`
+    #[serde(default)]
+    pub result: serde_json::Value,
+}
+
+/// `create_credential` 参数
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateCredentialParams {
+    /// 认证方式，目前仅支持 `oauth`
+    #[serde(default = "default_auth_type")]
+    pub auth_type: String,
+    /// 凭证内容
+    #[serde(default)]
+    #[schemars(with = "KiroCredentials")]
+    pub config: serde_json::Value,
+}
+
+fn default_auth_type() -> String {
+    "oauth".to_string()
+}
+
+impl Default for CreateCredentialParams {
+    fn default() -> Self {
+        Self {
+            auth_type: default_auth_type(),
+            config: serde_json::Value::Null,
+        }
+    }
+}
+
+/// `transform_request` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct TransformRequestParams {
+    /// Anthropic 或 OpenAI 格式的请求体
+    #[serde(default)]
+    pub request: serde_json::Value,
+}
+
+/// `transform_response` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct TransformResponseParams {
+    /// 上游响应体
+    #[serde(default)]
+    pub response: serde_json::Value,
+}
+
+/// `apply_risk_control` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct RiskControlParams {
+    /// 待发送的请求体
+    #[serde(default)]
+    pub request: serde_json::Value,
+    /// 凭证 ID
+    #[serde(default)]
+    pub credential_id: String,
+}
+
+/// `parse_error` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ParseErrorParams {
+    /// 上游 HTTP 状态码
+    #[serde(default)]
+    pub status: u16,
+    /// 上游响应体
+    #[serde(default)]
+    pub body: String,
+}
+
+/// `authenticate` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct AuthenticateParams {
+    /// 共享密钥
+    #[serde(default)]
+    pub token: String,
+}
+
+/// `initialize` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct InitializeParams {
+    /// 期望的分帧方式，不填则保持当前方式
+    #[serde(default)]
+    pub framing: Option<Framing>,
+}
+
+/// `shutdown` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ShutdownParams {
+    /// 等待进行中请求的最长时间（毫秒），默认 30000
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// `execute` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ExecuteParams {
+    /// Anthropic Messages 格式的请求体；`stream: true` 时通过 `execute/chunk` 通知推送 SSE
+    #[serde(default)]
+    pub request: serde_json::Value,
+}
+
+/// `cancel` 参数
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct CancelParams {
+    /// 要取消的 `execute` 请求的 JSON-RPC ID
+    #[serde(default)]
+    pub request_id: serde_json::Value,
+}
+
+/// `supports_model` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SupportsModelResult {
+    /// 是否支持
+    pub supports: bool,
+    /// 模型目录中的模型 ID（请求的是别名时与请求不同）
+    pub model: Option<String>,
+    /// 按模型族 glob 模式匹配到的模型族
+    pub family: Option<String>,
+    /// 能力等级，越大越强
+    pub tier: Option<u8>,
+    /// 不支持时建议使用的最接近模型
+    pub suggestion: Option<String>,
+}
+
+impl From<ModelMatch<'_>> for SupportsModelResult {
+    fn from(matched: ModelMatch<'_>) -> Self {
+        Self {
+            supports: matched.spec.is_some(),
+            model: matched.spec.map(|s| s.id.clone()),
+            family: matched.family.map(|f| f.name.clone()),
+            tier: matched.tier(),
+            suggestion: matched.suggestion.map(|s| s.id.clone()),
+        }
+    }
+}
+
+/// `execute` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExecuteResult {
+    /// 本次使用的凭证 ID
+    pub credential_id: String,
+    /// 依次尝试过的凭证 ID（前面的因可重试错误被跳过），最后一个即 `credential_id`
+    pub tried_credentials: Vec<String>,
+    /// 合并后的 Anthropic Message（流式请求同样返回，便于宿主读取 usage）
+    pub message: serde_json::Value,
+    /// 上下文预算：输入 token 估算值及裁剪情况，宿主可据此提示用户
+    pub context: Option<ContextReport>,
+    /// 为满足 CodeWhisperer 的格式要求对历史所做的改写
+    pub normalization: NormalizeReport,
+}
+
+/// `execute/chunk` 通知参数
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExecuteChunkParams {
+    /// 所属 `execute` 请求的 JSON-RPC ID
+    pub request_id: serde_json::Value,
+    /// 一个 Anthropic SSE 事件（`event: ...\ndata: ...\n\n`）
+    pub chunk: String,
+}
+
+/// `cancel` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CancelResult {
+    /// 是否找到并取消了进行中的请求
+    pub cancelled: bool,
+}
+
+/// `create_credential` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CreateCredentialResult {
+    /// 新凭证 ID
+    pub credential_id: String,
+}
+
+/// `transform_request` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TransformRequestResult {
+    /// 转换后的请求体
+    pub request: serde_json::Value,
+    /// 解析出的 CodeWhisperer modelId
+    pub model_id: String,
+    /// 上下文预算：输入 token 估算值及裁剪情况，模型未声明上下文长度时为空
+    pub context: Option<ContextReport>,
+    /// 为满足 CodeWhisperer 的格式要求对历史所做的改写
+    pub normalization: NormalizeReport,
+}
+
+/// `transform_response` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TransformResponseResult {
+    /// 转换后的响应体
+    pub response: serde_json::Value,
+}
+
+/// `apply_risk_control` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RiskControlResult {
+    /// 应用风控后的请求体
+    pub request: serde_json::Value,
+}
+
+/// 无内容的结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EmptyResult {}
+
+/// `authenticate` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AuthenticateResult {
+    /// 是否认证成功
+    pub authenticated: bool,
+}
+
+/// `initialize` 结果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct InitializeResult {
+    /// 插件名称
+    pub name: String,
+    /// 插件版本
+    pub version: String,
+    /// 此后使用的分帧方式
+    pub framing: Framing,
+    /// content-length 分帧下单条消息的大小上限
+    pub max_message_size: usize,
+}
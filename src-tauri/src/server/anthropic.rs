@@ -0,0 +1,156 @@
+//! Anthropic Messages API
+
+use super::{parse_request, ApiError};
+use crate::translator::anthropic_to_cw::thinking_budget;
+use crate::translator::cw_to_anthropic::{collect_message, AnthropicSseEvent};
+use crate::translator::{CwToAnthropicTranslator, RequestFormat};
+use crate::{provider, shutdown, upstream};
+use axum::body::{Body, Bytes};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+
+/// `POST /v1/messages`
+pub async fn messages(body: Bytes) -> Result<Response, ApiError> {
+    let Some(guard) = shutdown::track() else {
+        return Err(ApiError::shutting_down());
+    };
+
+    let (request, model) = parse_request(&body)?;
+    let stream = request["stream"].as_bool().unwrap_or(false);
+    let thinking = thinking_budget(&request).is_some();
+
+    let response = upstream::send(&request, RequestFormat::Anthropic, &model).await?;
+    let mut events = response.into_events(&model, thinking);
+
+    if stream {
+        // 守卫随响应体一起释放，关闭流程会等待流式响应发送完毕
+        let body = futures_util::stream::unfold((events, guard), |(mut events, guard)| async {
+            let event = events.recv().await?;
+            let chunk = Bytes::from(CwToAnthropicTranslator::format_sse(&event));
+            Some((Ok::<_, Infallible>(chunk), (events, guard)))
+        });
+        return Ok((
+            [
+                (header::CONTENT_TYPE, "text/event-stream"),
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            Body::from_stream(body),
+        )
+            .into_response());
+    }
+
+    let mut collected: Vec<AnthropicSseEvent> = Vec::new();
+    while let Some(event) = events.recv().await {
+        collected.push(event);
+    }
+    drop(guard);
+
+    let message = collect_message(&collected);
+    if message["type"] == "error" {
+        return Err(ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "api_error",
+            message["error"]["message"]
+                .as_str()
+                .unwrap_or("上游返回错误"),
+        ));
+    }
+    Ok(Json(message).into_response())
+}
+
+/// `GET /v1/models`
+///
+/// 同时带有 Anthropic 和 OpenAI 模型列表的字段，两种 SDK 都能解析。
+pub async fn models() -> Json<Value> {
+    let data: Vec<Value> = provider::list_models()
+        .into_iter()
+        .map(|model| {
+            json!({
+                "type": "model",
+                "object": "model",
+                "id": model.id,
+                "display_name": model.display_name,
+                "owned_by": "anthropic",
+            })
+        })
+        .collect();
+
+    Json(json!({
+        "object": "list",
+        "first_id": data.first().map(|m| m["id"].clone()),
+        "last_id": data.last().map(|m| m["id"].clone()),
+        "has_more": false,
+        "data": data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::tests::spawn_server;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn test_list_models() {
+        let base = spawn_server(None).await;
+        let body: Value = reqwest::get(format!("{}/v1/models", base))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let data = body["data"].as_array().unwrap();
+        assert!(!data.is_empty());
+        assert_eq!(data[0]["type"], "model");
+        assert_eq!(body["first_id"], data[0]["id"]);
+        assert_eq!(body["has_more"], false);
+    }
+
+    #[tokio::test]
+    async fn test_messages_rejects_invalid_requests() {
+        let base = spawn_server(None).await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/v1/messages", base);
+
+        let cases = [
+            ("not json", 400, "invalid_request_error"),
+            (r#"{"messages": []}"#, 400, "invalid_request_error"),
+            (
+                r#"{"model": "claude-sonnet-4-5", "messages": "hi"}"#,
+                400,
+                "invalid_request_error",
+            ),
+            (
+                r#"{"model": "gpt-4o", "messages": []}"#,
+                404,
+                "not_found_error",
+            ),
+        ];
+        for (body, status, error_type) in cases {
+            let response = client.post(&url).body(body).send().await.unwrap();
+            assert_eq!(response.status(), status, "{}", body);
+            let error: Value = response.json().await.unwrap();
+            assert_eq!(error["error"]["type"], error_type, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages_accepts_large_body() {
+        let base = spawn_server(None).await;
+        // 超过 axum 默认的 2 MB 上限，应进入请求解析而不是返回 413
+        let body = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "x".repeat(3 * 1024 * 1024)}]
+        });
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/messages", base))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,319 @@
+//! HTTP 服务模式
+//!
+//! `serve --bind 127.0.0.1:PORT` 直接提供 Anthropic 兼容的 HTTP 接口，不依赖
+//! ProxyCast 宿主：
+//! - `POST /v1/messages`：流式（SSE）与非流式
+//! - `POST /v1/chat/completions`：OpenAI 兼容，流式与非流式
+//! - `GET /v1/models`
+//!
+//! 配置了共享密钥时，请求需通过 `x-api-key` 或 `Authorization: Bearer` 携带。
+//! 请求体大小上限与 JSON-RPC 的 `--max-message-size` 相同。
+//! 收到 SIGTERM/SIGINT 后停止接收新连接，等待进行中的请求完成并写回凭证存储。
+
+mod anthropic;
+mod openai;
+
+use crate::rpc::transport::constant_time_eq;
+use crate::upstream::{SendError, UpstreamError};
+use crate::{provider, shutdown};
+use anyhow::{Context, Result};
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// 服务选项
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    pub bind: SocketAddr,
+    /// 共享密钥，为空时不校验
+    pub auth_token: Option<String>,
+    /// 请求体大小上限（字节）
+    pub max_body_size: usize,
+}
+
+/// 各请求共享的状态
+#[derive(Debug)]
+struct ServerState {
+    auth_token: Option<String>,
+}
+
+/// 启动 HTTP 服务，直到关闭流程完成
+pub async fn serve(options: ServerOptions) -> Result<()> {
+    if let Err(e) = provider::load_credentials().await {
+        warn!("加载凭证存储失败: {}", e);
+    }
+
+    let auth_token = options.auth_token.filter(|t| !t.is_empty());
+    if auth_token.is_none() && !options.bind.ip().is_loopback() {
+        warn!(
+            "HTTP 服务监听非本地地址 {} 且未设置共享密钥，任何能访问该地址的人都可以使用凭证池",
+            options.bind
+        );
+    }
+
+    tokio::spawn(async {
+        shutdown::wait_for_signal().await;
+        shutdown::shutdown_once(shutdown::DEFAULT_DRAIN_TIMEOUT).await;
+    });
+
+    let listener = tokio::net::TcpListener::bind(options.bind)
+        .await
+        .with_context(|| format!("监听 {} 失败", options.bind))?;
+    info!("HTTP 服务已启动: http://{}", listener.local_addr()?);
+
+    let app = router(Arc::new(ServerState { auth_token }), options.max_body_size);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown::stopping())
+        .await?;
+
+    shutdown::shutdown_once(shutdown::DEFAULT_DRAIN_TIMEOUT).await;
+    Ok(())
+}
+
+fn router(state: Arc<ServerState>, max_body_size: usize) -> Router {
+    Router::new()
+        .route("/v1/messages", post(anthropic::messages))
+        .route("/v1/chat/completions", post(openai::chat_completions))
+        .route("/v1/models", get(anthropic::models))
+        // 默认只允许 2 MB，带图片或长历史的请求会被拒绝
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .layer(middleware::from_fn_with_state(state, authenticate))
+}
+
+/// 校验共享密钥
+async fn authenticate(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ref expected) = state.auth_token {
+        let provided = request_token(request.headers()).unwrap_or_default();
+        if !constant_time_eq(provided, expected) {
+            let error = ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "authentication_error",
+                "无效的 API Key",
+            );
+            // OpenAI SDK 只认自己的错误格式
+            if request.uri().path() == "/v1/chat/completions" {
+                return OpenAiError(error).into_response();
+            }
+            return error.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// 从 `x-api-key` 或 `Authorization: Bearer` 中取出密钥
+fn request_token(headers: &HeaderMap) -> Option<&str> {
+    if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+        return Some(key);
+    }
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// 解析请求体，校验 `model` 和 `messages`
+fn parse_request(body: &[u8]) -> Result<(Value, String), ApiError> {
+    let request: Value = serde_json::from_slice(body)
+        .map_err(|e| ApiError::invalid_request(format!("请求体不是有效的 JSON: {}", e)))?;
+    let model = request["model"]
+        .as_str()
+        .ok_or_else(|| ApiError::invalid_request("缺少 model 字段"))?
+        .to_string();
+    if !request["messages"].is_array() {
+        return Err(ApiError::invalid_request("messages 必须是数组"));
+    }
+    if !provider::supports_model(&model) {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "not_found_error",
+            provider::unsupported_model_message(&model),
+        ));
+    }
+    Ok((request, model))
+}
+
+/// Anthropic 格式的错误响应
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    error_type: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, error_type: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            error_type,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+    }
+
+    fn shutting_down() -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "overloaded_error",
+            "服务正在关闭",
+        )
+    }
+}
+
+impl From<UpstreamError> for ApiError {
+    fn from(error: UpstreamError) -> Self {
+        let message = error.to_string();
+        match error {
+            UpstreamError::Credential(_) => {
+                Self::new(StatusCode::SERVICE_UNAVAILABLE, "overloaded_error", message)
+            }
+            UpstreamError::Status { status: 400, .. } | UpstreamError::InvalidRequest(_) => {
+                Self::invalid_request(message)
+            }
+            UpstreamError::Status { status: 429, .. } => {
+                Self::new(StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", message)
+            }
+            // 上游的 401/403/5xx 是凭证或服务端的问题，不是调用方的问题
+            UpstreamError::Status { .. } | UpstreamError::Network(_) => {
+                Self::new(StatusCode::BAD_GATEWAY, "api_error", message)
+            }
+        }
+    }
+}
+
+impl From<SendError> for ApiError {
+    fn from(error: SendError) -> Self {
+        error.error.into()
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "type": "error",
+            "error": { "type": self.error_type, "message": self.message },
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// OpenAI 格式的错误响应
+#[derive(Debug)]
+pub struct OpenAiError(ApiError);
+
+impl From<ApiError> for OpenAiError {
+    fn from(error: ApiError) -> Self {
+        Self(error)
+    }
+}
+
+impl From<SendError> for OpenAiError {
+    fn from(error: SendError) -> Self {
+        Self(error.into())
+    }
+}
+
+impl IntoResponse for OpenAiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "error": {
+                "message": self.0.message,
+                "type": self.0.error_type,
+                "param": null,
+                "code": null,
+            }
+        });
+        (self.0.status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在随机端口启动服务，返回 base URL
+    pub(super) async fn spawn_server(auth_token: Option<&str>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(
+            Arc::new(ServerState {
+                auth_token: auth_token.map(String::from),
+            }),
+            crate::rpc::framing::DEFAULT_MAX_MESSAGE_SIZE,
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_required() {
+        let base = spawn_server(Some("secret")).await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{}/v1/models", base))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "authentication_error");
+
+        for (name, value) in [("x-api-key", "secret"), ("authorization", "Bearer secret")] {
+            let response = client
+                .get(format!("{}/v1/models", base))
+                .header(name, value)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_upstream_error_mapping() {
+        let cases = [
+            (UpstreamError::Credential("没有可用的健康凭证".into()), 503),
+            (
+                UpstreamError::Status {
+                    status: 400,
+                    message: "too long".into(),
+                },
+                400,
+            ),
+            (
+                UpstreamError::Status {
+                    status: 429,
+                    message: String::new(),
+                },
+                429,
+            ),
+            (
+                UpstreamError::Status {
+                    status: 403,
+                    message: String::new(),
+                },
+                502,
+            ),
+            (UpstreamError::Network("timeout".into()), 502),
+            (UpstreamError::InvalidRequest("不支持的模型".into()), 400),
+        ];
+        for (error, status) in cases {
+            assert_eq!(ApiError::from(error).status.as_u16(), status);
+        }
+    }
+}
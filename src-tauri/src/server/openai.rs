@@ -0,0 +1,105 @@
+//! OpenAI Chat Completions API
+
+use super::{parse_request, ApiError, OpenAiError};
+use crate::translator::cw_to_anthropic::AnthropicSseEvent;
+use crate::translator::cw_to_openai::{collect_completion, CwToOpenAiTranslator, SSE_DONE};
+use crate::translator::RequestFormat;
+use crate::{shutdown, upstream};
+use axum::body::{Body, Bytes};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::convert::Infallible;
+
+/// `POST /v1/chat/completions`
+pub async fn chat_completions(body: Bytes) -> Result<Response, OpenAiError> {
+    let Some(guard) = shutdown::track() else {
+        return Err(ApiError::shutting_down().into());
+    };
+
+    let (request, model) = parse_request(&body)?;
+    let stream = request["stream"].as_bool().unwrap_or(false);
+    let include_usage = request["stream_options"]["include_usage"]
+        .as_bool()
+        .unwrap_or(false);
+
+    let response = upstream::send(&request, RequestFormat::OpenAi, &model).await?;
+    let mut events = response.into_events(&model, false);
+
+    if stream {
+        let translator = CwToOpenAiTranslator::new(&model, include_usage);
+        // 守卫随响应体一起释放，关闭流程会等待流式响应发送完毕
+        let body =
+            futures_util::stream::unfold(Some((events, translator, guard)), |state| async move {
+                let (mut events, mut translator, guard) = state?;
+                let Some(event) = events.recv().await else {
+                    // 事件流结束后补发 [DONE]，然后结束响应体
+                    return Some((Ok::<_, Infallible>(Bytes::from(SSE_DONE)), None));
+                };
+                let chunk: String = translator
+                    .translate(&event)
+                    .iter()
+                    .map(CwToOpenAiTranslator::format_sse)
+                    .collect();
+                Some((Ok(Bytes::from(chunk)), Some((events, translator, guard))))
+            });
+        return Ok((
+            [
+                (header::CONTENT_TYPE, "text/event-stream"),
+                (header::CACHE_CONTROL, "no-cache"),
+            ],
+            Body::from_stream(body),
+        )
+            .into_response());
+    }
+
+    let mut collected: Vec<AnthropicSseEvent> = Vec::new();
+    while let Some(event) = events.recv().await {
+        collected.push(event);
+    }
+    drop(guard);
+
+    let completion = collect_completion(&collected, &model);
+    if let Some(error) = completion.get("error") {
+        return Err(ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "api_error",
+            error["message"].as_str().unwrap_or("上游返回错误"),
+        )
+        .into());
+    }
+    Ok(Json(completion).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::tests::spawn_server;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn test_errors_use_openai_format() {
+        let base = spawn_server(Some("secret")).await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/v1/chat/completions", base);
+
+        let response = client.post(&url).body("{}").send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let error: Value = response.json().await.unwrap();
+        assert_eq!(error["error"]["type"], "authentication_error");
+        assert!(error.get("type").is_none());
+
+        let response = client
+            .post(&url)
+            .bearer_auth("secret")
+            .body(r#"{"model": "gpt-4o", "messages": []}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        let error: Value = response.json().await.unwrap();
+        assert!(error["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("gpt-4o"));
+    }
+}
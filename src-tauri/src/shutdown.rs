@@ -0,0 +1,270 @@
+//! 优雅关闭
+//!
+//! 收到 `shutdown` 请求、stdin 关闭或 SIGTERM/SIGINT 时：
+//! 1. 停止接收新请求
+//! 2. 在截止时间内等待进行中的请求（尤其是 Token 刷新）完成
+//! 3. 将凭证池写回持久化存储
+//!
+//! Token 刷新时服务端会轮换 refresh_token，若在拿到新 token 之前退出，
+//! 旧 token 已经失效，账号就丢失了，所以关闭前必须等刷新完成并落盘。
+
+use crate::provider;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{Notify, OnceCell};
+use tracing::{info, warn};
+
+/// 默认等待进行中请求的时间
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 写回存储的最长等待时间（凭证锁可能被超时未完成的刷新占用）
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static::lazy_static! {
+    static ref LIFECYCLE: Lifecycle = Lifecycle::new();
+    static ref REPORT: OnceCell<ShutdownReport> = OnceCell::new();
+}
+
+/// 关闭结果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ShutdownReport {
+    /// 进行中的请求是否全部完成
+    pub drained: bool,
+    /// 超时后仍未完成的请求数
+    pub pending: usize,
+    /// 凭证池是否已写回存储
+    pub flushed: bool,
+}
+
+/// 进程生命周期状态：是否正在关闭以及进行中的请求数
+struct Lifecycle {
+    shutting_down: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    stop: Notify,
+}
+
+/// 进行中请求的计数守卫，Drop 时自动减一
+pub struct InFlightGuard {
+    lifecycle: &'static Lifecycle,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.lifecycle.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.lifecycle.idle.notify_waiters();
+        }
+    }
+}
+
+impl Lifecycle {
+    fn new() -> Self {
+        Self {
+            shutting_down: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+            stop: Notify::new(),
+        }
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    fn track(&'static self) -> Option<InFlightGuard> {
+        if self.is_shutting_down() {
+            return None;
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Some(InFlightGuard { lifecycle: self })
+    }
+
+    fn begin(&self) -> bool {
+        let first = !self.shutting_down.swap(true, Ordering::SeqCst);
+        if first {
+            self.stop.notify_waiters();
+        }
+        first
+    }
+
+    async fn stopping(&self) {
+        loop {
+            let notified = self.stop.notified();
+            if self.is_shutting_down() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    async fn drain(&self, timeout: Duration) -> bool {
+        let wait_idle = async {
+            loop {
+                let notified = self.idle.notified();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+
+        tokio::time::timeout(timeout, wait_idle).await.is_ok()
+    }
+}
+
+/// 当前进行中的请求数
+pub fn in_flight() -> usize {
+    LIFECYCLE.in_flight()
+}
+
+/// 登记一个进行中的请求，正在关闭时返回 None
+pub fn track() -> Option<InFlightGuard> {
+    LIFECYCLE.track()
+}
+
+/// 标记开始关闭，之后的请求都会被拒绝
+///
+/// 返回 false 表示已经处于关闭流程中。
+pub fn begin() -> bool {
+    LIFECYCLE.begin()
+}
+
+/// 等待进行中的请求完成，超时返回 false
+pub async fn drain(timeout: Duration) -> bool {
+    LIFECYCLE.drain(timeout).await
+}
+
+/// 等待关闭流程开始，供各传输方式停止接收新连接和新请求
+pub async fn stopping() {
+    LIFECYCLE.stopping().await
+}
+
+/// 执行关闭流程（只执行一次）
+///
+/// 多个连接或信号同时触发时，后来者等待并共享第一次的结果。
+pub async fn shutdown_once(timeout: Duration) -> ShutdownReport {
+    REPORT
+        .get_or_init(|| graceful_shutdown(timeout))
+        .await
+        .clone()
+}
+
+/// 执行完整的关闭流程：停止接收 → 等待进行中请求 → 写回存储
+pub async fn graceful_shutdown(timeout: Duration) -> ShutdownReport {
+    begin();
+
+    let pending = in_flight();
+    if pending > 0 {
+        info!("等待 {} 个进行中的请求完成（最多 {:?}）", pending, timeout);
+    }
+
+    let drained = drain(timeout).await;
+    let pending = in_flight();
+    if !drained {
+        warn!("等待超时，仍有 {} 个请求未完成", pending);
+    }
+
+    let flushed = match tokio::time::timeout(FLUSH_TIMEOUT, provider::flush_credentials()).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            warn!("写回凭证存储失败: {}", e);
+            false
+        }
+        Err(_) => {
+            warn!("写回凭证存储超时");
+            false
+        }
+    };
+
+    info!(
+        "关闭完成: drained={}, pending={}, flushed={}",
+        drained, pending, flushed
+    );
+
+    ShutdownReport {
+        drained,
+        pending,
+        flushed,
+    }
+}
+
+/// 等待 SIGINT（Ctrl+C）或 SIGTERM
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => info!("收到 SIGINT"),
+                    _ = term.recv() => info!("收到 SIGTERM"),
+                }
+            }
+            Err(e) => {
+                warn!("无法监听 SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                info!("收到 SIGINT");
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("收到 Ctrl+C");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_track_and_drain() {
+        let lifecycle: &'static Lifecycle = Box::leak(Box::new(Lifecycle::new()));
+
+        let guard = lifecycle.track().expect("未关闭时应允许登记");
+        assert_eq!(lifecycle.in_flight(), 1);
+
+        // 有进行中请求时等待会超时
+        assert!(!lifecycle.drain(Duration::from_millis(20)).await);
+
+        let waiter = tokio::spawn(lifecycle.drain(Duration::from_secs(5)));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(guard);
+        assert!(waiter.await.unwrap());
+        assert_eq!(lifecycle.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_begin_rejects_new_requests() {
+        let lifecycle: &'static Lifecycle = Box::leak(Box::new(Lifecycle::new()));
+
+        assert!(lifecycle.begin());
+        assert!(!lifecycle.begin());
+        assert!(lifecycle.is_shutting_down());
+        assert!(lifecycle.track().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stopping_wakes_on_begin() {
+        let lifecycle: &'static Lifecycle = Box::leak(Box::new(Lifecycle::new()));
+
+        let waiter = tokio::spawn(lifecycle.stopping());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        lifecycle.begin();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("begin 之后 stopping 应当返回")
+            .unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+//! 凭证持久化存储
+//!
+//! 凭证池以 `{ 凭证 ID: KiroCredentials }` 的 JSON 形式保存在本地文件中，
+//! JSON-RPC 模式与 CLI 子命令共用同一份存储。
+//!
+//! 文件是唯一的事实来源：长期运行的进程每次写回前都重新读取文件，用
+//! [`merge_runtime`] 只带上本进程刷新的 token 和运行统计，因此 `credentials`
+//! 子命令在进程运行期间做的增删、禁用、改名不会被覆盖。
+
+use crate::credentials::KiroCredentials;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::debug;
+
+/// 覆盖存储路径的环境变量
+pub const STORE_ENV: &str = "KIRO_PROVIDER_STORE";
+
+lazy_static::lazy_static! {
+    static ref STORE_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// 默认存储路径
+///
+/// 优先使用 `KIRO_PROVIDER_STORE` 环境变量，否则为
+/// `<config_dir>/kiro-provider/credentials.json`。
+pub fn default_store_path() -> PathBuf {
+    if let Ok(path) = std::env::var(STORE_ENV) {
+        if !path.is_empty() {
+            return PathBuf::from(path);
+        }
+    }
+
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("kiro-provider")
+        .join("credentials.json")
+}
+
+/// 设置存储路径（命令行 `--store` 参数）
+pub fn set_store_path(path: PathBuf) {
+    *STORE_PATH.write().unwrap() = Some(path);
+}
+
+/// 当前使用的存储路径
+pub fn store_path() -> PathBuf {
+    STORE_PATH
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(default_store_path)
+}
+
+/// 从文件加载凭证池，文件不存在时返回空池
+pub fn load(path: &Path) -> Result<HashMap<String, KiroCredentials>> {
+    if !path.exists() {
+        debug!("凭证存储不存在，使用空凭证池: {}", path.display());
+        return Ok(HashMap::new());
+    }
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("读取凭证存储失败: {}", path.display()))?;
+    if content.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    serde_json::from_str(&content)
+        .with_context(|| format!("解析凭证存储失败: {}", path.display()))
+}
+
+/// 将凭证池写入文件
+///
+/// 通过 [`write_atomic`] 写入，避免进程中途退出时留下半截文件。
+pub fn save(path: &Path, credentials: &HashMap<String, KiroCredentials>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("创建存储目录失败: {}", parent.display()))?;
+        }
+    }
+
+    // 按 ID 排序输出，便于人工查看和比对
+    let sorted: BTreeMap<_, _> = credentials.iter().collect();
+    let content = serde_json::to_string_pretty(&sorted)?;
+
+    write_atomic(path, content.as_bytes())?;
+
+    debug!("凭证存储已写入: {} ({} 个凭证)", path.display(), credentials.len());
+    Ok(())
+}
+
+/// 把内存中的运行状态合并到刚从文件读取的凭证池
+///
+/// 凭证的增删、名称、禁用状态等以文件为准；本进程刷新得更晚（`last_refresh`
+/// 更新）的 token 和健康状态、使用统计取内存中的值。文件中的凭证在本进程载入后
+/// 被重置过（`reset_at` 更新）时，健康状态和统计以文件为准。文件中已删除的凭证
+/// 不会恢复。
+pub fn merge_runtime(
+    latest: &mut HashMap<String, KiroCredentials>,
+    memory: &HashMap<String, KiroCredentials>,
+) {
+    for (id, stored) in latest.iter_mut() {
+        let Some(current) = memory.get(id) else {
+            continue;
+        };
+        if later(&current.last_refresh, &stored.last_refresh) {
+            stored.access_token = current.access_token.clone();
+            stored.refresh_token = current.refresh_token.clone();
+            stored.expire = current.expire.clone();
+            stored.last_refresh = current.last_refresh.clone();
+        }
+        if later(&stored.reset_at, &current.reset_at) {
+            continue;
+        }
+        stored.is_healthy = current.is_healthy;
+        stored.usage_count = current.usage_count;
+        stored.error_count = current.error_count;
+        stored.last_error = current.last_error.clone();
+    }
+}
+
+/// 时间 `a` 是否晚于 `b`（RFC3339），无法解析或缺失的时间视为最早
+fn later(a: &Option<String>, b: &Option<String>) -> bool {
+    let parse = |t: &Option<String>| {
+        t.as_deref()
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+    };
+    parse(a) > parse(b)
+}
+
+/// 原子写入文件：先写同目录下的临时文件再重命名，Unix 下权限为 0600
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    write_private(&tmp_path, content)
+        .with_context(|| format!("写入文件失败: {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("替换文件失败: {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("kiro-provider-test-{}", uuid::Uuid::new_v4()))
+            .join("credentials.json")
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let path = temp_store();
+        let creds = load(&path).unwrap();
+        assert!(creds.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let path = temp_store();
+
+        let mut creds = HashMap::new();
+        creds.insert(
+            "cred-1".to_string(),
+            KiroCredentials {
+                refresh_token: Some("rt".to_string()),
+                usage_count: 7,
+                ..Default::default()
+            },
+        );
+        save(&path, &creds).unwrap();
+
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded["cred-1"].refresh_token.as_deref(), Some("rt"));
+        assert_eq!(loaded["cred-1"].usage_count, 7);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_merge_runtime_keeps_external_edits() {
+        let credential = |token: &str, refreshed: &str| KiroCredentials {
+            access_token: Some(token.to_string()),
+            refresh_token: Some(format!("{}-rt", token)),
+            last_refresh: Some(refreshed.to_string()),
+            ..Default::default()
+        };
+
+        // 内存：进程启动时加载的三个凭证，a 刚刷新过，b 有使用记录
+        let mut memory = HashMap::new();
+        memory.insert(
+            "a".to_string(),
+            credential("new", "2026-10-18T10:00:00+00:00"),
+        );
+        memory.insert(
+            "b".to_string(),
+            KiroCredentials {
+                usage_count: 5,
+                ..credential("b", "2026-10-18T08:00:00+00:00")
+            },
+        );
+        memory.insert(
+            "c".to_string(),
+            credential("c", "2026-10-18T08:00:00+00:00"),
+        );
+
+        // 文件：期间 CLI 禁用并改名了 a，删除了 c，另一个进程刷新了 b
+        let mut latest = HashMap::new();
+        latest.insert(
+            "a".to_string(),
+            KiroCredentials {
+                name: Some("work".to_string()),
+                disabled: true,
+                ..credential("old", "2026-10-18T08:00:00+00:00")
+            },
+        );
+        latest.insert(
+            "b".to_string(),
+            credential("b2", "2026-10-18T09:00:00.5+00:00"),
+        );
+
+        merge_runtime(&mut latest, &memory);
+
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest["a"].access_token.as_deref(), Some("new"));
+        assert_eq!(latest["a"].refresh_token.as_deref(), Some("new-rt"));
+        assert!(latest["a"].disabled);
+        assert_eq!(latest["a"].name.as_deref(), Some("work"));
+        assert_eq!(latest["b"].access_token.as_deref(), Some("b2"));
+        assert_eq!(latest["b"].usage_count, 5);
+    }
+
+    #[test]
+    fn test_merge_runtime_keeps_external_reset() {
+        // 运行中的进程仍认为凭证不健康
+        let stale = KiroCredentials {
+            is_healthy: false,
+            error_count: 3,
+            usage_count: 9,
+            last_error: Some("Token 刷新失败".to_string()),
+            ..Default::default()
+        };
+        let mut memory = HashMap::new();
+        memory.insert("a".to_string(), stale);
+
+        // CLI 在此期间执行了 `credentials reset`
+        let reset = KiroCredentials {
+            reset_at: Some("2026-10-18T10:00:00+00:00".to_string()),
+            ..Default::default()
+        };
+        let mut latest = HashMap::new();
+        latest.insert("a".to_string(), reset.clone());
+
+        merge_runtime(&mut latest, &memory);
+        assert!(latest["a"].is_healthy);
+        assert_eq!(latest["a"].error_count, 0);
+        assert_eq!(latest["a"].usage_count, 0);
+        assert!(latest["a"].last_error.is_none());
+
+        // 进程载入重置结果后，之后的运行状态照常写回
+        let mut memory = latest.clone();
+        memory.get_mut("a").unwrap().usage_count = 1;
+        let mut latest = HashMap::new();
+        latest.insert("a".to_string(), reset);
+        merge_runtime(&mut latest, &memory);
+        assert_eq!(latest["a"].usage_count, 1);
+    }
+}
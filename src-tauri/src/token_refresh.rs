@@ -10,11 +10,12 @@ use crate::risk_control::{
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
 use reqwest::Client;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 
 /// Token 刷新结果
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TokenRefreshResult {
     /// 新的 access_token
     pub access_token: String,
@@ -26,6 +27,23 @@ pub struct TokenRefreshResult {
     pub expires_at: Option<DateTime<Utc>>,
 }
 
+/// 认证服务拒绝刷新请求（非 2xx 响应）
+#[derive(Debug, thiserror::Error)]
+#[error("{auth_method} Token 刷新失败: {status} - {body}")]
+pub struct RefreshRejected {
+    pub auth_method: &'static str,
+    pub status: u16,
+    pub body: String,
+}
+
+impl RefreshRejected {
+    /// refresh_token 已被撤销或失效（400/401 `invalid_grant`），重试不会成功
+    pub fn is_invalid_grant(&self) -> bool {
+        matches!(self.status, 400 | 401)
+            && (self.body.contains("invalid_grant") || self.body.contains("InvalidGrant"))
+    }
+}
+
 /// Token 刷新响应
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -141,7 +159,12 @@ async fn refresh_social_token(
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        anyhow::bail!("Social Token 刷新失败: {} - {}", status, body);
+        return Err(RefreshRejected {
+            auth_method: "Social",
+            status: status.as_u16(),
+            body,
+        }
+        .into());
     }
 
     let data: TokenResponse = response.json().await?;
@@ -209,7 +232,12 @@ async fn refresh_idc_token(
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        anyhow::bail!("IdC Token 刷新失败: {} - {}", status, body);
+        return Err(RefreshRejected {
+            auth_method: "IdC",
+            status: status.as_u16(),
+            body,
+        }
+        .into());
     }
 
     let data: TokenResponse = response.json().await?;
@@ -1,8 +1,11 @@
 //! Anthropic → CodeWhisperer 转换
 
-use super::map_model_name;
+use super::context::{self, ContextReport};
+use super::normalize::{normalize, NormalizeReport};
+use super::{request_model_id, TranslateError};
+use crate::config;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 
 /// CodeWhisperer 请求结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,8 +16,12 @@ pub struct CodeWhispererRequest {
     pub profile_arn: Option<String>,
     pub source: String,
     pub assistant_response_config: AssistantResponseConfig,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tools: Option<Vec<Value>>,
+    /// 上下文预算结果，不发送给上游
+    #[serde(skip)]
+    pub context: Option<ContextReport>,
+    /// 历史规范化所做的改写，不发送给上游
+    #[serde(skip)]
+    pub normalization: NormalizeReport,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,7 +33,35 @@ pub struct ConversationState {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub customization_arn: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub history: Option<Vec<Value>>,
+    pub history: Option<Vec<HistoryMessage>>,
+}
+
+/// 历史消息，用户与助手交替出现
+///
+/// 序列化为 `{"userInputMessage": {...}}` 或 `{"assistantResponseMessage": {...}}`。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HistoryMessage {
+    UserInputMessage(UserInputMessage),
+    AssistantResponseMessage(AssistantResponseMessage),
+}
+
+/// 历史中的助手回复
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssistantResponseMessage {
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_uses: Option<Vec<ToolUse>>,
+}
+
+/// 助手发起的工具调用，`tool_use_id` 与后续的工具结果对应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolUse {
+    pub tool_use_id: String,
+    pub name: String,
+    pub input: Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,12 +74,103 @@ pub struct CurrentMessage {
 #[serde(rename_all = "camelCase")]
 pub struct UserInputMessage {
     pub content: String,
+    /// CodeWhisperer 模型 ID，只在当前消息上设置
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub user_input_message_context: Option<Value>,
+    pub model_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_input_message_context: Option<UserInputMessageContext>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub images: Option<Vec<CWImage>>,
 }
 
+impl UserInputMessage {
+    /// 附加工具定义，没有工具时不改动
+    pub fn attach_tools(&mut self, tools: Vec<Tool>) {
+        if tools.is_empty() {
+            return;
+        }
+        self.user_input_message_context
+            .get_or_insert_with(UserInputMessageContext::default)
+            .tools = Some(tools);
+    }
+}
+
+/// 用户消息的上下文（工具定义和工具结果）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInputMessageContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_results: Option<Vec<ToolResult>>,
+}
+
+/// 工具执行结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolResult {
+    pub tool_use_id: String,
+    pub content: Vec<ToolResultContent>,
+    /// `success` 或 `error`
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolResultContent {
+    pub text: String,
+}
+
+/// CodeWhisperer 工具定义
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    pub tool_specification: ToolSpecification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolSpecification {
+    pub name: String,
+    pub description: String,
+    pub input_schema: InputSchema,
+}
+
+/// 工具参数的 JSON Schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputSchema {
+    pub json: Value,
+}
+
+impl Tool {
+    /// 由名称、描述和参数 schema 构造工具定义
+    ///
+    /// CodeWhisperer 拒绝空描述，缺失时使用工具名；schema 缺失或为空时
+    /// 使用不带参数的 object schema，缺少 `type` 时补为 object。
+    pub fn new(name: &str, description: Option<&str>, schema: Option<&Value>) -> Self {
+        let description = description
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .unwrap_or(name)
+            .to_string();
+
+        let mut schema = match schema {
+            Some(Value::Object(object)) if !object.is_empty() => Value::Object(object.clone()),
+            _ => json!({ "type": "object", "properties": {} }),
+        };
+        if schema.get("type").is_none() {
+            schema["type"] = json!("object");
+        }
+
+        Self {
+            tool_specification: ToolSpecification {
+                name: name.to_string(),
+                description,
+                input_schema: InputSchema { json: schema },
+            },
+        }
+    }
+}
+
 /// CodeWhisperer 图片结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -88,120 +214,322 @@ fn extract_image_format(media_type: &str) -> String {
         .to_string()
 }
 
+/// 转换 Anthropic 图片块，仅支持 base64 来源
+/// Anthropic 格式: { "type": "image", "source": { "type": "base64", "media_type": "image/jpeg", "data": "..." } }
+fn convert_image(block: &Value) -> Option<CWImage> {
+    if block["type"].as_str() != Some("image") {
+        return None;
+    }
+    let source = &block["source"];
+    if source["type"].as_str() != Some("base64") {
+        return None;
+    }
+    let (Some(media_type), Some(data)) = (source["media_type"].as_str(), source["data"].as_str())
+    else {
+        return None;
+    };
+    Some(CWImage {
+        format: extract_image_format(media_type),
+        source: CWImageSource {
+            bytes: data.to_string(),
+        },
+    })
+}
+
 /// 从消息内容中提取图片
 fn extract_images_from_content(content: &Value) -> Vec<CWImage> {
-    let mut images = Vec::new();
-
-    if let Some(arr) = content.as_array() {
-        for item in arr {
-            if item["type"].as_str() == Some("image") {
-                // Anthropic 格式: { "type": "image", "source": { "type": "base64", "media_type": "image/jpeg", "data": "..." } }
-                if let Some(source) = item.get("source") {
-                    if source["type"].as_str() == Some("base64") {
-                        if let (Some(media_type), Some(data)) = (
-                            source["media_type"].as_str(),
-                            source["data"].as_str(),
-                        ) {
-                            images.push(CWImage {
-                                format: extract_image_format(media_type),
-                                source: CWImageSource {
-                                    bytes: data.to_string(),
-                                },
-                            });
-                        }
-                    }
+    content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(convert_image)
+        .collect()
+}
+
+/// 提取消息内容中的文本
+///
+/// 内容可能是字符串，也可能是内容块数组（多个文本块以换行拼接）。
+pub(super) fn extract_text(content: &Value) -> String {
+    if let Some(s) = content.as_str() {
+        return s.to_string();
+    }
+    content
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter(|c| c["type"].as_str() == Some("text"))
+                .filter_map(|c| c["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+/// 转换 `tool_result` 块
+///
+/// 文本内容转为 `{text}`；CodeWhisperer 的工具结果不支持图片，图片放入 `images`
+/// 随所在的用户消息发送。
+fn convert_tool_result(block: &Value, images: &mut Vec<CWImage>) -> ToolResult {
+    let content = &block["content"];
+    let mut texts = Vec::new();
+    match content {
+        Value::String(text) => texts.push(text.clone()),
+        Value::Array(items) => {
+            for item in items {
+                match item["type"].as_str() {
+                    Some("text") => texts.extend(item["text"].as_str().map(String::from)),
+                    Some("image") => images.extend(convert_image(item)),
+                    _ => {}
                 }
             }
         }
+        _ => {}
+    }
+    // 内容为空时保留一个空文本块，CodeWhisperer 要求 content 非空
+    if texts.is_empty() {
+        texts.push(String::new());
+    }
+
+    let is_error = block["is_error"].as_bool().unwrap_or(false);
+    ToolResult {
+        tool_use_id: block["tool_use_id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        content: texts
+            .into_iter()
+            .map(|text| ToolResultContent { text })
+            .collect(),
+        status: if is_error { "error" } else { "success" }.to_string(),
+    }
+}
+
+/// 转换一条用户消息（文本、图片和工具结果）
+fn convert_user_message(message: &Value) -> UserInputMessage {
+    let content = &message["content"];
+    let mut images = extract_images_from_content(content);
+    let tool_results: Vec<ToolResult> = content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|block| block["type"].as_str() == Some("tool_result"))
+        .map(|block| convert_tool_result(block, &mut images))
+        .collect();
+
+    UserInputMessage {
+        content: extract_text(content),
+        model_id: None,
+        user_input_message_context: Some(tool_results)
+            .filter(|results| !results.is_empty())
+            .map(|results| UserInputMessageContext {
+                tools: None,
+                tool_results: Some(results),
+            }),
+        images: Some(images).filter(|imgs| !imgs.is_empty()),
+    }
+}
+
+/// 转换一条助手消息（文本和工具调用），保留原始的工具调用 ID
+fn convert_assistant_message(message: &Value) -> AssistantResponseMessage {
+    let tool_uses: Vec<ToolUse> = message["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|block| block["type"].as_str() == Some("tool_use"))
+        .map(|block| ToolUse {
+            tool_use_id: block["id"].as_str().unwrap_or_default().to_string(),
+            name: block["name"].as_str().unwrap_or_default().to_string(),
+            input: match &block["input"] {
+                Value::Object(_) => block["input"].clone(),
+                _ => json!({}),
+            },
+        })
+        .collect();
+
+    AssistantResponseMessage {
+        content: extract_text(&message["content"]),
+        tool_uses: Some(tool_uses).filter(|uses| !uses.is_empty()),
+    }
+}
+
+/// 将当前消息之前的对话转换为历史消息
+fn convert_history(messages: &[Value]) -> Vec<HistoryMessage> {
+    messages
+        .iter()
+        .filter_map(|m| match m["role"].as_str() {
+            Some("user") => Some(HistoryMessage::UserInputMessage(convert_user_message(m))),
+            Some("assistant") => Some(HistoryMessage::AssistantResponseMessage(
+                convert_assistant_message(m),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 转换 Anthropic 工具定义 `{name, description, input_schema}`，跳过没有名称的工具
+fn convert_tools(tools: &Value) -> Vec<Tool> {
+    tools
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| {
+            let name = tool["name"].as_str().filter(|n| !n.is_empty())?;
+            Some(Tool::new(
+                name,
+                tool["description"].as_str(),
+                tool.get("input_schema"),
+            ))
+        })
+        .collect()
+}
+
+/// 系统提示以历史对话注入时，助手一方的确认回复
+const SYSTEM_PROMPT_ACK: &str = "Understood. I will follow these instructions.";
+
+/// 系统提示的注入方式
+///
+/// CodeWhisperer 没有真正的系统提示字段，只能借助对话内容或 `responseStyle` 传递。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptStrategy {
+    /// 在历史开头插入一轮用户/助手对话
+    #[default]
+    History,
+    /// 拼接到当前用户消息之前
+    Prepend,
+    /// 放入 `responseStyle.systemPromptUserCustomization`
+    ResponseStyle,
+}
+
+/// 提取系统提示
+///
+/// `system` 可以是字符串，也可以是文本块数组（如带 `cache_control` 的块），
+/// 块中除文本外的标记一律忽略。
+fn extract_system_prompt(system: &Value) -> Option<String> {
+    Some(extract_text(system)).filter(|s| !s.trim().is_empty())
+}
+
+/// 按注入方式把系统提示写入请求
+pub(super) fn apply_system_prompt(
+    cw_request: &mut CodeWhispererRequest,
+    system_prompt: String,
+    strategy: SystemPromptStrategy,
+) {
+    match strategy {
+        SystemPromptStrategy::History => {
+            let exchange = [
+                HistoryMessage::UserInputMessage(UserInputMessage {
+                    content: system_prompt,
+                    model_id: None,
+                    user_input_message_context: None,
+                    images: None,
+                }),
+                HistoryMessage::AssistantResponseMessage(AssistantResponseMessage {
+                    content: SYSTEM_PROMPT_ACK.to_string(),
+                    tool_uses: None,
+                }),
+            ];
+            cw_request
+                .conversation_state
+                .history
+                .get_or_insert_with(Vec::new)
+                .splice(0..0, exchange);
+        }
+        SystemPromptStrategy::Prepend => {
+            let message = &mut cw_request
+                .conversation_state
+                .current_message
+                .user_input_message;
+            message.content = if message.content.is_empty() {
+                system_prompt
+            } else {
+                format!("{}\n\n{}", system_prompt, message.content)
+            };
+        }
+        SystemPromptStrategy::ResponseStyle => {
+            cw_request.assistant_response_config.response_style = Some(ResponseStyle {
+                system_prompt_user_customization: Some(system_prompt),
+            });
+        }
+    }
+}
+
+/// 请求未指定 `budget_tokens` 时的思考预算
+const DEFAULT_THINKING_BUDGET: u64 = 10000;
+
+/// 扩展思考的预算，`thinking.type` 为 `enabled` 时返回 `budget_tokens`
+pub fn thinking_budget(request: &Value) -> Option<u64> {
+    let thinking = &request["thinking"];
+    if thinking["type"].as_str() != Some("enabled") {
+        return None;
     }
+    Some(
+        thinking["budget_tokens"]
+            .as_u64()
+            .unwrap_or(DEFAULT_THINKING_BUDGET),
+    )
+}
+
+/// 开启扩展思考的提示标记
+fn thinking_prefix(budget: u64) -> String {
+    format!(
+        "<thinking_mode>enabled</thinking_mode>\n<max_thinking_length>{}</max_thinking_length>\n\n",
+        budget
+    )
+}
 
-    images
+/// 开启扩展思考
+///
+/// CodeWhisperer 没有思考参数，改用 Kiro 客户端的提示标记开启：模型会在回复开头
+/// 输出 `<thinking>` 段落，由 `CwToAnthropicTranslator` 拆分为 thinking 内容块。
+fn apply_thinking(cw_request: &mut CodeWhispererRequest, budget: u64) {
+    let message = &mut cw_request
+        .conversation_state
+        .current_message
+        .user_input_message;
+    message.content = format!("{}{}", thinking_prefix(budget), message.content);
 }
 
 /// 将 Anthropic 请求转换为 CodeWhisperer 格式
 pub fn convert_anthropic_to_codewhisperer(
     request: &Value,
     profile_arn: Option<String>,
-) -> CodeWhispererRequest {
-    // 提取模型并映射
-    let _model = request["model"]
-        .as_str()
-        .map(map_model_name)
-        .unwrap_or_else(|| "claude-sonnet-4.5".to_string());
+) -> Result<CodeWhispererRequest, TranslateError> {
+    // 提取模型并映射，未知模型直接报错
+    let model_id = request_model_id(request)?;
 
     // 提取消息内容
-    let messages = request["messages"].as_array();
-
-    // 获取最后一条用户消息
-    let last_user_message = messages.and_then(|msgs| {
-        msgs.iter()
-            .filter(|m| m["role"].as_str() == Some("user"))
-            .last()
-    });
-
-    // 提取文本内容
-    let content = last_user_message
-        .and_then(|m| {
-            // 处理 content 可能是字符串或数组的情况
-            if let Some(s) = m["content"].as_str() {
-                Some(s.to_string())
-            } else if let Some(arr) = m["content"].as_array() {
-                // 提取文本内容
-                Some(
-                    arr.iter()
-                        .filter_map(|c| {
-                            if c["type"].as_str() == Some("text") {
-                                c["text"].as_str().map(String::from)
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                        .join("\n"),
-                )
-            } else {
-                None
-            }
-        })
+    let messages: &[Value] = request["messages"]
+        .as_array()
+        .map(Vec::as_slice)
         .unwrap_or_default();
 
-    // 提取图片
-    let images = last_user_message
-        .map(|m| extract_images_from_content(&m["content"]))
-        .filter(|imgs| !imgs.is_empty());
-
-    // 提取系统提示
-    let system_prompt = request["system"].as_str().map(String::from);
+    // 最后一条用户消息作为当前消息，之前的对话作为历史
+    let current_index = messages
+        .iter()
+        .rposition(|m| m["role"].as_str() == Some("user"));
+    let mut user_input_message = current_index
+        .map(|i| convert_user_message(&messages[i]))
+        .unwrap_or(UserInputMessage {
+            content: String::new(),
+            model_id: None,
+            user_input_message_context: None,
+            images: None,
+        });
+    user_input_message.model_id = Some(model_id);
+    // 工具定义随当前消息发送
+    user_input_message.attach_tools(convert_tools(&request["tools"]));
+    let history = current_index
+        .map(|i| convert_history(&messages[..i]))
+        .filter(|h| !h.is_empty());
 
     // 提取参数
     let max_tokens = request["max_tokens"].as_u64().map(|v| v as u32);
     let temperature = request["temperature"].as_f64().map(|v| v as f32);
 
-    // 构建历史消息
-    let history = if let Some(msgs) = messages {
-        if msgs.len() > 1 {
-            Some(
-                msgs.iter()
-                    .take(msgs.len() - 1)
-                    .cloned()
-                    .collect::<Vec<_>>(),
-            )
-        } else {
-            None
-        }
-    } else {
-        None
-    };
-
-    CodeWhispererRequest {
+    let mut cw_request = CodeWhispererRequest {
         conversation_state: ConversationState {
-            current_message: CurrentMessage {
-                user_input_message: UserInputMessage {
-                    content,
-                    user_input_message_context: None,
-                    images,
-                },
-            },
+            current_message: CurrentMessage { user_input_message },
             chat_trigger_type: "MANUAL".to_string(),
             user_intent: "CHAT".to_string(),
             customization_arn: None,
@@ -212,17 +540,44 @@ pub fn convert_anthropic_to_codewhisperer(
         assistant_response_config: AssistantResponseConfig {
             max_output_tokens: max_tokens,
             temperature,
-            response_style: system_prompt.map(|s| ResponseStyle {
-                system_prompt_user_customization: Some(s),
-            }),
+            response_style: None,
         },
-        tools: request["tools"].as_array().cloned(),
+        context: None,
+        normalization: NormalizeReport::default(),
+    };
+
+    // 当前消息之后的助手消息是预填充
+    let prefill = messages[current_index.map_or(0, |i| i + 1)..]
+        .iter()
+        .filter(|m| m["role"].as_str() == Some("assistant"))
+        .map(|m| extract_text(&m["content"]))
+        .collect::<Vec<_>>()
+        .join("\n");
+    cw_request.normalization = normalize(&mut cw_request.conversation_state, Some(prefill));
+
+    // 系统提示和思考标记在裁剪之后写入，不会被裁掉，只计入预算
+    let system_prompt = extract_system_prompt(&request["system"]);
+    let thinking = thinking_budget(request);
+    let reserved = system_prompt.as_deref().map_or(0, context::estimate_text)
+        + thinking.map_or(0, |budget| context::estimate_text(&thinking_prefix(budget)));
+    let model = request["model"].as_str().unwrap_or_default();
+    context::apply_budget(&mut cw_request, model, reserved);
+
+    if let Some(system_prompt) = system_prompt {
+        let strategy = config::current().settings.translation.system_prompt;
+        apply_system_prompt(&mut cw_request, system_prompt, strategy);
     }
+    // 在系统提示之后处理，保证思考标记位于当前消息开头
+    if let Some(budget) = thinking {
+        apply_thinking(&mut cw_request, budget);
+    }
+    Ok(cw_request)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::translator::normalize::Rewrite;
 
     #[test]
     fn test_convert_simple_request() {
@@ -234,7 +589,7 @@ mod tests {
             ]
         });
 
-        let result = convert_anthropic_to_codewhisperer(&request, None);
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
 
         assert_eq!(
             result.conversation_state.current_message.user_input_message.content,
@@ -243,6 +598,29 @@ mod tests {
         assert_eq!(result.assistant_response_config.max_output_tokens, Some(1024));
     }
 
+    #[test]
+    fn test_model_id_on_current_message() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "messages": [{"role": "user", "content": "Hi"}]
+        });
+        let json = serde_json::to_value(convert_anthropic_to_codewhisperer(&request, None).unwrap())
+            .unwrap();
+        assert_eq!(
+            json["conversationState"]["currentMessage"]["userInputMessage"]["modelId"],
+            "CLAUDE_SONNET_4_5_20250514_V1_0"
+        );
+
+        let unknown = serde_json::json!({
+            "model": "claude-instant-1",
+            "messages": [{"role": "user", "content": "Hi"}]
+        });
+        assert_eq!(
+            convert_anthropic_to_codewhisperer(&unknown, None).unwrap_err(),
+            TranslateError::UnknownModel("claude-instant-1".to_string())
+        );
+    }
+
     #[test]
     fn test_convert_with_system_prompt() {
         let request = serde_json::json!({
@@ -253,10 +631,21 @@ mod tests {
             ]
         });
 
-        let result = convert_anthropic_to_codewhisperer(&request, Some("arn:aws:iam::123".to_string()));
+        let result = convert_anthropic_to_codewhisperer(&request, Some("arn:aws:iam::123".to_string())).unwrap();
 
         assert!(result.profile_arn.is_some());
-        assert!(result.assistant_response_config.response_style.is_some());
+        // 默认以历史开头的一轮对话注入
+        let history = result.conversation_state.history.unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(matches!(
+            &history[0],
+            HistoryMessage::UserInputMessage(m) if m.content == "You are a helpful assistant."
+        ));
+        assert!(matches!(
+            &history[1],
+            HistoryMessage::AssistantResponseMessage(_)
+        ));
+        assert!(result.assistant_response_config.response_style.is_none());
     }
 
     #[test]
@@ -282,7 +671,7 @@ mod tests {
             ]
         });
 
-        let result = convert_anthropic_to_codewhisperer(&request, None);
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
 
         assert_eq!(
             result.conversation_state.current_message.user_input_message.content,
@@ -328,7 +717,7 @@ mod tests {
             ]
         });
 
-        let result = convert_anthropic_to_codewhisperer(&request, None);
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
 
         let images = result.conversation_state.current_message.user_input_message.images;
         assert!(images.is_some());
@@ -337,4 +726,422 @@ mod tests {
         assert_eq!(images[0].format, "png");
         assert_eq!(images[1].format, "jpeg");
     }
+
+    /// 构造多轮对话：偶数轮使用字符串内容，奇数轮使用内容块数组，每 3 轮附带一张图片
+    fn long_conversation(turns: usize) -> Vec<Value> {
+        let mut messages = Vec::new();
+        for turn in 0..turns {
+            let question = format!("question {}", turn);
+            let answer = format!("answer {}", turn);
+            let user_content = if turn % 3 == 0 {
+                serde_json::json!([
+                    {"type": "text", "text": question},
+                    {
+                        "type": "image",
+                        "source": {"type": "base64", "media_type": "image/png", "data": format!("aW1n{}", turn)}
+                    }
+                ])
+            } else if turn % 2 == 0 {
+                serde_json::json!(question)
+            } else {
+                serde_json::json!([{"type": "text", "text": question}])
+            };
+            let assistant_content = if turn % 2 == 0 {
+                serde_json::json!(answer)
+            } else {
+                serde_json::json!([{"type": "text", "text": answer}])
+            };
+            messages.push(serde_json::json!({"role": "user", "content": user_content}));
+            messages.push(serde_json::json!({"role": "assistant", "content": assistant_content}));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_history_round_trip_long_conversation() {
+        let turns = 12;
+        let mut messages = long_conversation(turns);
+        messages.push(serde_json::json!({"role": "user", "content": "final question"}));
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "messages": messages,
+        });
+
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        let json = serde_json::to_value(&result).unwrap();
+
+        // 序列化后的历史按 userInputMessage / assistantResponseMessage 交替
+        let entries = json["conversationState"]["history"].as_array().unwrap();
+        assert_eq!(entries.len(), turns * 2);
+        for (i, entry) in entries.iter().enumerate() {
+            let key = if i % 2 == 0 {
+                "userInputMessage"
+            } else {
+                "assistantResponseMessage"
+            };
+            let entry = entry.as_object().unwrap();
+            assert_eq!(entry.len(), 1);
+            assert!(entry.contains_key(key), "第 {} 条应为 {}", i, key);
+        }
+
+        let parsed: CodeWhispererRequest = serde_json::from_value(json).unwrap();
+        let history = parsed.conversation_state.history.unwrap();
+        for (turn, pair) in history.chunks(2).enumerate() {
+            match pair {
+                [HistoryMessage::UserInputMessage(user), HistoryMessage::AssistantResponseMessage(assistant)] =>
+                {
+                    assert_eq!(user.content, format!("question {}", turn));
+                    assert_eq!(assistant.content, format!("answer {}", turn));
+                    match &user.images {
+                        Some(images) => {
+                            assert_eq!(turn % 3, 0);
+                            assert_eq!(images[0].format, "png");
+                            assert_eq!(images[0].source.bytes, format!("aW1n{}", turn));
+                        }
+                        None => assert_ne!(turn % 3, 0),
+                    }
+                }
+                other => panic!("第 {} 轮历史格式错误: {:?}", turn, other),
+            }
+        }
+        assert_eq!(
+            parsed.conversation_state.current_message.user_input_message.content,
+            "final question"
+        );
+    }
+
+    #[test]
+    fn test_single_message_has_no_history() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "messages": [{"role": "user", "content": "Hi"}]
+        });
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        let json = serde_json::to_value(result).unwrap();
+        assert!(json["conversationState"].get("history").is_none());
+    }
+
+    #[test]
+    fn test_convert_tools_to_tool_specification() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "messages": [{"role": "user", "content": "What's the weather?"}],
+            "tools": [
+                {
+                    "name": "get_weather",
+                    "description": "Get the weather for a city",
+                    "input_schema": {
+                        "type": "object",
+                        "properties": {"city": {"type": "string"}},
+                        "required": ["city"]
+                    }
+                },
+                {"name": "list_files", "input_schema": {}},
+                {"name": "ping", "description": "  ", "input_schema": {"properties": {}}},
+                {"description": "no name"}
+            ]
+        });
+
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json.get("tools").is_none());
+
+        let tools = &json["conversationState"]["currentMessage"]["userInputMessage"]
+            ["userInputMessageContext"]["tools"];
+        assert_eq!(tools.as_array().unwrap().len(), 3);
+
+        let weather = &tools[0]["toolSpecification"];
+        assert_eq!(weather["name"], "get_weather");
+        assert_eq!(weather["description"], "Get the weather for a city");
+        assert_eq!(weather["inputSchema"]["json"]["required"][0], "city");
+
+        // 缺失的描述使用工具名，空 schema 补为不带参数的 object
+        let list_files = &tools[1]["toolSpecification"];
+        assert_eq!(list_files["description"], "list_files");
+        assert_eq!(
+            list_files["inputSchema"]["json"],
+            serde_json::json!({"type": "object", "properties": {}})
+        );
+
+        let ping = &tools[2]["toolSpecification"];
+        assert_eq!(ping["description"], "ping");
+        assert_eq!(ping["inputSchema"]["json"]["type"], "object");
+    }
+
+    #[test]
+    fn test_no_tools_no_context() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "tools": []
+        });
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        assert!(result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .user_input_message_context
+            .is_none());
+    }
+
+    #[test]
+    fn test_tool_loop_round_trip() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris and a chart?"},
+                {
+                    "role": "assistant",
+                    "content": [
+                        {"type": "text", "text": "Let me check."},
+                        {"type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": {"city": "Paris"}},
+                        {"type": "tool_use", "id": "toolu_02", "name": "render_chart", "input": {}}
+                    ]
+                },
+                {
+                    "role": "user",
+                    "content": [
+                        {"type": "tool_result", "tool_use_id": "toolu_01", "content": "18°C, sunny"},
+                        {
+                            "type": "tool_result",
+                            "tool_use_id": "toolu_02",
+                            "is_error": true,
+                            "content": [
+                                {"type": "text", "text": "renderer crashed"},
+                                {
+                                    "type": "image",
+                                    "source": {"type": "base64", "media_type": "image/png", "data": "Y3Jhc2g="}
+                                }
+                            ]
+                        }
+                    ]
+                }
+            ]
+        });
+
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        let json = serde_json::to_value(&result).unwrap();
+
+        let assistant = &json["conversationState"]["history"][1]["assistantResponseMessage"];
+        assert_eq!(assistant["content"], "Let me check.");
+        assert_eq!(assistant["toolUses"][0]["toolUseId"], "toolu_01");
+        assert_eq!(assistant["toolUses"][0]["name"], "get_weather");
+        assert_eq!(assistant["toolUses"][0]["input"]["city"], "Paris");
+        assert_eq!(assistant["toolUses"][1]["toolUseId"], "toolu_02");
+
+        let current = &json["conversationState"]["currentMessage"]["userInputMessage"];
+        let results = &current["userInputMessageContext"]["toolResults"];
+        assert_eq!(results[0]["toolUseId"], "toolu_01");
+        assert_eq!(results[0]["status"], "success");
+        assert_eq!(results[0]["content"][0]["text"], "18°C, sunny");
+        assert_eq!(results[1]["toolUseId"], "toolu_02");
+        assert_eq!(results[1]["status"], "error");
+        assert_eq!(results[1]["content"][0]["text"], "renderer crashed");
+        // 工具结果中的图片随用户消息发送
+        assert_eq!(current["images"][0]["source"]["bytes"], "Y3Jhc2g=");
+
+        let parsed: CodeWhispererRequest = serde_json::from_value(json).unwrap();
+        assert!(matches!(
+            &parsed.conversation_state.history.unwrap()[1],
+            HistoryMessage::AssistantResponseMessage(m) if m.tool_uses.as_ref().unwrap().len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_tool_results_in_history() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "tools": [{"name": "get_weather", "input_schema": {"type": "object"}}],
+            "messages": [
+                {"role": "user", "content": "Weather?"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_01", "content": []}]},
+                {"role": "assistant", "content": "It's sunny."},
+                {"role": "user", "content": "Thanks!"}
+            ]
+        });
+
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        let json = serde_json::to_value(result).unwrap();
+        let history = json["conversationState"]["history"].as_array().unwrap();
+        assert_eq!(history.len(), 4);
+
+        let results = &history[2]["userInputMessage"]["userInputMessageContext"]["toolResults"];
+        assert_eq!(results[0]["toolUseId"], "toolu_01");
+        assert_eq!(results[0]["content"], serde_json::json!([{"text": ""}]));
+
+        // 工具定义只随当前消息发送，与工具结果互不覆盖
+        let context = &json["conversationState"]["currentMessage"]["userInputMessage"]
+            ["userInputMessageContext"];
+        assert_eq!(
+            context["tools"][0]["toolSpecification"]["name"],
+            "get_weather"
+        );
+        assert!(context.get("toolResults").is_none());
+    }
+
+    #[test]
+    fn test_system_prompt_blocks_with_cache_control() {
+        let system = serde_json::json!([
+            {"type": "text", "text": "You are Claude Code."},
+            {"type": "text", "text": "Follow the repo conventions.", "cache_control": {"type": "ephemeral"}}
+        ]);
+        assert_eq!(
+            extract_system_prompt(&system).as_deref(),
+            Some("You are Claude Code.\nFollow the repo conventions.")
+        );
+        assert_eq!(extract_system_prompt(&serde_json::json!([])), None);
+        assert_eq!(extract_system_prompt(&Value::Null), None);
+    }
+
+    #[test]
+    fn test_system_prompt_strategies() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5-20250514",
+            "messages": [
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello!"},
+                {"role": "user", "content": "Bye"}
+            ]
+        });
+        let convert = |strategy| {
+            let mut result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+            apply_system_prompt(&mut result, "Be brief.".to_string(), strategy);
+            result
+        };
+
+        let history = convert(SystemPromptStrategy::History)
+            .conversation_state
+            .history
+            .unwrap();
+        assert_eq!(history.len(), 4);
+        assert!(
+            matches!(&history[0], HistoryMessage::UserInputMessage(m) if m.content == "Be brief.")
+        );
+        assert!(matches!(
+            &history[1],
+            HistoryMessage::AssistantResponseMessage(m) if m.content == SYSTEM_PROMPT_ACK
+        ));
+        assert!(matches!(&history[2], HistoryMessage::UserInputMessage(m) if m.content == "Hi"));
+
+        let prepended = convert(SystemPromptStrategy::Prepend);
+        assert_eq!(
+            prepended.conversation_state.current_message.user_input_message.content,
+            "Be brief.\n\nBye"
+        );
+        assert_eq!(prepended.conversation_state.history.unwrap().len(), 2);
+
+        let response_style = convert(SystemPromptStrategy::ResponseStyle);
+        assert_eq!(
+            response_style
+                .assistant_response_config
+                .response_style
+                .unwrap()
+                .system_prompt_user_customization
+                .as_deref(),
+            Some("Be brief.")
+        );
+    }
+
+    #[test]
+    fn test_thinking_enabled_prefixes_current_message() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 16000,
+            "thinking": {"type": "enabled", "budget_tokens": 8000},
+            "messages": [{"role": "user", "content": "Why is the sky blue?"}]
+        });
+        assert_eq!(thinking_budget(&request), Some(8000));
+
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        // 思考标记在裁剪之后写入，但已计入预算
+        let estimated = result.context.as_ref().unwrap().estimated_tokens;
+        assert!(estimated >= context::estimate_conversation(&result.conversation_state));
+        let content = result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .content;
+        assert!(content.starts_with(
+            "<thinking_mode>enabled</thinking_mode>\n<max_thinking_length>8000</max_thinking_length>"
+        ));
+        assert!(content.ends_with("\n\nWhy is the sky blue?"));
+
+        let disabled = serde_json::json!({"thinking": {"type": "disabled"}});
+        assert_eq!(thinking_budget(&disabled), None);
+        assert_eq!(thinking_budget(&serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn test_history_normalized_before_sending() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "assistant", "content": "I'm ready."},
+                {"role": "user", "content": "First"},
+                {"role": "user", "content": []},
+                {"role": "assistant", "content": ""},
+                {"role": "user", "content": "Give me JSON"},
+                {"role": "assistant", "content": "{\"result\":"}
+            ]
+        });
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+        assert!(result
+            .normalization
+            .rewrites
+            .iter()
+            .any(|r| matches!(r, Rewrite::PrefillConverted { .. })));
+
+        let history = result.conversation_state.history.unwrap();
+        assert_eq!(history.len(), 4);
+        assert!(matches!(&history[0], HistoryMessage::UserInputMessage(_)));
+        assert!(
+            matches!(&history[2], HistoryMessage::UserInputMessage(m) if m.content == "First")
+        );
+        assert!(matches!(
+            &history[3],
+            HistoryMessage::AssistantResponseMessage(m) if !m.content.is_empty()
+        ));
+
+        let content = result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .content;
+        assert!(content.starts_with("Give me JSON\n\n"));
+        assert!(content.ends_with("{\"result\":"));
+    }
+
+    #[test]
+    fn test_history_trimmed_to_context_budget() {
+        // 上下文 200000，输出预留 198000，输入只剩 2000
+        let long = "x".repeat(4000);
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 198000,
+            "system": "Be brief.",
+            "messages": [
+                {"role": "user", "content": long},
+                {"role": "assistant", "content": "one"},
+                {"role": "user", "content": long},
+                {"role": "assistant", "content": "two"},
+                {"role": "user", "content": "Summarize"}
+            ]
+        });
+        let result = convert_anthropic_to_codewhisperer(&request, None).unwrap();
+
+        let context = result.context.unwrap();
+        assert_eq!(context.budget, 2000);
+        assert_eq!(context.dropped_messages, 2);
+        assert!(!context.over_budget);
+        assert!(context.estimated_tokens < context.original_tokens);
+        // 系统提示在裁剪之后写入，仍是历史的第一轮
+        let history = result.conversation_state.history.unwrap();
+        assert!(
+            matches!(&history[0], HistoryMessage::UserInputMessage(m) if m.content == "Be brief.")
+        );
+        assert_eq!(history.len(), 6);
+    }
 }
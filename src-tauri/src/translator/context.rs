@@ -0,0 +1,466 @@
+//! 上下文窗口预算
+//!
+//! 估算 CodeWhisperer 请求的输入 token 数，超出模型上下文（模型目录中的
+//! `context_length` 减去输出预留）时从最早的历史开始裁剪，避免长会话在完整
+//! 往返之后才被上游以校验错误拒绝。工具调用与对应的工具结果总是一起保留或裁剪。
+
+use super::anthropic_to_cw::{
+    AssistantResponseMessage, CodeWhispererRequest, ConversationState, HistoryMessage, Tool,
+    UserInputMessage,
+};
+use super::normalize::{assistant_message, user_message};
+use crate::{config, models};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// 每张图片按 Anthropic 单图上限估算
+const IMAGE_TOKENS: u32 = 1600;
+/// 每条消息的结构开销
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+
+/// 裁剪后替代早期历史的用户消息
+const OMITTED_PLACEHOLDER: &str = "[Earlier conversation omitted to fit the context window]";
+/// 替代早期历史时助手一方的回复
+const OMITTED_ACK: &str = "Understood.";
+
+/// 历史超出上下文时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextOverflow {
+    /// 删除最早的历史，并插入一轮说明已省略的对话
+    #[default]
+    Placeholder,
+    /// 直接删除最早的历史
+    Truncate,
+    /// 只估算不裁剪
+    Off,
+}
+
+/// 预算结果，供宿主提示用户
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ContextReport {
+    /// 处理后请求的输入 token 估算值
+    pub estimated_tokens: u32,
+    /// 处理前的估算值
+    pub original_tokens: u32,
+    /// 可用于输入的 token 数（上下文长度减去输出预留）
+    pub budget: u32,
+    /// 被裁剪的历史消息数
+    pub dropped_messages: usize,
+    /// 裁剪后仍超出预算
+    pub over_budget: bool,
+}
+
+/// 逐段累计的文本 token 估算，用于流式输出
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextEstimate {
+    ascii: u32,
+    other: u32,
+}
+
+impl TextEstimate {
+    pub fn add(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_ascii() {
+                self.ascii += 1;
+            } else {
+                self.other += 1;
+            }
+        }
+    }
+
+    /// ASCII 约 4 字符一个 token，其他字符（如中文）按一字一个
+    pub fn tokens(&self) -> u32 {
+        self.ascii.div_ceil(4) + self.other
+    }
+}
+
+/// 估算文本的 token 数（见 [`TextEstimate`]）
+pub fn estimate_text(text: &str) -> u32 {
+    let mut estimate = TextEstimate::default();
+    estimate.add(text);
+    estimate.tokens()
+}
+
+/// 估算 JSON 值（工具 schema、工具参数）的 token 数
+fn estimate_json<T: Serialize>(value: &T) -> u32 {
+    serde_json::to_string(value)
+        .map(|s| estimate_text(&s))
+        .unwrap_or_default()
+}
+
+/// 估算工具定义的 token 数
+pub fn estimate_tools(tools: &[Tool]) -> u32 {
+    tools.iter().map(estimate_json).sum()
+}
+
+fn estimate_user(message: &UserInputMessage) -> u32 {
+    let mut tokens = MESSAGE_OVERHEAD_TOKENS + estimate_text(&message.content);
+    tokens += message.images.as_ref().map_or(0, |i| i.len() as u32) * IMAGE_TOKENS;
+    if let Some(ref context) = message.user_input_message_context {
+        tokens += context.tools.as_deref().map_or(0, estimate_tools);
+        for result in context.tool_results.iter().flatten() {
+            tokens += MESSAGE_OVERHEAD_TOKENS;
+            tokens += result
+                .content
+                .iter()
+                .map(|c| estimate_text(&c.text))
+                .sum::<u32>();
+        }
+    }
+    tokens
+}
+
+fn estimate_assistant(message: &AssistantResponseMessage) -> u32 {
+    let mut tokens = MESSAGE_OVERHEAD_TOKENS + estimate_text(&message.content);
+    for tool_use in message.tool_uses.iter().flatten() {
+        tokens += MESSAGE_OVERHEAD_TOKENS + estimate_text(&tool_use.name);
+        tokens += estimate_json(&tool_use.input);
+    }
+    tokens
+}
+
+/// 估算一条历史消息的 token 数
+pub fn estimate_message(message: &HistoryMessage) -> u32 {
+    match message {
+        HistoryMessage::UserInputMessage(m) => estimate_user(m),
+        HistoryMessage::AssistantResponseMessage(m) => estimate_assistant(m),
+    }
+}
+
+/// 估算整个对话（当前消息、工具定义和历史）的 token 数
+pub fn estimate_conversation(conversation: &ConversationState) -> u32 {
+    estimate_user(&conversation.current_message.user_input_message)
+        + conversation
+            .history
+            .iter()
+            .flatten()
+            .map(estimate_message)
+            .sum::<u32>()
+}
+
+/// 模型可用于输入的 token 数：上下文长度减去输出预留
+///
+/// 输出预留取请求的 `max_tokens`，未指定时取模型的 `max_output_tokens`；
+/// 模型不在目录中或没有声明上下文长度时返回 None。
+pub fn input_budget(model: &str, max_tokens: Option<u32>) -> Option<u32> {
+    let spec = models::catalog().find(model)?;
+    let context_length = spec.context_length?;
+    let output = max_tokens.or(spec.max_output_tokens).unwrap_or(0);
+    Some(context_length.saturating_sub(output))
+}
+
+/// 按模型的上下文预算处理请求，结果记录在 `cw_request.context`
+///
+/// 处理方式见配置 `translation.context_overflow`。
+pub(super) fn apply_budget(cw_request: &mut CodeWhispererRequest, model: &str, reserved: u32) {
+    let max_tokens = cw_request.assistant_response_config.max_output_tokens;
+    let Some(budget) = input_budget(model, max_tokens) else {
+        return;
+    };
+    let overflow = config::current().settings.translation.context_overflow;
+    let report = fit_to_budget(
+        &mut cw_request.conversation_state,
+        budget,
+        reserved,
+        overflow,
+    );
+    cw_request.context = Some(report);
+}
+
+/// 用户消息是否带有工具结果（其前面的助手消息不能单独裁掉）
+fn has_tool_results(message: &UserInputMessage) -> bool {
+    message
+        .user_input_message_context
+        .as_ref()
+        .and_then(|c| c.tool_results.as_ref())
+        .is_some_and(|r| !r.is_empty())
+}
+
+/// 把对话裁剪到预算以内
+///
+/// `reserved` 为稍后才写入请求的内容（如系统提示）的估算值。只在不带工具结果的
+/// 用户消息之前切分，保证工具调用和工具结果不会被拆开；历史须已规范化
+/// （用户开头、用户/助手交替）。
+pub fn fit_to_budget(
+    conversation: &mut ConversationState,
+    budget: u32,
+    reserved: u32,
+    overflow: ContextOverflow,
+) -> ContextReport {
+    let original_tokens = estimate_conversation(conversation) + reserved;
+    let mut report = ContextReport {
+        estimated_tokens: original_tokens,
+        original_tokens,
+        budget,
+        dropped_messages: 0,
+        over_budget: original_tokens > budget,
+    };
+    if !report.over_budget || overflow == ContextOverflow::Off {
+        return report;
+    }
+    let Some(history) = conversation.history.as_mut() else {
+        return report;
+    };
+
+    let placeholder = overflow == ContextOverflow::Placeholder;
+    let placeholder_tokens = if placeholder {
+        2 * MESSAGE_OVERHEAD_TOKENS
+            + estimate_text(OMITTED_PLACEHOLDER)
+            + estimate_text(OMITTED_ACK)
+    } else {
+        0
+    };
+
+    // 可切分的位置：不带工具结果的用户消息之前；当前消息不带工具结果时也可以清空历史
+    let current_has_results = has_tool_results(&conversation.current_message.user_input_message);
+    let mut cut = None;
+    let mut fitted = false;
+    let mut dropped_tokens = 0;
+    for (index, message) in history.iter().enumerate() {
+        let boundary = index > 0
+            && matches!(message, HistoryMessage::UserInputMessage(m) if !has_tool_results(m));
+        if boundary {
+            cut = Some(index);
+            if original_tokens - dropped_tokens + placeholder_tokens <= budget {
+                fitted = true;
+                break;
+            }
+        }
+        dropped_tokens += estimate_message(message);
+    }
+    if !fitted && !current_has_results {
+        cut = Some(history.len());
+    }
+    let Some(cut) = cut.filter(|&cut| cut > 0) else {
+        warn!(
+            "请求估算 {} tokens，超出上下文预算 {}，历史中没有可裁剪的位置",
+            original_tokens, budget
+        );
+        return report;
+    };
+
+    let removed: Vec<HistoryMessage> = history.drain(..cut).collect();
+    if placeholder {
+        history.splice(
+            0..0,
+            [
+                user_message(OMITTED_PLACEHOLDER),
+                assistant_message(OMITTED_ACK),
+            ],
+        );
+    }
+    if history.is_empty() {
+        conversation.history = None;
+    }
+
+    report.dropped_messages = removed.len();
+    report.estimated_tokens = estimate_conversation(conversation) + reserved;
+    report.over_budget = report.estimated_tokens > budget;
+    if report.over_budget {
+        warn!(
+            "裁剪 {} 条历史后请求估算仍有 {} tokens，超出上下文预算 {}",
+            report.dropped_messages, report.estimated_tokens, budget
+        );
+    } else {
+        debug!(
+            "裁剪 {} 条历史以适应上下文: {} → {} tokens（预算 {}）",
+            report.dropped_messages, original_tokens, report.estimated_tokens, budget
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::anthropic_to_cw::{
+        CWImage, CWImageSource, CurrentMessage, ToolResult, ToolResultContent, ToolUse,
+        UserInputMessageContext,
+    };
+    use serde_json::json;
+
+    fn conversation(history: Vec<HistoryMessage>, current: UserInputMessage) -> ConversationState {
+        ConversationState {
+            current_message: CurrentMessage {
+                user_input_message: current,
+            },
+            chat_trigger_type: "MANUAL".to_string(),
+            user_intent: "CHAT".to_string(),
+            customization_arn: None,
+            history: Some(history).filter(|h| !h.is_empty()),
+        }
+    }
+
+    fn user(content: &str) -> UserInputMessage {
+        UserInputMessage {
+            content: content.to_string(),
+            model_id: None,
+            user_input_message_context: None,
+            images: None,
+        }
+    }
+
+    fn tool_call(id: &str) -> HistoryMessage {
+        HistoryMessage::AssistantResponseMessage(AssistantResponseMessage {
+            content: "Let me check.".to_string(),
+            tool_uses: Some(vec![ToolUse {
+                tool_use_id: id.to_string(),
+                name: "read_file".to_string(),
+                input: json!({"path": "src/main.rs"}),
+            }]),
+        })
+    }
+
+    fn tool_result(id: &str, text: &str) -> UserInputMessage {
+        UserInputMessage {
+            user_input_message_context: Some(UserInputMessageContext {
+                tools: None,
+                tool_results: Some(vec![ToolResult {
+                    tool_use_id: id.to_string(),
+                    content: vec![ToolResultContent {
+                        text: text.to_string(),
+                    }],
+                    status: "success".to_string(),
+                }]),
+            }),
+            ..user("")
+        }
+    }
+
+    fn contents(conversation: &ConversationState) -> Vec<&str> {
+        conversation
+            .history
+            .iter()
+            .flatten()
+            .map(|m| match m {
+                HistoryMessage::UserInputMessage(m) => m.content.as_str(),
+                HistoryMessage::AssistantResponseMessage(m) => m.content.as_str(),
+            })
+            .collect()
+    }
+
+    /// 三轮对话，第二轮是工具调用，每条用户消息约 1000 tokens
+    fn long_session(current: UserInputMessage) -> ConversationState {
+        let long = "x".repeat(4000);
+        conversation(
+            vec![
+                user_message(&format!("first {}", long)),
+                assistant_message("one"),
+                user_message(&format!("second {}", long)),
+                tool_call("t1"),
+                HistoryMessage::UserInputMessage(tool_result("t1", &long)),
+                assistant_message("two"),
+                user_message(&format!("third {}", long)),
+                assistant_message("three"),
+            ],
+            current,
+        )
+    }
+
+    #[test]
+    fn test_estimate_text() {
+        assert_eq!(estimate_text(""), 0);
+        assert_eq!(estimate_text("abcd"), 1);
+        assert_eq!(estimate_text("abcde"), 2);
+        assert_eq!(estimate_text("你好"), 2);
+    }
+
+    #[test]
+    fn test_estimate_images_and_tool_results() {
+        let mut message = user("hi");
+        let plain = estimate_user(&message);
+        message.images = Some(vec![CWImage {
+            format: "png".to_string(),
+            source: CWImageSource {
+                bytes: "a".repeat(100_000),
+            },
+        }]);
+        // 图片按固定值估算，与编码长度无关
+        assert_eq!(estimate_user(&message), plain + IMAGE_TOKENS);
+
+        let result = tool_result("t1", &"x".repeat(400));
+        assert_eq!(estimate_user(&result), 2 * MESSAGE_OVERHEAD_TOKENS + 100);
+    }
+
+    #[test]
+    fn test_within_budget_unchanged() {
+        let mut state = long_session(user("go on"));
+        let tokens = estimate_conversation(&state);
+        let report = fit_to_budget(&mut state, tokens, 0, ContextOverflow::Placeholder);
+        assert_eq!(report.estimated_tokens, tokens);
+        assert_eq!(report.dropped_messages, 0);
+        assert!(!report.over_budget);
+        assert_eq!(contents(&state).len(), 8);
+    }
+
+    #[test]
+    fn test_trim_keeps_tool_pairs_together() {
+        // 预算只够最后一轮：工具调用与工具结果一起被裁掉
+        let mut state = long_session(user("go on"));
+        let report = fit_to_budget(&mut state, 1200, 0, ContextOverflow::Truncate);
+        assert_eq!(report.dropped_messages, 6);
+        assert!(!report.over_budget);
+        assert!(report.estimated_tokens <= 1200);
+        assert!(report.original_tokens > 4000);
+        assert_eq!(contents(&state).len(), 2);
+        assert!(contents(&state)[0].starts_with("third"));
+
+        // 当前消息是工具结果时，不能裁掉发起调用的助手消息
+        let mut state = conversation(
+            vec![user_message(&"x".repeat(4000)), tool_call("t2")],
+            tool_result("t2", "done"),
+        );
+        let report = fit_to_budget(&mut state, 100, 0, ContextOverflow::Truncate);
+        assert_eq!(report.dropped_messages, 0);
+        assert!(report.over_budget);
+        assert_eq!(contents(&state).len(), 2);
+    }
+
+    #[test]
+    fn test_placeholder_replaces_dropped_history() {
+        let mut state = long_session(user("go on"));
+        let report = fit_to_budget(&mut state, 1300, 0, ContextOverflow::Placeholder);
+        assert_eq!(report.dropped_messages, 6);
+        assert!(!report.over_budget);
+        assert_eq!(contents(&state)[..2], [OMITTED_PLACEHOLDER, OMITTED_ACK]);
+        assert!(contents(&state)[2].starts_with("third"));
+
+        // 整段历史都放不下时清空历史，只保留占位的一轮
+        let mut state = long_session(user("go on"));
+        let report = fit_to_budget(&mut state, 50, 0, ContextOverflow::Placeholder);
+        assert_eq!(report.dropped_messages, 8);
+        assert_eq!(contents(&state), [OMITTED_PLACEHOLDER, OMITTED_ACK]);
+
+        let mut state = long_session(user("go on"));
+        fit_to_budget(&mut state, 50, 0, ContextOverflow::Truncate);
+        assert!(state.history.is_none());
+    }
+
+    #[test]
+    fn test_reserved_counts_and_off_only_estimates() {
+        let mut state = long_session(user("go on"));
+        let tokens = estimate_conversation(&state);
+        let report = fit_to_budget(&mut state, tokens, 10, ContextOverflow::Off);
+        assert_eq!(report.estimated_tokens, tokens + 10);
+        assert!(report.over_budget);
+        assert_eq!(report.dropped_messages, 0);
+        assert_eq!(contents(&state).len(), 8);
+    }
+
+    #[test]
+    fn test_input_budget_from_catalog() {
+        let spec = models::catalog().find("claude-sonnet-4-5").unwrap();
+        let context_length = spec.context_length.unwrap();
+        assert_eq!(
+            input_budget("claude-sonnet-4-5", Some(8000)),
+            Some(context_length - 8000)
+        );
+        assert_eq!(
+            input_budget("claude-sonnet-4-5", None),
+            Some(context_length - spec.max_output_tokens.unwrap())
+        );
+        assert_eq!(input_budget("gpt-4o", Some(8000)), None);
+    }
+}
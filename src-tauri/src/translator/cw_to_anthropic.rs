@@ -1,7 +1,10 @@
 //! CodeWhisperer → Anthropic SSE 转换
 
+use super::context::TextEstimate;
+use super::event_stream::CwEvent;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use tracing::warn;
 
 /// AWS Event Stream 事件类型
 #[derive(Debug, Clone)]
@@ -44,6 +47,9 @@ pub enum AnthropicSseEvent {
         usage: Usage,
     },
     MessageStop,
+    Error {
+        error: ErrorData,
+    },
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -53,26 +59,122 @@ pub struct MessageStartData {
     pub type_: String,
     pub role: String,
     pub model: String,
+    pub content: Vec<Value>,
+    pub stop_reason: Option<String>,
+    pub stop_sequence: Option<String>,
+    pub usage: Usage,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ContentBlock {
     #[serde(rename = "type")]
     pub type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<Value>,
+}
+
+impl ContentBlock {
+    /// 空文本块
+    pub fn text() -> Self {
+        Self {
+            type_: "text".to_string(),
+            text: Some(String::new()),
+            thinking: None,
+            signature: None,
+            id: None,
+            name: None,
+            input: None,
+        }
+    }
+
+    /// 空思考块，内容通过 `thinking_delta` 增量下发
+    pub fn thinking() -> Self {
+        Self {
+            type_: "thinking".to_string(),
+            text: None,
+            thinking: Some(String::new()),
+            signature: Some(THINKING_SIGNATURE_PLACEHOLDER.to_string()),
+            id: None,
+            name: None,
+            input: None,
+        }
+    }
+
+    /// 工具调用块，参数通过 `input_json_delta` 增量下发
+    pub fn tool_use(id: &str, name: &str) -> Self {
+        Self {
+            type_: "tool_use".to_string(),
+            text: None,
+            thinking: None,
+            signature: None,
+            id: Some(id.to_string()),
+            name: Some(name.to_string()),
+            input: Some(json!({})),
+        }
+    }
 }
 
+// 变体名与 Anthropic 的 delta 类型（text_delta、input_json_delta 等）一一对应
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Delta {
     TextDelta { text: String },
+    ThinkingDelta { thinking: String },
+    InputJsonDelta { partial_json: String },
     ToolUse { id: String, name: String, input: Value },
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorData {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub message: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct MessageDelta {
     pub stop_reason: Option<String>,
 }
 
+/// 当前打开的内容块
+#[derive(Debug, Clone, PartialEq)]
+enum OpenBlock {
+    Text,
+    Thinking,
+    ToolUse(String),
+}
+
+/// 模型内联输出的思考段落标记
+const THINKING_START: &str = "<thinking>";
+const THINKING_END: &str = "</thinking>";
+
+/// 思考块的占位签名
+///
+/// 上游不提供签名，回放思考块的客户端要求该字段存在。
+pub const THINKING_SIGNATURE_PLACEHOLDER: &str = "kiro-provider-placeholder";
+
+/// 拆分 `<thinking>` 段落时所处的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ThinkingPhase {
+    /// 回复开头，目前只收到空白
+    Leading,
+    /// 开头的 `<thinking>` 段落中
+    Thinking,
+    /// 开头段落已结束（或回复不以思考段落开头），之后全部是正文
+    Done,
+}
+
 /// CodeWhisperer → Anthropic SSE 转换器
 pub struct CwToAnthropicTranslator {
     message_id: String,
@@ -80,6 +182,21 @@ pub struct CwToAnthropicTranslator {
     current_index: u32,
     input_tokens: u32,
     output_tokens: u32,
+    started: bool,
+    open_block: Option<OpenBlock>,
+    has_tool_use: bool,
+    stop_reason: Option<String>,
+    failed: bool,
+    /// 是否把文本中的 `<thinking>` 段落拆分为 thinking 内容块
+    split_thinking: bool,
+    /// 开头 `<thinking>` 段落的拆分进度
+    phase: ThinkingPhase,
+    /// 可能是被截断的标记的文本结尾，等下一段文本到达后再处理
+    pending: String,
+    /// 思考段落刚结束，之后文本开头的换行需要去掉
+    after_thinking: bool,
+    /// 已输出内容（文本、思考和工具调用）的 token 估算
+    output: TextEstimate,
 }
 
 impl CwToAnthropicTranslator {
@@ -91,28 +208,74 @@ impl CwToAnthropicTranslator {
             current_index: 0,
             input_tokens: 0,
             output_tokens: 0,
+            started: false,
+            open_block: None,
+            has_tool_use: false,
+            stop_reason: None,
+            failed: false,
+            split_thinking: false,
+            phase: ThinkingPhase::Leading,
+            pending: String::new(),
+            after_thinking: false,
+            output: TextEstimate::default(),
         }
     }
 
+    /// 设置输入 token 数
+    ///
+    /// 上游不返回 token 用量，由调用方按请求估算（见 `translator::context`）；
+    /// 输出 token 数按已输出的内容估算。
+    pub fn with_input_tokens(mut self, input_tokens: u32) -> Self {
+        self.input_tokens = input_tokens;
+        self
+    }
+
+    /// 拆分文本中的 `<thinking>` 段落（请求开启了扩展思考时使用）
+    pub fn with_thinking(mut self, enabled: bool) -> Self {
+        self.split_thinking = enabled;
+        self
+    }
+
     /// 转换 AWS Event Stream 事件为 Anthropic SSE 事件
     pub fn translate_event(&mut self, event_type: AwsEventType) -> Vec<AnthropicSseEvent> {
         let mut events = Vec::new();
 
         match event_type {
             AwsEventType::MessageStart => {
+                self.started = true;
                 events.push(AnthropicSseEvent::MessageStart {
                     message: MessageStartData {
                         id: self.message_id.clone(),
                         type_: "message".to_string(),
                         role: "assistant".to_string(),
                         model: self.model.clone(),
+                        content: Vec::new(),
+                        stop_reason: None,
+                        stop_sequence: None,
+                        usage: Usage {
+                            input_tokens: self.input_tokens,
+                            output_tokens: 0,
+                        },
                     },
                 });
             }
             AwsEventType::ContentBlockStart { content_type } => {
+                let content_block = if content_type == "text" {
+                    ContentBlock::text()
+                } else {
+                    ContentBlock {
+                        type_: content_type,
+                        text: None,
+                        thinking: None,
+                        signature: None,
+                        id: None,
+                        name: None,
+                        input: None,
+                    }
+                };
                 events.push(AnthropicSseEvent::ContentBlockStart {
                     index: self.current_index,
-                    content_block: ContentBlock { type_: content_type },
+                    content_block,
                 });
             }
             AwsEventType::ContentBlockDelta { delta } => {
@@ -146,6 +309,232 @@ impl CwToAnthropicTranslator {
         events
     }
 
+    /// 转换一个 CodeWhisperer 响应事件
+    ///
+    /// 首个事件前补发 `message_start`，文本与工具调用分别放在独立的内容块中。
+    pub fn translate_cw_event(&mut self, event: CwEvent) -> Vec<AnthropicSseEvent> {
+        let mut events = Vec::new();
+        if self.failed {
+            return events;
+        }
+        if !self.started {
+            events.extend(self.translate_event(AwsEventType::MessageStart));
+        }
+
+        match event {
+            CwEvent::AssistantResponse { content } => {
+                self.output.add(&content);
+                if self.split_thinking {
+                    events.extend(self.split_text(content));
+                } else {
+                    events.extend(self.text_delta(content));
+                }
+            }
+            CwEvent::ToolUse {
+                tool_use_id,
+                name,
+                input,
+                stop,
+            } => {
+                if self.open_block != Some(OpenBlock::ToolUse(tool_use_id.clone())) {
+                    self.output.add(&name);
+                    events.extend(self.flush_pending());
+                    events.extend(self.close_block());
+                    events.push(AnthropicSseEvent::ContentBlockStart {
+                        index: self.current_index,
+                        content_block: ContentBlock::tool_use(&tool_use_id, &name),
+                    });
+                    self.open_block = Some(OpenBlock::ToolUse(tool_use_id));
+                    self.has_tool_use = true;
+                }
+                if !input.is_empty() {
+                    self.output.add(&input);
+                    events.push(AnthropicSseEvent::ContentBlockDelta {
+                        index: self.current_index,
+                        delta: Delta::InputJsonDelta {
+                            partial_json: input,
+                        },
+                    });
+                }
+                if stop {
+                    events.extend(self.close_block());
+                }
+            }
+            CwEvent::Exception {
+                exception_type,
+                message,
+            } => {
+                // 输出超长时上游以异常结束流，对应 Anthropic 的 max_tokens
+                if exception_type == "ContentLengthExceededException" {
+                    self.stop_reason = Some("max_tokens".to_string());
+                } else {
+                    warn!("上游返回异常: {} {}", exception_type, message);
+                    self.failed = true;
+                    events.push(AnthropicSseEvent::Error {
+                        error: ErrorData {
+                            type_: "api_error".to_string(),
+                            message: format!("{}: {}", exception_type, message),
+                        },
+                    });
+                }
+            }
+            CwEvent::Other { .. } => {}
+        }
+
+        events
+    }
+
+    /// 流结束：关闭打开的内容块并发送 `message_delta` / `message_stop`
+    pub fn finish(&mut self) -> Vec<AnthropicSseEvent> {
+        let mut events = Vec::new();
+        if self.failed {
+            return events;
+        }
+        if !self.started {
+            events.extend(self.translate_event(AwsEventType::MessageStart));
+        }
+        events.extend(self.flush_pending());
+        events.extend(self.close_block());
+
+        let stop_reason = self.stop_reason.clone().unwrap_or_else(|| {
+            if self.has_tool_use {
+                "tool_use".to_string()
+            } else {
+                "end_turn".to_string()
+            }
+        });
+        events.extend(self.translate_event(AwsEventType::MessageDelta {
+            stop_reason: Some(stop_reason),
+            usage: Usage {
+                input_tokens: self.input_tokens,
+                output_tokens: self.output.tokens(),
+            },
+        }));
+        events.extend(self.translate_event(AwsEventType::MessageStop));
+        events
+    }
+
+    /// 追加文本，必要时先打开文本块
+    fn text_delta(&mut self, text: String) -> Vec<AnthropicSseEvent> {
+        let mut events = Vec::new();
+        if text.is_empty() {
+            return events;
+        }
+        if self.open_block != Some(OpenBlock::Text) {
+            events.extend(self.close_block());
+            events.push(AnthropicSseEvent::ContentBlockStart {
+                index: self.current_index,
+                content_block: ContentBlock::text(),
+            });
+            self.open_block = Some(OpenBlock::Text);
+        }
+        events.extend(self.translate_event(AwsEventType::ContentBlockDelta { delta: text }));
+        events
+    }
+
+    /// 追加思考内容，必要时先打开思考块
+    fn thinking_delta(&mut self, thinking: String) -> Vec<AnthropicSseEvent> {
+        let mut events = Vec::new();
+        if thinking.is_empty() {
+            return events;
+        }
+        if self.open_block != Some(OpenBlock::Thinking) {
+            events.extend(self.close_block());
+            events.push(AnthropicSseEvent::ContentBlockStart {
+                index: self.current_index,
+                content_block: ContentBlock::thinking(),
+            });
+            self.open_block = Some(OpenBlock::Thinking);
+        }
+        events.push(AnthropicSseEvent::ContentBlockDelta {
+            index: self.current_index,
+            delta: Delta::ThinkingDelta { thinking },
+        });
+        events
+    }
+
+    /// 按当前所处的段落输出一段内容
+    fn segment(&mut self, segment: &str) -> Vec<AnthropicSseEvent> {
+        if self.phase == ThinkingPhase::Thinking {
+            return self.thinking_delta(segment.to_string());
+        }
+        let mut segment = segment;
+        if self.after_thinking {
+            segment = segment.trim_start_matches(['\r', '\n']);
+            if segment.is_empty() {
+                return Vec::new();
+            }
+            self.after_thinking = false;
+        }
+        self.text_delta(segment.to_string())
+    }
+
+    /// 拆分回复开头的 `<thinking>` 段落
+    ///
+    /// 只识别回复开头（前面只有空白）的开始标记，第一个结束标记之后的内容
+    /// 全部按正文输出，正文里字面出现的标记不会被拆分。标记可能被拆在两个
+    /// 事件中，文本结尾可能是标记开头的部分先暂存。
+    fn split_text(&mut self, content: String) -> Vec<AnthropicSseEvent> {
+        let mut text = std::mem::take(&mut self.pending) + &content;
+
+        if self.phase == ThinkingPhase::Leading {
+            let rest = text.trim_start();
+            if rest.starts_with(THINKING_START) {
+                let start = text.len() - rest.len() + THINKING_START.len();
+                text.drain(..start);
+                self.phase = ThinkingPhase::Thinking;
+            } else if THINKING_START.starts_with(rest) {
+                // 只有空白或开始标记的一部分，等待后续文本
+                self.pending = text;
+                return Vec::new();
+            } else {
+                self.phase = ThinkingPhase::Done;
+            }
+        }
+
+        if self.phase == ThinkingPhase::Done {
+            return self.segment(&text);
+        }
+
+        let mut events = Vec::new();
+        if let Some(pos) = text.find(THINKING_END) {
+            events.extend(self.segment(&text[..pos]));
+            self.phase = ThinkingPhase::Done;
+            self.after_thinking = true;
+            events.extend(self.segment(&text[pos + THINKING_END.len()..]));
+            return events;
+        }
+
+        // 标记是 ASCII，匹配到的后缀一定落在字符边界上
+        let partial = (1..THINKING_END.len())
+            .rev()
+            .find(|&len| text.ends_with(&THINKING_END[..len]))
+            .unwrap_or(0);
+        self.pending = text.split_off(text.len() - partial);
+        events.extend(self.segment(&text));
+        events
+    }
+
+    /// 输出暂存的文本（流结束或转入工具调用时，暂存内容不再可能是标记）
+    fn flush_pending(&mut self) -> Vec<AnthropicSseEvent> {
+        if self.phase == ThinkingPhase::Leading {
+            self.phase = ThinkingPhase::Done;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        if pending.is_empty() {
+            return Vec::new();
+        }
+        self.segment(&pending)
+    }
+
+    fn close_block(&mut self) -> Vec<AnthropicSseEvent> {
+        if self.open_block.take().is_some() {
+            self.translate_event(AwsEventType::ContentBlockStop)
+        } else {
+            Vec::new()
+        }
+    }
+
     /// 格式化为 SSE 字符串
     pub fn format_sse(event: &AnthropicSseEvent) -> String {
         let event_type = match event {
@@ -155,6 +544,7 @@ impl CwToAnthropicTranslator {
             AnthropicSseEvent::ContentBlockStop { .. } => "content_block_stop",
             AnthropicSseEvent::MessageDelta { .. } => "message_delta",
             AnthropicSseEvent::MessageStop => "message_stop",
+            AnthropicSseEvent::Error { .. } => "error",
         };
 
         let data = serde_json::to_string(event).unwrap_or_default();
@@ -162,6 +552,72 @@ impl CwToAnthropicTranslator {
     }
 }
 
+/// 将 SSE 事件序列合并为非流式的 Anthropic Message
+///
+/// 流中出现 `error` 事件时返回 Anthropic 错误响应体。
+pub fn collect_message(events: &[AnthropicSseEvent]) -> Value {
+    let mut message = json!({
+        "type": "message",
+        "role": "assistant",
+        "content": [],
+        "stop_reason": null,
+        "stop_sequence": null,
+        "usage": Usage::default(),
+    });
+    let mut content: Vec<Value> = Vec::new();
+    let mut tool_inputs: Vec<String> = Vec::new();
+
+    for event in events {
+        match event {
+            AnthropicSseEvent::MessageStart { message: start } => {
+                message["id"] = json!(start.id);
+                message["model"] = json!(start.model);
+            }
+            AnthropicSseEvent::ContentBlockStart { content_block, .. } => {
+                content.push(serde_json::to_value(content_block).unwrap_or_default());
+                tool_inputs.push(String::new());
+            }
+            AnthropicSseEvent::ContentBlockDelta { delta, .. } => {
+                let (Some(block), Some(input)) = (content.last_mut(), tool_inputs.last_mut())
+                else {
+                    continue;
+                };
+                match delta {
+                    Delta::TextDelta { text } => {
+                        let current = block["text"].as_str().unwrap_or_default();
+                        block["text"] = json!(format!("{}{}", current, text));
+                    }
+                    Delta::ThinkingDelta { thinking } => {
+                        let current = block["thinking"].as_str().unwrap_or_default();
+                        block["thinking"] = json!(format!("{}{}", current, thinking));
+                    }
+                    Delta::InputJsonDelta { partial_json } => input.push_str(partial_json),
+                    Delta::ToolUse { input, .. } => block["input"] = input.clone(),
+                }
+            }
+            AnthropicSseEvent::ContentBlockStop { .. } => {
+                if let (Some(block), Some(input)) = (content.last_mut(), tool_inputs.last()) {
+                    if block["type"] == "tool_use" && !input.is_empty() {
+                        block["input"] = serde_json::from_str(input)
+                            .unwrap_or_else(|_| json!({ "_raw": input }));
+                    }
+                }
+            }
+            AnthropicSseEvent::MessageDelta { delta, usage } => {
+                message["stop_reason"] = json!(delta.stop_reason);
+                message["usage"] = json!(usage);
+            }
+            AnthropicSseEvent::MessageStop => {}
+            AnthropicSseEvent::Error { error } => {
+                return json!({ "type": "error", "error": error });
+            }
+        }
+    }
+
+    message["content"] = Value::Array(content);
+    message
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,4 +653,164 @@ mod tests {
         assert!(sse.starts_with("event: content_block_delta"));
         assert!(sse.contains("Hello"));
     }
+
+    #[test]
+    fn test_translate_cw_events_to_message() {
+        let mut translator =
+            CwToAnthropicTranslator::new("claude-sonnet-4.5").with_input_tokens(42);
+        let mut events = Vec::new();
+        for event in [
+            CwEvent::AssistantResponse {
+                content: "Let me check. ".to_string(),
+            },
+            CwEvent::ToolUse {
+                tool_use_id: "toolu_1".to_string(),
+                name: "get_weather".to_string(),
+                input: "{\"city\":".to_string(),
+                stop: false,
+            },
+            CwEvent::ToolUse {
+                tool_use_id: "toolu_1".to_string(),
+                name: "get_weather".to_string(),
+                input: "\"Paris\"}".to_string(),
+                stop: true,
+            },
+        ] {
+            events.extend(translator.translate_cw_event(event));
+        }
+        events.extend(translator.finish());
+
+        assert!(matches!(events[0], AnthropicSseEvent::MessageStart { .. }));
+        assert!(matches!(events.last(), Some(AnthropicSseEvent::MessageStop)));
+
+        let message = collect_message(&events);
+        assert_eq!(message["model"], "claude-sonnet-4.5");
+        assert_eq!(message["stop_reason"], "tool_use");
+        assert_eq!(message["content"][0]["text"], "Let me check. ");
+        assert_eq!(message["content"][1]["type"], "tool_use");
+        assert_eq!(message["content"][1]["id"], "toolu_1");
+        assert_eq!(message["content"][1]["input"]["city"], "Paris");
+        assert_eq!(message["usage"]["input_tokens"], 42);
+        assert!(message["usage"]["output_tokens"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_translate_cw_exception() {
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4.5");
+        let mut events = translator.translate_cw_event(CwEvent::Exception {
+            exception_type: "ThrottlingException".to_string(),
+            message: "slow down".to_string(),
+        });
+        events.extend(translator.finish());
+
+        assert!(matches!(events.last(), Some(AnthropicSseEvent::Error { .. })));
+        let message = collect_message(&events);
+        assert_eq!(message["type"], "error");
+    }
+
+    fn translate_text(translator: &mut CwToAnthropicTranslator, chunks: &[&str]) -> Value {
+        let mut events = Vec::new();
+        for chunk in chunks {
+            events.extend(translator.translate_cw_event(CwEvent::AssistantResponse {
+                content: chunk.to_string(),
+            }));
+        }
+        events.extend(translator.finish());
+        collect_message(&events)
+    }
+
+    #[test]
+    fn test_split_inline_thinking() {
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4-5").with_thinking(true);
+        // 标记被拆在多个事件中
+        let message = translate_text(
+            &mut translator,
+            &[
+                "<thin",
+                "king>Rayleigh ",
+                "scattering.</thi",
+                "nking>\n\nBecause of ",
+                "scattering.",
+            ],
+        );
+
+        let content = message["content"].as_array().unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content[0]["type"], "thinking");
+        assert_eq!(content[0]["thinking"], "Rayleigh scattering.");
+        assert_eq!(content[1]["type"], "text");
+        assert_eq!(content[1]["text"], "Because of scattering.");
+    }
+
+    #[test]
+    fn test_only_leading_thinking_is_split() {
+        // 正文中字面出现的标记不拆分
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4-5").with_thinking(true);
+        let message = translate_text(
+            &mut translator,
+            &["Wrap it in <thinking>", "...</thinking> tags."],
+        );
+        let content = message["content"].as_array().unwrap();
+        assert_eq!(content.len(), 1);
+        assert_eq!(
+            content[0]["text"],
+            "Wrap it in <thinking>...</thinking> tags."
+        );
+
+        // 第一个结束标记之后的内容都是正文
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4-5").with_thinking(true);
+        let message = translate_text(
+            &mut translator,
+            &[
+                "  <thinking>plan</thinking>\n",
+                "Use <thinking> and </thinking>.",
+            ],
+        );
+        let content = message["content"].as_array().unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content[0]["thinking"], "plan");
+        assert_eq!(content[1]["text"], "Use <thinking> and </thinking>.");
+    }
+
+    #[test]
+    fn test_thinking_block_has_signature() {
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4-5").with_thinking(true);
+        let message = translate_text(&mut translator, &["<thinking>hmm</thinking>ok"]);
+        assert_eq!(
+            message["content"][0]["signature"],
+            THINKING_SIGNATURE_PLACEHOLDER
+        );
+        assert!(message["content"][1].get("signature").is_none());
+    }
+
+    #[test]
+    fn test_thinking_delta_events() {
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4-5").with_thinking(true);
+        let events = translator.translate_cw_event(CwEvent::AssistantResponse {
+            content: "<thinking>hmm".to_string(),
+        });
+        assert!(matches!(
+            &events[1],
+            AnthropicSseEvent::ContentBlockStart { index: 0, content_block }
+                if content_block.type_ == "thinking"
+        ));
+        let sse = CwToAnthropicTranslator::format_sse(&events[2]);
+        assert!(sse.contains(r#""delta":{"type":"thinking_delta","thinking":"hmm"}"#));
+    }
+
+    #[test]
+    fn test_thinking_not_split_when_disabled() {
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4-5");
+        let message = translate_text(&mut translator, &["Use <thinking>", " tags</thinking>"]);
+        assert_eq!(message["content"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            message["content"][0]["text"],
+            "Use <thinking> tags</thinking>"
+        );
+
+        // 开启时结尾未完成的标记前缀按原文输出
+        let mut translator = CwToAnthropicTranslator::new("claude-sonnet-4-5").with_thinking(true);
+        let message = translate_text(&mut translator, &["a < b, a <thin"]);
+        assert_eq!(message["content"][0]["text"], "a < b, a <thin");
+    }
 }
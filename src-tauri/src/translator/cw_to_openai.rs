@@ -0,0 +1,308 @@
+//! CodeWhisperer → OpenAI Chat Completions 转换
+//!
+//! 以 `CwToAnthropicTranslator` 输出的 Anthropic 事件为输入，流式转换为
+//! `chat.completion.chunk`，非流式合并为 `chat.completion`。
+
+use super::cw_to_anthropic::{collect_message, AnthropicSseEvent, Delta, Usage};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// 流式响应的结束标记
+pub const SSE_DONE: &str = "data: [DONE]\n\n";
+
+/// Anthropic stop_reason → OpenAI finish_reason
+pub fn map_finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        _ => "stop",
+    }
+}
+
+fn openai_usage(usage: &Usage) -> Value {
+    json!({
+        "prompt_tokens": usage.input_tokens,
+        "completion_tokens": usage.output_tokens,
+        "total_tokens": usage.input_tokens + usage.output_tokens,
+    })
+}
+
+/// Anthropic 事件 → `chat.completion.chunk` 转换器
+pub struct CwToOpenAiTranslator {
+    id: String,
+    model: String,
+    created: i64,
+    /// 是否在结束前单独发送 usage chunk（`stream_options.include_usage`）
+    include_usage: bool,
+    /// Anthropic 内容块 index → OpenAI tool_calls index
+    tool_calls: HashMap<u32, u32>,
+}
+
+impl CwToOpenAiTranslator {
+    pub fn new(model: &str, include_usage: bool) -> Self {
+        Self {
+            id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+            model: model.to_string(),
+            created: chrono::Utc::now().timestamp(),
+            include_usage,
+            tool_calls: HashMap::new(),
+        }
+    }
+
+    /// 转换一个事件，返回需要发送的 chunk
+    pub fn translate(&mut self, event: &AnthropicSseEvent) -> Vec<Value> {
+        match event {
+            AnthropicSseEvent::MessageStart { .. } => {
+                vec![self.chunk(json!({ "role": "assistant", "content": "" }), None)]
+            }
+            AnthropicSseEvent::ContentBlockStart {
+                index,
+                content_block,
+            } if content_block.type_ == "tool_use" => {
+                let tool_index = self.tool_calls.len() as u32;
+                self.tool_calls.insert(*index, tool_index);
+                vec![self.chunk(
+                    json!({
+                        "tool_calls": [{
+                            "index": tool_index,
+                            "id": content_block.id,
+                            "type": "function",
+                            "function": { "name": content_block.name, "arguments": "" },
+                        }]
+                    }),
+                    None,
+                )]
+            }
+            AnthropicSseEvent::ContentBlockDelta { index, delta } => match delta {
+                Delta::TextDelta { text } => vec![self.chunk(json!({ "content": text }), None)],
+                Delta::InputJsonDelta { partial_json } => {
+                    let Some(tool_index) = self.tool_calls.get(index) else {
+                        return Vec::new();
+                    };
+                    vec![self.chunk(
+                        json!({
+                            "tool_calls": [{
+                                "index": tool_index,
+                                "function": { "arguments": partial_json },
+                            }]
+                        }),
+                        None,
+                    )]
+                }
+                // Chat Completions 没有思考内容的字段
+                Delta::ThinkingDelta { .. } | Delta::ToolUse { .. } => Vec::new(),
+            },
+            AnthropicSseEvent::MessageDelta { delta, usage } => {
+                let finish_reason = map_finish_reason(delta.stop_reason.as_deref().unwrap_or(""));
+                let mut chunks = vec![self.chunk(json!({}), Some(finish_reason))];
+                if self.include_usage {
+                    let mut usage_chunk = self.chunk(json!({}), None);
+                    usage_chunk["choices"] = json!([]);
+                    usage_chunk["usage"] = openai_usage(usage);
+                    chunks.push(usage_chunk);
+                }
+                chunks
+            }
+            AnthropicSseEvent::Error { error } => {
+                vec![json!({ "error": { "message": error.message, "type": error.type_ } })]
+            }
+            AnthropicSseEvent::ContentBlockStart { .. }
+            | AnthropicSseEvent::ContentBlockStop { .. }
+            | AnthropicSseEvent::MessageStop => Vec::new(),
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason,
+            }],
+        })
+    }
+
+    /// 格式化为 SSE 字符串
+    pub fn format_sse(chunk: &Value) -> String {
+        format!("data: {}\n\n", chunk)
+    }
+}
+
+/// 将事件序列合并为非流式的 `chat.completion`
+///
+/// 流中出现 `error` 事件时返回 OpenAI 错误响应体。
+pub fn collect_completion(events: &[AnthropicSseEvent], model: &str) -> Value {
+    let message = collect_message(events);
+    if message["type"] == "error" {
+        return json!({
+            "error": {
+                "message": message["error"]["message"],
+                "type": message["error"]["type"],
+            }
+        });
+    }
+
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in message["content"].as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block["input"].to_string(),
+                },
+            })),
+            _ => {}
+        }
+    }
+
+    let mut response_message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !tool_calls.is_empty() {
+        response_message["tool_calls"] = json!(tool_calls);
+    }
+
+    let usage: Usage = serde_json::from_value(message["usage"].clone()).unwrap_or_default();
+    json!({
+        "id": format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": response_message,
+            "finish_reason": map_finish_reason(message["stop_reason"].as_str().unwrap_or("")),
+        }],
+        "usage": openai_usage(&usage),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::event_stream::CwEvent;
+    use crate::translator::CwToAnthropicTranslator;
+
+    fn anthropic_events(cw_events: Vec<CwEvent>) -> Vec<AnthropicSseEvent> {
+        let mut translator =
+            CwToAnthropicTranslator::new("claude-sonnet-4-5").with_input_tokens(12);
+        let mut events: Vec<_> = cw_events
+            .into_iter()
+            .flat_map(|e| translator.translate_cw_event(e))
+            .collect();
+        events.extend(translator.finish());
+        events
+    }
+
+    fn tool_call_events() -> Vec<AnthropicSseEvent> {
+        anthropic_events(vec![
+            CwEvent::AssistantResponse {
+                content: "Checking".to_string(),
+            },
+            CwEvent::ToolUse {
+                tool_use_id: "tooluse_1".to_string(),
+                name: "get_weather".to_string(),
+                input: "{\"city\":".to_string(),
+                stop: false,
+            },
+            CwEvent::ToolUse {
+                tool_use_id: "tooluse_1".to_string(),
+                name: "get_weather".to_string(),
+                input: "\"Paris\"}".to_string(),
+                stop: true,
+            },
+        ])
+    }
+
+    #[test]
+    fn test_stream_chunks_with_tool_calls() {
+        let mut translator = CwToOpenAiTranslator::new("claude-sonnet-4-5", true);
+        let chunks: Vec<Value> = tool_call_events()
+            .iter()
+            .flat_map(|e| translator.translate(e))
+            .collect();
+
+        assert_eq!(chunks[0]["object"], "chat.completion.chunk");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Checking");
+
+        let start = &chunks[2]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(start["index"], 0);
+        assert_eq!(start["id"], "tooluse_1");
+        assert_eq!(start["function"]["name"], "get_weather");
+        let arguments: String = chunks[3..5]
+            .iter()
+            .map(|c| {
+                c["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(arguments, "{\"city\":\"Paris\"}");
+
+        assert_eq!(chunks[5]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[6]["choices"], json!([]));
+        // 输出 35 个 ASCII 字符（文本、工具名和参数）约 9 个 token
+        assert_eq!(chunks[6]["usage"]["prompt_tokens"], 12);
+        assert_eq!(chunks[6]["usage"]["completion_tokens"], 9);
+        assert_eq!(chunks[6]["usage"]["total_tokens"], 21);
+        assert_eq!(chunks.len(), 7);
+    }
+
+    #[test]
+    fn test_collect_completion() {
+        let completion = collect_completion(&tool_call_events(), "claude-sonnet-4-5");
+        let choice = &completion["choices"][0];
+        assert_eq!(completion["object"], "chat.completion");
+        assert_eq!(choice["message"]["content"], "Checking");
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        let call = &choice["message"]["tool_calls"][0];
+        assert_eq!(call["function"]["name"], "get_weather");
+        let arguments: Value =
+            serde_json::from_str(call["function"]["arguments"].as_str().unwrap()).unwrap();
+        assert_eq!(arguments, json!({"city": "Paris"}));
+        assert_eq!(completion["usage"]["prompt_tokens"], 12);
+        assert_eq!(completion["usage"]["completion_tokens"], 9);
+
+        let text_only = collect_completion(
+            &anthropic_events(vec![CwEvent::AssistantResponse {
+                content: "Hi".to_string(),
+            }]),
+            "claude-sonnet-4-5",
+        );
+        assert_eq!(text_only["choices"][0]["finish_reason"], "stop");
+        assert!(text_only["choices"][0]["message"]
+            .get("tool_calls")
+            .is_none());
+    }
+
+    #[test]
+    fn test_error_event() {
+        let events = anthropic_events(vec![CwEvent::Exception {
+            exception_type: "ThrottlingException".to_string(),
+            message: "slow down".to_string(),
+        }]);
+        let mut translator = CwToOpenAiTranslator::new("claude-sonnet-4-5", false);
+        let chunks: Vec<Value> = events
+            .iter()
+            .flat_map(|e| translator.translate(e))
+            .collect();
+        assert!(chunks.last().unwrap()["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("ThrottlingException"));
+
+        let completion = collect_completion(&events, "claude-sonnet-4-5");
+        assert!(completion["error"]["message"].is_string());
+    }
+}
@@ -0,0 +1,361 @@
+//! AWS Event Stream 解码
+//!
+//! CodeWhisperer 的 `generateAssistantResponse` 以 `application/vnd.amazon.eventstream`
+//! 二进制帧返回。每帧格式：
+//!
+//! ```text
+//! total_len(u32) headers_len(u32) prelude_crc(u32) headers payload message_crc(u32)
+//! ```
+//!
+//! 解码器支持增量输入，既可以处理抓包保存的完整 dump，也可以处理网络流。
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// prelude（两个长度字段 + CRC）长度
+const PRELUDE_LEN: usize = 12;
+/// 帧的最小长度：prelude + message CRC
+const MIN_FRAME_LEN: usize = PRELUDE_LEN + 4;
+/// 单帧长度上限，防止损坏的长度字段导致无限缓冲
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// 一个 Event Stream 帧
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// 字符串类型的头部（`:event-type`、`:message-type` 等），其他类型的头部被跳过
+    pub headers: HashMap<String, String>,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// `:message-type` 头部，通常为 `event`、`exception` 或 `error`
+    pub fn message_type(&self) -> &str {
+        self.headers
+            .get(":message-type")
+            .map(String::as_str)
+            .unwrap_or("event")
+    }
+
+    /// `:event-type` 头部
+    pub fn event_type(&self) -> Option<&str> {
+        self.headers.get(":event-type").map(String::as_str)
+    }
+}
+
+/// 增量 Event Stream 解码器
+#[derive(Debug, Default)]
+pub struct EventStreamDecoder {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加收到的字节
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// 缓冲区中尚未组成完整帧的字节数
+    pub fn remaining(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// 取出下一个完整帧，数据不足时返回 `None`
+    pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+        if self.buffer.len() < PRELUDE_LEN {
+            return Ok(None);
+        }
+
+        let total_len = read_u32(&self.buffer[0..4]) as usize;
+        let headers_len = read_u32(&self.buffer[4..8]) as usize;
+        let prelude_crc = read_u32(&self.buffer[8..12]);
+
+        if crc32fast::hash(&self.buffer[0..8]) != prelude_crc {
+            bail!("Event Stream prelude CRC 校验失败");
+        }
+        if !(MIN_FRAME_LEN..=MAX_FRAME_LEN).contains(&total_len)
+            || headers_len > total_len - MIN_FRAME_LEN
+        {
+            bail!("Event Stream 帧长度无效: total={}, headers={}", total_len, headers_len);
+        }
+        if self.buffer.len() < total_len {
+            return Ok(None);
+        }
+
+        let frame: Vec<u8> = self.buffer.drain(..total_len).collect();
+        let message_crc = read_u32(&frame[total_len - 4..]);
+        if crc32fast::hash(&frame[..total_len - 4]) != message_crc {
+            bail!("Event Stream 消息 CRC 校验失败");
+        }
+
+        let headers = parse_headers(&frame[PRELUDE_LEN..PRELUDE_LEN + headers_len])?;
+        let payload = frame[PRELUDE_LEN + headers_len..total_len - 4].to_vec();
+
+        Ok(Some(Frame { headers, payload }))
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// 解析头部，只保留字符串类型的值
+fn parse_headers(mut data: &[u8]) -> Result<HashMap<String, String>> {
+    let mut headers = HashMap::new();
+
+    while !data.is_empty() {
+        let name_len = data[0] as usize;
+        if data.len() < 1 + name_len + 1 {
+            bail!("Event Stream 头部被截断");
+        }
+        let name = String::from_utf8_lossy(&data[1..1 + name_len]).to_string();
+        let value_type = data[1 + name_len];
+        data = &data[2 + name_len..];
+
+        // 各类型值的长度，参见 AWS Event Stream 编码规范
+        let value_len = match value_type {
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            6 | 7 => {
+                if data.len() < 2 {
+                    bail!("Event Stream 头部被截断");
+                }
+                let len = u16::from_be_bytes([data[0], data[1]]) as usize;
+                data = &data[2..];
+                len
+            }
+            other => bail!("未知的 Event Stream 头部类型: {}", other),
+        };
+        if data.len() < value_len {
+            bail!("Event Stream 头部被截断");
+        }
+        if value_type == 7 {
+            headers.insert(
+                name,
+                String::from_utf8_lossy(&data[..value_len]).to_string(),
+            );
+        }
+        data = &data[value_len..];
+    }
+
+    Ok(headers)
+}
+
+/// CodeWhisperer 响应事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum CwEvent {
+    /// 文本增量
+    AssistantResponse { content: String },
+    /// 工具调用，`input` 为 JSON 字符串片段，`stop` 表示该工具调用结束
+    ToolUse {
+        tool_use_id: String,
+        name: String,
+        input: String,
+        stop: bool,
+    },
+    /// 上游异常，如 `ContentLengthExceededException`
+    Exception { exception_type: String, message: String },
+    /// 其他事件（计费、上下文用量、元数据等）
+    Other { event_type: String, payload: Value },
+}
+
+impl CwEvent {
+    /// 从帧解析事件
+    pub fn from_frame(frame: &Frame) -> Self {
+        let payload: Value = serde_json::from_slice(&frame.payload).unwrap_or(Value::Null);
+
+        match frame.message_type() {
+            "exception" => {
+                return CwEvent::Exception {
+                    exception_type: frame
+                        .headers
+                        .get(":exception-type")
+                        .cloned()
+                        .unwrap_or_default(),
+                    message: payload["message"].as_str().unwrap_or_default().to_string(),
+                };
+            }
+            "error" => {
+                return CwEvent::Exception {
+                    exception_type: frame
+                        .headers
+                        .get(":error-code")
+                        .cloned()
+                        .unwrap_or_default(),
+                    message: frame
+                        .headers
+                        .get(":error-message")
+                        .cloned()
+                        .unwrap_or_default(),
+                };
+            }
+            _ => {}
+        }
+
+        match frame.event_type().unwrap_or_default() {
+            "assistantResponseEvent" => CwEvent::AssistantResponse {
+                content: payload["content"].as_str().unwrap_or_default().to_string(),
+            },
+            "toolUseEvent" => CwEvent::ToolUse {
+                tool_use_id: payload["toolUseId"].as_str().unwrap_or_default().to_string(),
+                name: payload["name"].as_str().unwrap_or_default().to_string(),
+                input: match &payload["input"] {
+                    Value::String(s) => s.clone(),
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                },
+                stop: payload["stop"].as_bool().unwrap_or(false),
+            },
+            other => CwEvent::Other {
+                event_type: other.to_string(),
+                payload,
+            },
+        }
+    }
+}
+
+/// 解码完整的 Event Stream dump
+pub fn decode_all(data: &[u8]) -> Result<Vec<CwEvent>> {
+    let mut decoder = EventStreamDecoder::new();
+    decoder.feed(data);
+
+    let mut events = Vec::new();
+    while let Some(frame) = decoder.next_frame()? {
+        events.push(CwEvent::from_frame(&frame));
+    }
+    if decoder.remaining() > 0 {
+        bail!("Event Stream 末尾有 {} 字节不完整的数据", decoder.remaining());
+    }
+
+    Ok(events)
+}
+
+/// 编码一个只含字符串头部的帧（用于测试和构造样例数据）
+#[cfg(test)]
+pub fn encode_frame(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut header_bytes = Vec::new();
+    for (name, value) in headers {
+        header_bytes.push(name.len() as u8);
+        header_bytes.extend_from_slice(name.as_bytes());
+        header_bytes.push(7);
+        header_bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        header_bytes.extend_from_slice(value.as_bytes());
+    }
+
+    let total_len = (PRELUDE_LEN + header_bytes.len() + payload.len() + 4) as u32;
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&total_len.to_be_bytes());
+    frame.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+    let prelude_crc = crc32fast::hash(&frame);
+    frame.extend_from_slice(&prelude_crc.to_be_bytes());
+    frame.extend_from_slice(&header_bytes);
+    frame.extend_from_slice(payload);
+    let message_crc = crc32fast::hash(&frame);
+    frame.extend_from_slice(&message_crc.to_be_bytes());
+    frame
+}
+
+/// 编码一个 CodeWhisperer 事件帧（测试用）
+#[cfg(test)]
+pub fn encode_event(event_type: &str, payload: &Value) -> Vec<u8> {
+    encode_frame(
+        &[
+            (":event-type", event_type),
+            (":content-type", "application/json"),
+            (":message-type", "event"),
+        ],
+        payload.to_string().as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode_incremental() {
+        let data = [
+            encode_event("assistantResponseEvent", &json!({"content": "Hel"})),
+            encode_event("assistantResponseEvent", &json!({"content": "lo"})),
+        ]
+        .concat();
+
+        let mut decoder = EventStreamDecoder::new();
+        let mut events = Vec::new();
+        // 按字节逐个输入，模拟网络分片
+        for byte in &data {
+            decoder.feed(std::slice::from_ref(byte));
+            while let Some(frame) = decoder.next_frame().unwrap() {
+                events.push(CwEvent::from_frame(&frame));
+            }
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                CwEvent::AssistantResponse {
+                    content: "Hel".to_string()
+                },
+                CwEvent::AssistantResponse {
+                    content: "lo".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_tool_use_and_exception() {
+        let data = [
+            encode_event(
+                "toolUseEvent",
+                &json!({"toolUseId": "t1", "name": "get_weather", "input": "{\"city\":"}),
+            ),
+            encode_frame(
+                &[
+                    (":message-type", "exception"),
+                    (":exception-type", "ContentLengthExceededException"),
+                ],
+                br#"{"message":"too long"}"#,
+            ),
+        ]
+        .concat();
+
+        let events = decode_all(&data).unwrap();
+        assert_eq!(
+            events[0],
+            CwEvent::ToolUse {
+                tool_use_id: "t1".to_string(),
+                name: "get_weather".to_string(),
+                input: "{\"city\":".to_string(),
+                stop: false,
+            }
+        );
+        assert_eq!(
+            events[1],
+            CwEvent::Exception {
+                exception_type: "ContentLengthExceededException".to_string(),
+                message: "too long".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_corruption() {
+        let mut data = encode_event("assistantResponseEvent", &json!({"content": "x"}));
+        let last = data.len() - 5;
+        data[last] ^= 0xff;
+        assert!(decode_all(&data).is_err());
+
+        let truncated = encode_event("assistantResponseEvent", &json!({"content": "x"}));
+        assert!(decode_all(&truncated[..truncated.len() - 1]).is_err());
+    }
+}
@@ -1,37 +1,129 @@
 //! 协议转换模块
 //!
-//! 实现 Anthropic/OpenAI → CodeWhisperer 和 CodeWhisperer → Anthropic SSE 的转换。
+//! 实现 Anthropic/OpenAI → CodeWhisperer 和 CodeWhisperer → Anthropic SSE / OpenAI 的转换。
 
 pub mod anthropic_to_cw;
+pub mod context;
 pub mod cw_to_anthropic;
+pub mod cw_to_openai;
+pub mod event_stream;
+pub mod normalize;
 pub mod openai_to_cw;
 
 pub use anthropic_to_cw::convert_anthropic_to_codewhisperer;
 pub use cw_to_anthropic::CwToAnthropicTranslator;
 pub use openai_to_cw::convert_openai_to_codewhisperer;
 
-/// 模型名称映射
-pub fn map_model_name(model: &str) -> String {
-    let mappings = [
-        ("claude-opus-4-5", "claude-opus-4.5"),
-        ("claude-opus-4-5-20251101", "claude-opus-4.5"),
-        ("claude-haiku-4-5", "claude-haiku-4.5"),
-        ("claude-haiku-4-5-20251001", "claude-haiku-4.5"),
-        ("claude-sonnet-4-5", "CLAUDE_SONNET_4_5_20250929_V1_0"),
-        ("claude-sonnet-4-5-20250929", "CLAUDE_SONNET_4_5_20250929_V1_0"),
-        ("claude-sonnet-4-5-20250514", "CLAUDE_SONNET_4_5_20250514_V1_0"),
-        ("claude-sonnet-4-20250514", "CLAUDE_SONNET_4_20250514_V1_0"),
-        ("claude-3-7-sonnet-20250219", "CLAUDE_3_7_SONNET_20250219_V1_0"),
-        ("claude-3-5-sonnet-20241022", "CLAUDE_3_7_SONNET_20250219_V1_0"),
-    ];
-
-    for (from, to) in mappings {
-        if model.contains(from) {
-            return to.to_string();
+use crate::models;
+use anthropic_to_cw::CodeWhispererRequest;
+use serde_json::Value;
+
+/// 请求体格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestFormat {
+    Anthropic,
+    OpenAi,
+}
+
+impl std::str::FromStr for RequestFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anthropic" => Ok(RequestFormat::Anthropic),
+            "openai" => Ok(RequestFormat::OpenAi),
+            other => Err(format!("未知的请求格式: {}（可选 anthropic、openai）", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for RequestFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestFormat::Anthropic => write!(f, "anthropic"),
+            RequestFormat::OpenAi => write!(f, "openai"),
+        }
+    }
+}
+
+/// 根据请求体特征判断格式，无法区分时按 Anthropic 处理
+///
+/// OpenAI 的系统提示、工具结果和图片都放在 `messages` 中，
+/// Anthropic 则使用顶层 `system` 和 `tool_use` / `tool_result` / `image` 内容块。
+pub fn detect_format(request: &Value) -> RequestFormat {
+    if request.get("system").is_some() {
+        return RequestFormat::Anthropic;
+    }
+
+    let openai_tools = request["tools"]
+        .as_array()
+        .is_some_and(|tools| tools.iter().any(|t| t["type"] == "function"));
+    if openai_tools
+        || request.get("max_completion_tokens").is_some()
+        || request.get("response_format").is_some()
+    {
+        return RequestFormat::OpenAi;
+    }
+
+    for message in request["messages"].as_array().into_iter().flatten() {
+        if matches!(
+            message["role"].as_str(),
+            Some("system" | "developer" | "tool")
+        ) || message.get("tool_calls").is_some()
+        {
+            return RequestFormat::OpenAi;
+        }
+        for block in message["content"].as_array().into_iter().flatten() {
+            match block["type"].as_str() {
+                Some("image_url") => return RequestFormat::OpenAi,
+                Some("image" | "tool_use" | "tool_result") => return RequestFormat::Anthropic,
+                _ => {}
+            }
         }
     }
 
-    model.to_string()
+    RequestFormat::Anthropic
+}
+
+/// 请求转换错误
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TranslateError {
+    #[error("请求缺少 model 字段")]
+    MissingModel,
+    #[error("不支持的模型: {0}（没有对应的 CodeWhisperer 模型）")]
+    UnknownModel(String),
+}
+
+/// 按格式将请求转换为 CodeWhisperer 格式
+pub fn convert_request(
+    request: &Value,
+    format: RequestFormat,
+    profile_arn: Option<String>,
+) -> Result<CodeWhispererRequest, TranslateError> {
+    match format {
+        RequestFormat::Anthropic => convert_anthropic_to_codewhisperer(request, profile_arn),
+        RequestFormat::OpenAi => convert_openai_to_codewhisperer(request, profile_arn),
+    }
+}
+
+/// 模型名称 → CodeWhisperer modelId
+///
+/// 按模型目录中的 ID 和别名完整匹配；没有对应模型时返回 None，
+/// 不回退到服务端默认模型。
+pub fn resolve_model_id(model: &str) -> Option<&'static str> {
+    models::catalog()
+        .find(model)
+        .map(|spec| spec.cw_model_id.as_str())
+}
+
+/// 读取请求中的模型并解析为 CodeWhisperer modelId
+pub(crate) fn request_model_id(request: &Value) -> Result<String, TranslateError> {
+    let model = request["model"]
+        .as_str()
+        .ok_or(TranslateError::MissingModel)?;
+    resolve_model_id(model)
+        .map(String::from)
+        .ok_or_else(|| TranslateError::UnknownModel(model.to_string()))
 }
 
 #[cfg(test)]
@@ -40,11 +132,68 @@ mod tests {
 
     #[test]
     fn test_model_mapping() {
-        assert_eq!(map_model_name("claude-opus-4-5"), "claude-opus-4.5");
+        assert_eq!(resolve_model_id("claude-opus-4-5"), Some("claude-opus-4.5"));
+        assert_eq!(
+            resolve_model_id("claude-sonnet-4-5-20250929"),
+            Some("CLAUDE_SONNET_4_5_20250929_V1_0")
+        );
+        assert_eq!(
+            resolve_model_id("claude-sonnet-4-5-20250514"),
+            Some("CLAUDE_SONNET_4_5_20250514_V1_0")
+        );
+        assert_eq!(
+            resolve_model_id("claude-sonnet-4-20250514"),
+            Some("CLAUDE_SONNET_4_20250514_V1_0")
+        );
+        // 不按子串匹配到相近的模型
+        assert_eq!(resolve_model_id("claude-3-5-sonnet-20241022"), None);
+        assert_eq!(resolve_model_id("unknown-model"), None);
+    }
+
+    #[test]
+    fn test_request_model_id() {
+        let request = serde_json::json!({"model": "claude-haiku-4-5", "messages": []});
+        assert_eq!(request_model_id(&request).unwrap(), "claude-haiku-4.5");
+
+        let unknown = serde_json::json!({"model": "claude-2.1", "messages": []});
         assert_eq!(
-            map_model_name("claude-sonnet-4-5-20250929"),
-            "CLAUDE_SONNET_4_5_20250929_V1_0"
+            request_model_id(&unknown),
+            Err(TranslateError::UnknownModel("claude-2.1".to_string()))
         );
-        assert_eq!(map_model_name("unknown-model"), "unknown-model");
+        assert_eq!(
+            request_model_id(&serde_json::json!({"messages": []})),
+            Err(TranslateError::MissingModel)
+        );
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let anthropic = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "system": "Be brief.",
+            "messages": [{"role": "user", "content": "Hi"}]
+        });
+        assert_eq!(detect_format(&anthropic), RequestFormat::Anthropic);
+
+        let openai = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"}
+            ]
+        });
+        assert_eq!(detect_format(&openai), RequestFormat::OpenAi);
+
+        let openai_image = serde_json::json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AA=="}}
+            ]}]
+        });
+        assert_eq!(detect_format(&openai_image), RequestFormat::OpenAi);
+
+        let ambiguous = serde_json::json!({
+            "messages": [{"role": "user", "content": "Hi"}]
+        });
+        assert_eq!(detect_format(&ambiguous), RequestFormat::Anthropic);
     }
 }
@@ -0,0 +1,492 @@
+//! 历史规范化
+//!
+//! CodeWhisperer 拒绝相邻的同角色消息、空内容、以助手消息开头的历史，也没有
+//! 助手预填充（结尾的助手消息）的概念，而 Anthropic 和 OpenAI 客户端都会产生这些
+//! 情况。两个转换器在写入系统提示之前都经过这里，每处改写都记录在报告中，
+//! 报告随 `transform_request` / `execute` 的结果返回。
+
+use super::anthropic_to_cw::{
+    AssistantResponseMessage, ConversationState, HistoryMessage, UserInputMessage,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+use tracing::debug;
+
+/// 插入的用户占位消息
+const USER_PLACEHOLDER: &str = "Continue.";
+/// 插入的助手占位消息
+const ASSISTANT_PLACEHOLDER: &str = "Understood.";
+/// 替换空内容的占位文本
+const EMPTY_CONTENT_PLACEHOLDER: &str = ".";
+
+/// 一处改写，`index` 为规范化后历史中的位置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rewrite {
+    /// 合并了 `count` 条相邻的同角色消息
+    Merged {
+        role: &'static str,
+        index: usize,
+        count: usize,
+    },
+    /// 插入占位消息以保持用户/助手交替
+    PlaceholderInserted { role: &'static str, index: usize },
+    /// 空内容替换为占位文本，`index` 为 None 时是当前消息
+    EmptyContentFilled {
+        role: &'static str,
+        index: Option<usize>,
+    },
+    /// 结尾的助手预填充改写为当前消息中的续写指令
+    PrefillConverted { length: usize },
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rewrite::Merged { role, index, count } => {
+                write!(
+                    f,
+                    "合并 {} 条相邻的 {} 消息到 history[{}]",
+                    count, role, index
+                )
+            }
+            Rewrite::PlaceholderInserted { role, index } => {
+                write!(f, "在 history[{}] 插入 {} 占位消息", index, role)
+            }
+            Rewrite::EmptyContentFilled {
+                role,
+                index: Some(index),
+            } => write!(
+                f,
+                "history[{}] 的 {} 消息内容为空，使用占位文本",
+                index, role
+            ),
+            Rewrite::EmptyContentFilled { index: None, .. } => {
+                write!(f, "当前消息内容为空，使用占位文本")
+            }
+            Rewrite::PrefillConverted { length } => {
+                write!(f, "结尾的助手预填充（{} 字符）改写为续写指令", length)
+            }
+        }
+    }
+}
+
+/// 规范化报告
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct NormalizeReport {
+    pub rewrites: Vec<Rewrite>,
+}
+
+impl NormalizeReport {
+    pub fn is_empty(&self) -> bool {
+        self.rewrites.is_empty()
+    }
+}
+
+impl fmt::Display for NormalizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rewrites: Vec<String> = self.rewrites.iter().map(ToString::to_string).collect();
+        write!(f, "{}", rewrites.join("; "))
+    }
+}
+
+/// 规范化历史和当前消息
+///
+/// `prefill` 为当前用户消息之后的助手消息文本（Anthropic 的预填充）。
+pub fn normalize(conversation: &mut ConversationState, prefill: Option<String>) -> NormalizeReport {
+    let mut report = NormalizeReport::default();
+    let mut history = conversation.history.take().unwrap_or_default();
+
+    // 首尾的角色不受合并影响，先补齐：历史以用户开头、以助手结尾（当前消息是用户）
+    let leading = matches!(
+        history.first(),
+        Some(HistoryMessage::AssistantResponseMessage(_))
+    );
+    if leading {
+        history.insert(0, user_message(USER_PLACEHOLDER));
+    }
+    let trailing = matches!(history.last(), Some(HistoryMessage::UserInputMessage(_)));
+    if trailing {
+        history.push(assistant_message(ASSISTANT_PLACEHOLDER));
+    }
+
+    let mut merges = NormalizeReport::default();
+    let mut history = merge_adjacent(history, &mut merges);
+    // 占位消息的位置按合并后的历史记录
+    if leading {
+        report.rewrites.push(Rewrite::PlaceholderInserted {
+            role: "user",
+            index: 0,
+        });
+    }
+    if trailing {
+        report.rewrites.push(Rewrite::PlaceholderInserted {
+            role: "assistant",
+            index: history.len() - 1,
+        });
+    }
+    report.rewrites.extend(merges.rewrites);
+
+    for (index, message) in history.iter_mut().enumerate() {
+        let (role, content) = match message {
+            HistoryMessage::UserInputMessage(m) => ("user", &mut m.content),
+            HistoryMessage::AssistantResponseMessage(m) => ("assistant", &mut m.content),
+        };
+        if fill_empty(content) {
+            report.rewrites.push(Rewrite::EmptyContentFilled {
+                role,
+                index: Some(index),
+            });
+        }
+    }
+    conversation.history = Some(history).filter(|h| !h.is_empty());
+
+    let current = &mut conversation.current_message.user_input_message;
+    if let Some(prefill) = prefill.filter(|p| !p.trim().is_empty()) {
+        report.rewrites.push(Rewrite::PrefillConverted {
+            length: prefill.chars().count(),
+        });
+        let instruction = prefill_instruction(&prefill);
+        current.content = if current.content.trim().is_empty() {
+            instruction
+        } else {
+            format!("{}\n\n{}", current.content, instruction)
+        };
+    }
+    if fill_empty(&mut current.content) {
+        report.rewrites.push(Rewrite::EmptyContentFilled {
+            role: "user",
+            index: None,
+        });
+    }
+
+    if !report.is_empty() {
+        debug!("历史规范化: {}", report);
+    }
+    report
+}
+
+/// 预填充改写成的续写指令
+fn prefill_instruction(prefill: &str) -> String {
+    format!(
+        "The following text is the beginning of your reply and has already been sent. \
+         Continue directly from where it leaves off, without repeating it:\n\n{}",
+        prefill
+    )
+}
+
+/// 合并相邻的同角色消息
+fn merge_adjacent(
+    history: Vec<HistoryMessage>,
+    report: &mut NormalizeReport,
+) -> Vec<HistoryMessage> {
+    let mut merged: Vec<HistoryMessage> = Vec::with_capacity(history.len());
+    let mut counts: Vec<usize> = Vec::with_capacity(history.len());
+
+    for message in history {
+        match (merged.last_mut(), message) {
+            (Some(HistoryMessage::UserInputMessage(last)), HistoryMessage::UserInputMessage(m)) => {
+                merge_user(last, m);
+                *counts.last_mut().unwrap() += 1;
+            }
+            (
+                Some(HistoryMessage::AssistantResponseMessage(last)),
+                HistoryMessage::AssistantResponseMessage(m),
+            ) => {
+                merge_assistant(last, m);
+                *counts.last_mut().unwrap() += 1;
+            }
+            (_, message) => {
+                merged.push(message);
+                counts.push(1);
+            }
+        }
+    }
+
+    for (index, (message, count)) in merged.iter().zip(counts).enumerate() {
+        if count > 1 {
+            let role = match message {
+                HistoryMessage::UserInputMessage(_) => "user",
+                HistoryMessage::AssistantResponseMessage(_) => "assistant",
+            };
+            report.rewrites.push(Rewrite::Merged { role, index, count });
+        }
+    }
+    merged
+}
+
+fn merge_user(target: &mut UserInputMessage, message: UserInputMessage) {
+    join_content(&mut target.content, message.content);
+    if let Some(images) = message.images {
+        target.images.get_or_insert_with(Vec::new).extend(images);
+    }
+    if let Some(context) = message.user_input_message_context {
+        let target_context = target
+            .user_input_message_context
+            .get_or_insert_with(Default::default);
+        if let Some(results) = context.tool_results {
+            target_context
+                .tool_results
+                .get_or_insert_with(Vec::new)
+                .extend(results);
+        }
+        if let Some(tools) = context.tools {
+            target_context
+                .tools
+                .get_or_insert_with(Vec::new)
+                .extend(tools);
+        }
+    }
+}
+
+fn merge_assistant(target: &mut AssistantResponseMessage, message: AssistantResponseMessage) {
+    join_content(&mut target.content, message.content);
+    if let Some(tool_uses) = message.tool_uses {
+        target
+            .tool_uses
+            .get_or_insert_with(Vec::new)
+            .extend(tool_uses);
+    }
+}
+
+/// 以空行拼接两段内容，忽略空内容
+fn join_content(target: &mut String, content: String) {
+    if content.trim().is_empty() {
+        return;
+    }
+    if !target.trim().is_empty() {
+        target.push_str("\n\n");
+    } else {
+        target.clear();
+    }
+    target.push_str(&content);
+}
+
+/// 空内容替换为占位文本，返回是否替换
+fn fill_empty(content: &mut String) -> bool {
+    if !content.trim().is_empty() {
+        return false;
+    }
+    *content = EMPTY_CONTENT_PLACEHOLDER.to_string();
+    true
+}
+
+pub(super) fn user_message(content: &str) -> HistoryMessage {
+    HistoryMessage::UserInputMessage(UserInputMessage {
+        content: content.to_string(),
+        model_id: None,
+        user_input_message_context: None,
+        images: None,
+    })
+}
+
+pub(super) fn assistant_message(content: &str) -> HistoryMessage {
+    HistoryMessage::AssistantResponseMessage(AssistantResponseMessage {
+        content: content.to_string(),
+        tool_uses: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::anthropic_to_cw::{
+        CurrentMessage, ToolResult, ToolResultContent, ToolUse, UserInputMessageContext,
+    };
+    use serde_json::json;
+
+    fn conversation(history: Vec<HistoryMessage>, current: &str) -> ConversationState {
+        ConversationState {
+            current_message: CurrentMessage {
+                user_input_message: UserInputMessage {
+                    content: current.to_string(),
+                    model_id: None,
+                    user_input_message_context: None,
+                    images: None,
+                },
+            },
+            chat_trigger_type: "MANUAL".to_string(),
+            user_intent: "CHAT".to_string(),
+            customization_arn: None,
+            history: Some(history).filter(|h| !h.is_empty()),
+        }
+    }
+
+    fn roles(conversation: &ConversationState) -> Vec<&'static str> {
+        conversation
+            .history
+            .iter()
+            .flatten()
+            .map(|m| match m {
+                HistoryMessage::UserInputMessage(_) => "user",
+                HistoryMessage::AssistantResponseMessage(_) => "assistant",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_history_unchanged() {
+        let mut state = conversation(
+            vec![user_message("Hi"), assistant_message("Hello")],
+            "How are you?",
+        );
+        let report = normalize(&mut state, None);
+        assert!(report.is_empty());
+        assert_eq!(roles(&state), ["user", "assistant"]);
+    }
+
+    #[test]
+    fn test_merge_adjacent_same_role() {
+        let tool_result = |id: &str| {
+            HistoryMessage::UserInputMessage(UserInputMessage {
+                content: String::new(),
+                model_id: None,
+                user_input_message_context: Some(UserInputMessageContext {
+                    tools: None,
+                    tool_results: Some(vec![ToolResult {
+                        tool_use_id: id.to_string(),
+                        content: vec![ToolResultContent {
+                            text: "ok".to_string(),
+                        }],
+                        status: "success".to_string(),
+                    }]),
+                }),
+                images: None,
+            })
+        };
+        let tool_use = HistoryMessage::AssistantResponseMessage(AssistantResponseMessage {
+            content: "Running".to_string(),
+            tool_uses: Some(vec![ToolUse {
+                tool_use_id: "t1".to_string(),
+                name: "run".to_string(),
+                input: json!({}),
+            }]),
+        });
+        let mut state = conversation(
+            vec![
+                user_message("First"),
+                user_message("Second"),
+                tool_use,
+                assistant_message("More"),
+                tool_result("t1"),
+                tool_result("t2"),
+                assistant_message("Done"),
+            ],
+            "Thanks",
+        );
+        let report = normalize(&mut state, None);
+
+        assert_eq!(roles(&state), ["user", "assistant", "user", "assistant"]);
+        let history = state.history.unwrap();
+        let HistoryMessage::UserInputMessage(first) = &history[0] else {
+            panic!("expected user message");
+        };
+        assert_eq!(first.content, "First\n\nSecond");
+        let HistoryMessage::AssistantResponseMessage(assistant) = &history[1] else {
+            panic!("expected assistant message");
+        };
+        assert_eq!(assistant.content, "Running\n\nMore");
+        assert_eq!(assistant.tool_uses.as_ref().unwrap().len(), 1);
+        let HistoryMessage::UserInputMessage(results) = &history[2] else {
+            panic!("expected user message");
+        };
+        let context = results.user_input_message_context.as_ref().unwrap();
+        assert_eq!(context.tool_results.as_ref().unwrap().len(), 2);
+        // 只有工具结果的消息没有文本，使用占位文本
+        assert_eq!(results.content, EMPTY_CONTENT_PLACEHOLDER);
+
+        assert_eq!(
+            report.rewrites,
+            [
+                Rewrite::Merged {
+                    role: "user",
+                    index: 0,
+                    count: 2
+                },
+                Rewrite::Merged {
+                    role: "assistant",
+                    index: 1,
+                    count: 2
+                },
+                Rewrite::Merged {
+                    role: "user",
+                    index: 2,
+                    count: 2
+                },
+                Rewrite::EmptyContentFilled {
+                    role: "user",
+                    index: Some(2)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_placeholders_keep_alternation() {
+        let mut state = conversation(
+            vec![assistant_message("I'm ready."), user_message("Hi")],
+            "Hello?",
+        );
+        let report = normalize(&mut state, None);
+
+        assert_eq!(roles(&state), ["user", "assistant", "user", "assistant"]);
+        assert_eq!(
+            report.rewrites,
+            [
+                Rewrite::PlaceholderInserted {
+                    role: "user",
+                    index: 0
+                },
+                Rewrite::PlaceholderInserted {
+                    role: "assistant",
+                    index: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_placeholder_index_after_merge() {
+        let mut state = conversation(vec![user_message("First"), user_message("Second")], "Next");
+        let report = normalize(&mut state, None);
+
+        assert_eq!(roles(&state), ["user", "assistant"]);
+        assert_eq!(
+            report.rewrites,
+            [
+                Rewrite::PlaceholderInserted {
+                    role: "assistant",
+                    index: 1
+                },
+                Rewrite::Merged {
+                    role: "user",
+                    index: 0,
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "在 history[1] 插入 assistant 占位消息; 合并 2 条相邻的 user 消息到 history[0]"
+        );
+    }
+
+    #[test]
+    fn test_empty_current_message_and_prefill() {
+        let mut state = conversation(Vec::new(), "");
+        let report = normalize(&mut state, None);
+        assert_eq!(
+            state.current_message.user_input_message.content,
+            EMPTY_CONTENT_PLACEHOLDER
+        );
+        assert_eq!(report.to_string(), "当前消息内容为空，使用占位文本");
+
+        let mut state = conversation(Vec::new(), "Write a haiku.");
+        let report = normalize(&mut state, Some("Autumn".to_string()));
+        let content = &state.current_message.user_input_message.content;
+        assert!(content.starts_with("Write a haiku.\n\n"));
+        assert!(content.ends_with("\n\nAutumn"));
+        assert_eq!(report.rewrites, [Rewrite::PrefillConverted { length: 6 }]);
+    }
+}
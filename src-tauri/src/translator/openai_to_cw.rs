@@ -1,11 +1,15 @@
 //! OpenAI → CodeWhisperer 转换
 
 use super::anthropic_to_cw::{
-    AssistantResponseConfig, CWImage, CWImageSource, CodeWhispererRequest, ConversationState,
-    CurrentMessage, ResponseStyle, UserInputMessage,
+    apply_system_prompt, extract_text, AssistantResponseConfig, AssistantResponseMessage, CWImage,
+    CWImageSource, CodeWhispererRequest, ConversationState, CurrentMessage, HistoryMessage, Tool,
+    ToolResult, ToolResultContent, ToolUse, UserInputMessage, UserInputMessageContext,
 };
-use super::map_model_name;
-use serde_json::Value;
+use super::context;
+use super::normalize::{normalize, NormalizeReport};
+use super::{request_model_id, TranslateError};
+use crate::config;
+use serde_json::{json, Value};
 
 /// 从 OpenAI 格式的 image_url 中提取图片
 /// OpenAI 格式: { "type": "image_url", "image_url": { "url": "data:image/jpeg;base64,..." } }
@@ -42,65 +46,185 @@ fn extract_images_from_openai_content(content: &Value) -> Vec<CWImage> {
     images
 }
 
+/// 转换一条用户消息（文本和图片）
+fn convert_user_message(message: &Value) -> UserInputMessage {
+    let images = extract_images_from_openai_content(&message["content"]);
+    UserInputMessage {
+        content: extract_text(&message["content"]),
+        model_id: None,
+        user_input_message_context: None,
+        images: Some(images).filter(|imgs| !imgs.is_empty()),
+    }
+}
+
+/// 转换一条助手消息（文本和 `tool_calls`），保留原始的调用 ID
+///
+/// `arguments` 是 JSON 字符串，解析失败或不是对象时按空对象处理。
+fn convert_assistant_message(message: &Value) -> AssistantResponseMessage {
+    let tool_uses: Vec<ToolUse> = message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|call| call["type"].as_str().unwrap_or("function") == "function")
+        .map(|call| {
+            let function = &call["function"];
+            let input = function["arguments"]
+                .as_str()
+                .and_then(|args| serde_json::from_str::<Value>(args).ok())
+                .filter(Value::is_object)
+                .unwrap_or_else(|| json!({}));
+            ToolUse {
+                tool_use_id: call["id"].as_str().unwrap_or_default().to_string(),
+                name: function["name"].as_str().unwrap_or_default().to_string(),
+                input,
+            }
+        })
+        .collect();
+
+    AssistantResponseMessage {
+        content: extract_text(&message["content"]),
+        tool_uses: Some(tool_uses).filter(|uses| !uses.is_empty()),
+    }
+}
+
+/// 转换 `role: tool` 消息
+fn convert_tool_message(message: &Value) -> ToolResult {
+    ToolResult {
+        tool_use_id: message["tool_call_id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        content: vec![ToolResultContent {
+            text: extract_text(&message["content"]),
+        }],
+        status: "success".to_string(),
+    }
+}
+
+/// 按 CodeWhisperer 的用户/助手角色整理对话
+///
+/// 连续的 `role: tool` 消息合并为工具结果，附加到紧随其后的用户消息；后面没有
+/// 用户消息（或紧接着助手消息）时单独成为一条只带工具结果的用户消息。
+fn convert_messages(messages: &[&Value]) -> Vec<HistoryMessage> {
+    let mut converted = Vec::new();
+    let mut pending: Vec<ToolResult> = Vec::new();
+    let with_results = |mut message: UserInputMessage, results: Vec<ToolResult>| {
+        if !results.is_empty() {
+            message.user_input_message_context = Some(UserInputMessageContext {
+                tools: None,
+                tool_results: Some(results),
+            });
+        }
+        HistoryMessage::UserInputMessage(message)
+    };
+    let results_only = || UserInputMessage {
+        content: String::new(),
+        model_id: None,
+        user_input_message_context: None,
+        images: None,
+    };
+
+    for message in messages {
+        match message["role"].as_str() {
+            Some("tool") => pending.push(convert_tool_message(message)),
+            Some("assistant") => {
+                if !pending.is_empty() {
+                    converted.push(with_results(results_only(), std::mem::take(&mut pending)));
+                }
+                converted.push(HistoryMessage::AssistantResponseMessage(
+                    convert_assistant_message(message),
+                ));
+            }
+            _ => converted.push(with_results(
+                convert_user_message(message),
+                std::mem::take(&mut pending),
+            )),
+        }
+    }
+    if !pending.is_empty() {
+        converted.push(with_results(results_only(), pending));
+    }
+    converted
+}
+
+/// 系统消息的角色，`developer` 是新版 OpenAI API 对 `system` 的称呼
+fn is_system_role(message: &Value) -> bool {
+    matches!(message["role"].as_str(), Some("system" | "developer"))
+}
+
+/// 转换 OpenAI 函数工具 `{type: function, function: {name, description, parameters}}`
+fn convert_tools(tools: &Value) -> Vec<Tool> {
+    tools
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|tool| tool["type"].as_str() == Some("function"))
+        .filter_map(|tool| {
+            let function = &tool["function"];
+            let name = function["name"].as_str().filter(|n| !n.is_empty())?;
+            Some(Tool::new(
+                name,
+                function["description"].as_str(),
+                function.get("parameters"),
+            ))
+        })
+        .collect()
+}
+
 /// 将 OpenAI 请求转换为 CodeWhisperer 格式
 pub fn convert_openai_to_codewhisperer(
     request: &Value,
     profile_arn: Option<String>,
-) -> CodeWhispererRequest {
-    // 提取模型并映射
-    let _model = request["model"]
-        .as_str()
-        .map(map_model_name)
-        .unwrap_or_else(|| "claude-sonnet-4.5".to_string());
+) -> Result<CodeWhispererRequest, TranslateError> {
+    // 提取模型并映射，未知模型直接报错
+    let model_id = request_model_id(request)?;
 
     // 提取消息内容
     let messages = request["messages"].as_array();
 
-    // 提取系统提示（OpenAI 格式中系统消息是 messages 数组的一部分）
-    let system_prompt = if let Some(msgs) = messages {
-        msgs.iter()
-            .find(|m| m["role"].as_str() == Some("system"))
-            .and_then(|m| m["content"].as_str())
-            .map(String::from)
-    } else {
-        None
-    };
+    // 提取系统提示（OpenAI 格式中系统消息是 messages 数组的一部分，可能有多条）
+    let system_prompt = messages
+        .into_iter()
+        .flatten()
+        .filter(|m| is_system_role(m))
+        .map(|m| extract_text(&m["content"]))
+        .filter(|s| !s.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
 
-    // 获取最后一条用户消息
-    let last_user_message = messages.and_then(|msgs| {
-        msgs.iter()
-            .filter(|m| m["role"].as_str() == Some("user"))
-            .last()
-    });
-
-    // 提取用户消息内容
-    let content = last_user_message
-        .and_then(|m| {
-            if let Some(s) = m["content"].as_str() {
-                Some(s.to_string())
-            } else if let Some(arr) = m["content"].as_array() {
-                Some(
-                    arr.iter()
-                        .filter_map(|c| {
-                            if c["type"].as_str() == Some("text") {
-                                c["text"].as_str().map(String::from)
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                        .join("\n"),
-                )
-            } else {
-                None
-            }
+    // 最后一条用户消息（含只带工具结果的消息）作为当前消息，之前的对话
+    // （不含系统消息）作为历史，之后的助手消息是预填充
+    let non_system: Vec<&Value> = messages
+        .into_iter()
+        .flatten()
+        .filter(|m| !is_system_role(m))
+        .collect();
+    let mut converted = convert_messages(&non_system);
+    let current_index = converted
+        .iter()
+        .rposition(|m| matches!(m, HistoryMessage::UserInputMessage(_)));
+    let prefill = converted[current_index.map_or(0, |i| i + 1)..]
+        .iter()
+        .filter_map(|m| match m {
+            HistoryMessage::AssistantResponseMessage(m) => Some(m.content.as_str()),
+            HistoryMessage::UserInputMessage(_) => None,
         })
-        .unwrap_or_default();
-
-    // 提取图片
-    let images = last_user_message
-        .map(|m| extract_images_from_openai_content(&m["content"]))
-        .filter(|imgs| !imgs.is_empty());
+        .collect::<Vec<_>>()
+        .join("\n");
+    converted.truncate(current_index.map_or(0, |i| i + 1));
+    let mut user_input_message = match converted.pop() {
+        Some(HistoryMessage::UserInputMessage(message)) => message,
+        _ => UserInputMessage {
+            content: String::new(),
+            model_id: None,
+            user_input_message_context: None,
+            images: None,
+        },
+    };
+    user_input_message.model_id = Some(model_id);
+    // 工具定义随当前消息发送
+    user_input_message.attach_tools(convert_tools(&request["tools"]));
+    let history = Some(converted).filter(|h| !h.is_empty());
 
     // 提取参数
     let max_tokens = request["max_tokens"]
@@ -109,33 +233,9 @@ pub fn convert_openai_to_codewhisperer(
         .map(|v| v as u32);
     let temperature = request["temperature"].as_f64().map(|v| v as f32);
 
-    // 构建历史消息（排除系统消息和最后一条用户消息）
-    let history = if let Some(msgs) = messages {
-        let non_system: Vec<_> = msgs
-            .iter()
-            .filter(|m| m["role"].as_str() != Some("system"))
-            .cloned()
-            .collect();
-
-        let len = non_system.len();
-        if len > 1 {
-            Some(non_system.into_iter().take(len - 1).collect())
-        } else {
-            None
-        }
-    } else {
-        None
-    };
-
-    CodeWhispererRequest {
+    let mut cw_request = CodeWhispererRequest {
         conversation_state: ConversationState {
-            current_message: CurrentMessage {
-                user_input_message: UserInputMessage {
-                    content,
-                    user_input_message_context: None,
-                    images,
-                },
-            },
+            current_message: CurrentMessage { user_input_message },
             chat_trigger_type: "MANUAL".to_string(),
             user_intent: "CHAT".to_string(),
             customization_arn: None,
@@ -146,12 +246,24 @@ pub fn convert_openai_to_codewhisperer(
         assistant_response_config: AssistantResponseConfig {
             max_output_tokens: max_tokens,
             temperature,
-            response_style: system_prompt.map(|s| ResponseStyle {
-                system_prompt_user_customization: Some(s),
-            }),
+            response_style: None,
         },
-        tools: request["tools"].as_array().cloned(),
+        context: None,
+        normalization: NormalizeReport::default(),
+    };
+
+    cw_request.normalization = normalize(&mut cw_request.conversation_state, Some(prefill));
+
+    // 系统提示在裁剪之后写入，不会被裁掉，只计入预算
+    let model = request["model"].as_str().unwrap_or_default();
+    let reserved = context::estimate_text(&system_prompt);
+    context::apply_budget(&mut cw_request, model, reserved);
+
+    if !system_prompt.is_empty() {
+        let strategy = config::current().settings.translation.system_prompt;
+        apply_system_prompt(&mut cw_request, system_prompt, strategy);
     }
+    Ok(cw_request)
 }
 
 #[cfg(test)]
@@ -161,7 +273,7 @@ mod tests {
     #[test]
     fn test_convert_openai_request() {
         let request = serde_json::json!({
-            "model": "gpt-4",
+            "model": "claude-sonnet-4-5",
             "max_tokens": 1024,
             "messages": [
                 {"role": "system", "content": "You are helpful."},
@@ -169,19 +281,23 @@ mod tests {
             ]
         });
 
-        let result = convert_openai_to_codewhisperer(&request, None);
+        let result = convert_openai_to_codewhisperer(&request, None).unwrap();
 
         assert_eq!(
             result.conversation_state.current_message.user_input_message.content,
             "Hello!"
         );
-        assert!(result.assistant_response_config.response_style.is_some());
+        let history = result.conversation_state.history.unwrap();
+        assert!(matches!(
+            &history[0],
+            HistoryMessage::UserInputMessage(m) if m.content == "You are helpful."
+        ));
     }
 
     #[test]
     fn test_convert_openai_with_image() {
         let request = serde_json::json!({
-            "model": "gpt-4-vision-preview",
+            "model": "claude-sonnet-4-5",
             "max_tokens": 1024,
             "messages": [
                 {
@@ -199,7 +315,7 @@ mod tests {
             ]
         });
 
-        let result = convert_openai_to_codewhisperer(&request, None);
+        let result = convert_openai_to_codewhisperer(&request, None).unwrap();
 
         assert_eq!(
             result.conversation_state.current_message.user_input_message.content,
@@ -217,7 +333,7 @@ mod tests {
     #[test]
     fn test_convert_openai_with_multiple_images() {
         let request = serde_json::json!({
-            "model": "gpt-4-vision-preview",
+            "model": "claude-sonnet-4-5",
             "max_tokens": 1024,
             "messages": [
                 {
@@ -241,7 +357,7 @@ mod tests {
             ]
         });
 
-        let result = convert_openai_to_codewhisperer(&request, None);
+        let result = convert_openai_to_codewhisperer(&request, None).unwrap();
 
         let images = result.conversation_state.current_message.user_input_message.images;
         assert!(images.is_some());
@@ -250,4 +366,158 @@ mod tests {
         assert_eq!(images[0].format, "png");
         assert_eq!(images[1].format, "jpeg");
     }
+
+    #[test]
+    fn test_convert_openai_history() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "system", "content": "You are helpful."},
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello! How can I help?"},
+                {"role": "user", "content": [{"type": "text", "text": "Tell me a joke"}]}
+            ]
+        });
+
+        let result = convert_openai_to_codewhisperer(&request, None).unwrap();
+
+        // 系统提示占用历史开头的一轮对话
+        let history = result.conversation_state.history.unwrap();
+        assert_eq!(history.len(), 4);
+        assert!(matches!(&history[2], HistoryMessage::UserInputMessage(m) if m.content == "Hi"));
+        assert!(matches!(
+            &history[3],
+            HistoryMessage::AssistantResponseMessage(m) if m.content == "Hello! How can I help?"
+        ));
+        assert_eq!(
+            result.conversation_state.current_message.user_input_message.content,
+            "Tell me a joke"
+        );
+    }
+
+    #[test]
+    fn test_convert_openai_tools() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{"role": "user", "content": "What's the weather?"}],
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+                }
+            }]
+        });
+
+        let result = convert_openai_to_codewhisperer(&request, None).unwrap();
+
+        let context = result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .user_input_message_context
+            .unwrap();
+        let tools = context.tools.unwrap();
+        assert_eq!(tools.len(), 1);
+        let spec = &tools[0].tool_specification;
+        assert_eq!(spec.name, "get_weather");
+        assert_eq!(spec.description, "get_weather");
+        assert_eq!(spec.input_schema.json["properties"]["city"]["type"], "string");
+    }
+
+    #[test]
+    fn test_tool_loop_round_trip() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "developer", "content": "Answer briefly."},
+                {"role": "user", "content": "Weather in Paris?"},
+                {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                    }]
+                },
+                {"role": "tool", "tool_call_id": "call_1", "content": "22C sunny"}
+            ],
+            "tools": [{
+                "type": "function",
+                "function": {"name": "get_weather", "parameters": {"type": "object"}}
+            }]
+        });
+
+        let result = convert_openai_to_codewhisperer(&request, None).unwrap();
+        let body = serde_json::to_value(&result).unwrap();
+        let state = &body["conversationState"];
+
+        // developer 消息作为系统提示，占用历史开头的一轮
+        let history = state["history"].as_array().unwrap();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[0]["userInputMessage"]["content"], "Answer briefly.");
+        assert_eq!(
+            history[2]["userInputMessage"]["content"],
+            "Weather in Paris?"
+        );
+        let tool_use = &history[3]["assistantResponseMessage"]["toolUses"][0];
+        assert_eq!(tool_use["toolUseId"], "call_1");
+        assert_eq!(tool_use["name"], "get_weather");
+        assert_eq!(tool_use["input"]["city"], "Paris");
+
+        // 末尾的工具结果成为当前消息，工具定义随之发送
+        let context = &state["currentMessage"]["userInputMessage"]["userInputMessageContext"];
+        let tool_result = &context["toolResults"][0];
+        assert_eq!(tool_result["toolUseId"], "call_1");
+        assert_eq!(tool_result["content"][0]["text"], "22C sunny");
+        assert_eq!(tool_result["status"], "success");
+        assert_eq!(
+            context["tools"][0]["toolSpecification"]["name"],
+            "get_weather"
+        );
+    }
+
+    #[test]
+    fn test_tool_results_attach_to_following_user_message() {
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "user", "content": "Check both files"},
+                {
+                    "role": "assistant",
+                    "content": "Reading.",
+                    "tool_calls": [
+                        {"id": "call_a", "type": "function", "function": {"name": "read", "arguments": "{\"path\":\"a\"}"}},
+                        {"id": "call_b", "type": "function", "function": {"name": "read", "arguments": "not json"}}
+                    ]
+                },
+                {"role": "tool", "tool_call_id": "call_a", "content": "A"},
+                {"role": "tool", "tool_call_id": "call_b", "content": [{"type": "text", "text": "B"}]},
+                {"role": "user", "content": "Now compare them"}
+            ]
+        });
+
+        let result = convert_openai_to_codewhisperer(&request, None).unwrap();
+
+        let history = result.conversation_state.history.unwrap();
+        assert_eq!(history.len(), 2);
+        let HistoryMessage::AssistantResponseMessage(ref assistant) = history[1] else {
+            panic!("应为助手消息");
+        };
+        let tool_uses = assistant.tool_uses.as_ref().unwrap();
+        assert_eq!(tool_uses.len(), 2);
+        assert_eq!(tool_uses[1].input, serde_json::json!({}));
+
+        let current = result.conversation_state.current_message.user_input_message;
+        assert_eq!(current.content, "Now compare them");
+        let results = current
+            .user_input_message_context
+            .unwrap()
+            .tool_results
+            .unwrap();
+        let ids: Vec<_> = results.iter().map(|r| r.tool_use_id.as_str()).collect();
+        assert_eq!(ids, ["call_a", "call_b"]);
+        assert_eq!(results[1].content[0].text, "B");
+    }
 }
@@ -0,0 +1,500 @@
+//! CodeWhisperer 上游调用
+//!
+//! 本进程直接转发请求时使用（`serve` 模式和 `execute` 方法）：从凭证池选择凭证、
+//! 必要时刷新 Token、发送 `generateAssistantResponse`，再把返回的 Event Stream 翻译为
+//! Anthropic SSE 事件。凭证的使用结果通过 `release_credential` 记回凭证池，
+//! 可重试的失败在开始转发事件前换下一个凭证重试。
+
+use crate::credentials::KiroCredentials;
+use crate::token_refresh::{is_token_expired, RefreshRejected};
+use crate::translator::anthropic_to_cw::CodeWhispererRequest;
+use crate::translator::context::{estimate_conversation, ContextReport};
+use crate::translator::cw_to_anthropic::{AnthropicSseEvent, ErrorData};
+use crate::translator::event_stream::{CwEvent, EventStreamDecoder};
+use crate::translator::normalize::NormalizeReport;
+use crate::translator::{convert_request, CwToAnthropicTranslator, RequestFormat};
+use crate::{config, provider};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// 建立连接的超时；流式响应可能持续很久，不设整体超时
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// 翻译后事件的缓冲数
+const EVENT_BUFFER: usize = 64;
+/// 错误信息中保留的上游响应体长度
+const MAX_ERROR_BODY: usize = 500;
+/// Token 刷新暂时失败（网络错误、超时等）后凭证的冷却时间
+const REFRESH_FAILURE_COOLDOWN_SECONDS: u64 = 30;
+
+lazy_static::lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .expect("创建 HTTP 客户端失败");
+}
+
+/// 上游调用错误
+#[derive(Debug, thiserror::Error)]
+pub enum UpstreamError {
+    /// 没有可用凭证，或凭证无法刷新
+    #[error("{0}")]
+    Credential(String),
+    /// 上游返回非 2xx 状态码
+    #[error("上游返回 HTTP {status}: {message}")]
+    Status { status: u16, message: String },
+    /// 请求未能送达上游
+    #[error("请求上游失败: {0}")]
+    Network(String),
+    /// 请求无法转换为 CodeWhisperer 格式（如模型没有对应的 modelId）
+    #[error("{0}")]
+    InvalidRequest(String),
+}
+
+impl UpstreamError {
+    /// 换一个凭证重试是否可能成功
+    ///
+    /// 依据 `parse_error` 的 `retryable`；403 是账号本身的问题，对同一凭证不可重试，
+    /// 但换一个账号可能成功。凭证刷新失败和网络错误同样换凭证重试。
+    fn should_failover(&self) -> bool {
+        match self {
+            UpstreamError::Status { status: 403, .. } => true,
+            UpstreamError::Status { status, .. } => {
+                provider::parse_error(*status, "").is_some_and(|e| e.retryable)
+            }
+            UpstreamError::Credential(_) | UpstreamError::Network(_) => true,
+            UpstreamError::InvalidRequest(_) => false,
+        }
+    }
+}
+
+/// 所有尝试都失败时的错误
+#[derive(Debug, thiserror::Error)]
+#[error("{error}")]
+pub struct SendError {
+    /// 最后一次尝试的错误
+    pub error: UpstreamError,
+    /// 依次尝试过的凭证 ID
+    pub tried_credentials: Vec<String>,
+}
+
+/// 上游的成功响应，事件流尚未读取
+pub struct UpstreamResponse {
+    pub credential_id: String,
+    /// 依次尝试过的凭证 ID，最后一个即 `credential_id`
+    pub tried_credentials: Vec<String>,
+    /// 上下文预算结果（见 `translator::context`）
+    pub context: Option<ContextReport>,
+    /// 历史规范化所做的改写（见 `translator::normalize`）
+    pub normalization: NormalizeReport,
+    /// 请求的输入 token 估算，上游不返回用量，用于响应中的 `usage`
+    input_tokens: u32,
+    response: reqwest::Response,
+}
+
+/// 选择凭证并发送请求，可重试的失败换下一个凭证
+///
+/// `model` 用于凭证选择；请求体按 `format` 转换为 CodeWhisperer 格式，
+/// 并带上所选凭证的 profileArn。请求无法转换时不选择凭证，直接返回错误。
+/// 尝试次数和截止时间见配置 `settings.retry`，每次尝试等待响应的时间不超过
+/// 剩余的截止时间。收到 2xx 响应后即返回，事件流开始转发后不再切换凭证。
+pub async fn send(
+    request: &Value,
+    format: RequestFormat,
+    model: &str,
+) -> Result<UpstreamResponse, SendError> {
+    let mut cw_request = convert_request(request, format, None).map_err(|e| SendError {
+        error: UpstreamError::InvalidRequest(e.to_string()),
+        tried_credentials: Vec::new(),
+    })?;
+    let context = cw_request.context.take();
+    let normalization = std::mem::take(&mut cw_request.normalization);
+    let input_tokens = estimate_conversation(&cw_request.conversation_state);
+
+    let retry = &config::current().settings.retry;
+    let deadline = Instant::now() + Duration::from_millis(retry.deadline_ms);
+
+    let mut tried_credentials: Vec<String> = Vec::new();
+    let mut last_error = None;
+    for attempt in 0..retry.max_attempts.max(1) {
+        if attempt > 0 && Instant::now() >= deadline {
+            debug!("已超过重试截止时间，不再换凭证");
+            break;
+        }
+
+        let credential_id = match provider::select_credential(model, &tried_credentials).await {
+            Ok(credential_id) => credential_id,
+            Err(e) => {
+                // 已有失败时保留上游错误，比"没有可用凭证"更有用
+                last_error.get_or_insert(UpstreamError::Credential(e.to_string()));
+                break;
+            }
+        };
+        tried_credentials.push(credential_id.clone());
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let attempt_future = send_once(&cw_request, model, &credential_id);
+        let result = match tokio::time::timeout(remaining, attempt_future).await {
+            Ok(result) => result,
+            Err(_) => {
+                let message = format!("等待上游响应超时（{}ms）", retry.deadline_ms);
+                release(&credential_id, Some(&message), false, None).await;
+                Err(UpstreamError::Network(message))
+            }
+        };
+        match result {
+            Ok(response) => {
+                if attempt > 0 {
+                    info!(
+                        "换凭证重试成功: credential={} tried={:?}",
+                        credential_id, tried_credentials
+                    );
+                }
+                return Ok(UpstreamResponse {
+                    credential_id,
+                    tried_credentials,
+                    context,
+                    normalization,
+                    input_tokens,
+                    response,
+                });
+            }
+            Err(error) => {
+                let failover = error.should_failover();
+                warn!(
+                    "上游请求失败: credential={} {}{}",
+                    credential_id,
+                    error,
+                    if failover { "，换凭证重试" } else { "" }
+                );
+                last_error = Some(error);
+                if !failover {
+                    break;
+                }
+            }
+        }
+    }
+
+    Err(SendError {
+        error: last_error
+            .unwrap_or_else(|| UpstreamError::Credential("没有可用的健康凭证".to_string())),
+        tried_credentials,
+    })
+}
+
+/// 使用指定凭证发送一次请求
+async fn send_once(
+    cw_request: &CodeWhispererRequest,
+    model: &str,
+    credential_id: &str,
+) -> Result<reqwest::Response, UpstreamError> {
+    let credential = prepare_credential(credential_id).await?;
+
+    let mut cw_request = cw_request.clone();
+    cw_request.profile_arn = credential.profile_arn.clone();
+    let headers = provider::build_request_headers(&credential)
+        .map_err(|e| UpstreamError::Credential(e.to_string()))?;
+    let url = format!(
+        "{}/generateAssistantResponse",
+        provider::base_url(&credential)
+    );
+
+    let mut builder = CLIENT.post(&url).json(&cw_request);
+    for (name, value) in &headers {
+        builder = builder.header(name, value);
+    }
+
+    debug!("发送上游请求: credential={} model={}", credential_id, model);
+    let response = match builder.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            release(credential_id, Some(&e.to_string()), false, None).await;
+            return Err(UpstreamError::Network(e.to_string()));
+        }
+    };
+
+    let status = response.status().as_u16();
+    if !response.status().is_success() {
+        let body = response.text().await.unwrap_or_default();
+        let body: String = body.chars().take(MAX_ERROR_BODY).collect();
+        let error = provider::parse_error(status, &body);
+        let cooldown = error.as_ref().and_then(|e| e.cooldown_seconds);
+        let message = match error {
+            Some(error) => format!("{}: {}", error.message, body),
+            None => body,
+        };
+        // 403 说明账号本身不可用，后续请求不再分配该凭证；
+        // 429、5xx 让凭证冷却一段时间，期间其他请求也不会选到它
+        release(credential_id, Some(&message), status == 403, cooldown).await;
+        return Err(UpstreamError::Status { status, message });
+    }
+
+    Ok(response)
+}
+
+impl UpstreamResponse {
+    /// 在后台读取事件流并逐个发出 Anthropic 事件
+    ///
+    /// 流正常结束时以 `message_stop` 收尾，中途失败时以 `error` 事件收尾。
+    /// 接收端被丢弃（客户端断开）时停止读取上游。`thinking` 为请求是否开启了
+    /// 扩展思考，开启时文本中的 `<thinking>` 段落拆分为 thinking 内容块。
+    pub fn into_events(self, model: &str, thinking: bool) -> mpsc::Receiver<AnthropicSseEvent> {
+        let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+        let model = model.to_string();
+
+        tokio::spawn(async move {
+            let UpstreamResponse {
+                credential_id,
+                input_tokens,
+                response,
+                ..
+            } = self;
+            let translator = CwToAnthropicTranslator::new(&model)
+                .with_thinking(thinking)
+                .with_input_tokens(input_tokens);
+            let error = pump_events(response, translator, &tx).await;
+            if let Some(ref message) = error {
+                warn!("上游事件流失败: credential={} {}", credential_id, message);
+            }
+            release(&credential_id, error.as_deref(), false, None).await;
+        });
+
+        rx
+    }
+}
+
+/// 读取并翻译事件流，返回失败原因
+async fn pump_events(
+    response: reqwest::Response,
+    mut translator: CwToAnthropicTranslator,
+    tx: &mpsc::Sender<AnthropicSseEvent>,
+) -> Option<String> {
+    let mut stream = response.bytes_stream();
+    let mut decoder = EventStreamDecoder::new();
+
+    let mut failure = None;
+    // 上游异常事件已由翻译器转换为 error 事件，不需要再补发
+    let mut error_sent = false;
+    'read: while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(bytes) => decoder.feed(&bytes),
+            Err(e) => {
+                failure = Some(format!("读取上游响应失败: {}", e));
+                break;
+            }
+        }
+
+        loop {
+            match decoder.next_frame() {
+                Ok(Some(frame)) => {
+                    for event in translator.translate_cw_event(CwEvent::from_frame(&frame)) {
+                        if let AnthropicSseEvent::Error { ref error } = event {
+                            failure = Some(error.message.clone());
+                            error_sent = true;
+                        }
+                        if tx.send(event).await.is_err() {
+                            debug!("客户端已断开，停止读取上游");
+                            return failure;
+                        }
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    failure = Some(format!("解析上游事件流失败: {}", e));
+                    break 'read;
+                }
+            }
+        }
+    }
+
+    if failure.is_none() && decoder.remaining() > 0 {
+        failure = Some(format!(
+            "上游事件流在帧中间结束（剩余 {} 字节）",
+            decoder.remaining()
+        ));
+    }
+
+    let tail = match failure {
+        Some(_) if error_sent => Vec::new(),
+        Some(ref message) => vec![AnthropicSseEvent::Error {
+            error: ErrorData {
+                type_: "api_error".to_string(),
+                message: message.clone(),
+            },
+        }],
+        None => translator.finish(),
+    };
+    for event in tail {
+        if tx.send(event).await.is_err() {
+            break;
+        }
+    }
+
+    failure
+}
+
+/// 读取凭证，Token 缺失或即将过期时先刷新
+async fn prepare_credential(credential_id: &str) -> Result<KiroCredentials, UpstreamError> {
+    let credential = provider::get_credential(credential_id)
+        .await
+        .ok_or_else(|| UpstreamError::Credential(format!("凭证不存在: {}", credential_id)))?;
+
+    if credential.access_token.is_some() && !is_token_expired(credential.expire.as_deref()) {
+        return Ok(credential);
+    }
+
+    if let Err(e) = provider::refresh_token(credential_id).await {
+        let message = format!("Token 刷新失败: {}", e);
+        let rejected = e.downcast_ref::<RefreshRejected>();
+        // 只有 refresh_token 确定失效时才移出轮换（健康状态会持久化），
+        // 其他失败只冷却一段时间
+        if rejected.is_some_and(RefreshRejected::is_invalid_grant) {
+            release(credential_id, Some(&message), true, None).await;
+        } else {
+            release(
+                credential_id,
+                Some(&message),
+                false,
+                Some(refresh_cooldown(rejected)),
+            )
+            .await;
+        }
+        return Err(UpstreamError::Credential(message));
+    }
+    provider::get_credential(credential_id)
+        .await
+        .ok_or_else(|| UpstreamError::Credential(format!("凭证不存在: {}", credential_id)))
+}
+
+/// Token 刷新暂时失败后的冷却时间，认证服务的 429/5xx 沿用 `parse_error` 的值
+fn refresh_cooldown(rejected: Option<&RefreshRejected>) -> u64 {
+    rejected
+        .and_then(|r| provider::parse_error(r.status, &r.body))
+        .and_then(|e| e.cooldown_seconds)
+        .filter(|s| *s > 0)
+        .unwrap_or(REFRESH_FAILURE_COOLDOWN_SECONDS)
+}
+
+/// 记录凭证使用结果
+async fn release(
+    credential_id: &str,
+    error: Option<&str>,
+    mark_unhealthy: bool,
+    cooldown_seconds: Option<u64>,
+) {
+    let result = match error {
+        Some(message) => json!({
+            "error": {
+                "message": message,
+                "mark_unhealthy": mark_unhealthy,
+                "cooldown_seconds": cooldown_seconds,
+            }
+        }),
+        None => json!({}),
+    };
+    if let Err(e) = provider::release_credential(credential_id, result).await {
+        warn!("记录凭证使用结果失败: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::event_stream::encode_event;
+
+    /// 在随机端口返回固定的响应体，模拟上游事件流
+    async fn fetch(body: Vec<u8>) -> reqwest::Response {
+        let app = axum::Router::new().route("/", axum::routing::post(move || async move { body }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        CLIENT
+            .post(format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    async fn collect(response: reqwest::Response) -> Vec<AnthropicSseEvent> {
+        let upstream = UpstreamResponse {
+            credential_id: "test".to_string(),
+            tried_credentials: vec!["test".to_string()],
+            context: None,
+            normalization: NormalizeReport::default(),
+            input_tokens: 0,
+            response,
+        };
+        let mut rx = upstream.into_events("claude-sonnet-4-5", false);
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_should_failover() {
+        let status = |status| UpstreamError::Status {
+            status,
+            message: String::new(),
+        };
+        for code in [401, 403, 429, 500, 503] {
+            assert!(status(code).should_failover(), "{}", code);
+        }
+        assert!(!status(400).should_failover());
+        assert!(!status(404).should_failover());
+        assert!(UpstreamError::Network("timeout".into()).should_failover());
+    }
+
+    #[test]
+    fn test_refresh_failure_classification() {
+        let rejected = |status, body: &str| RefreshRejected {
+            auth_method: "IdC",
+            status,
+            body: body.to_string(),
+        };
+        assert!(rejected(400, r#"{"error":"invalid_grant"}"#).is_invalid_grant());
+        assert!(rejected(401, "InvalidGrantException").is_invalid_grant());
+        assert!(!rejected(500, "invalid_grant").is_invalid_grant());
+        assert!(!rejected(401, "ExpiredTokenException").is_invalid_grant());
+
+        // 暂时性失败只冷却
+        assert_eq!(refresh_cooldown(None), REFRESH_FAILURE_COOLDOWN_SECONDS);
+        assert_eq!(refresh_cooldown(Some(&rejected(429, ""))), 60);
+        assert_eq!(refresh_cooldown(Some(&rejected(503, ""))), 10);
+    }
+
+    #[tokio::test]
+    async fn test_into_events_translates_stream() {
+        let mut body = encode_event("assistantResponseEvent", &json!({"content": "Hel"}));
+        body.extend(encode_event(
+            "assistantResponseEvent",
+            &json!({"content": "lo"}),
+        ));
+
+        let events = collect(fetch(body).await).await;
+        let message = crate::translator::cw_to_anthropic::collect_message(&events);
+        assert_eq!(message["content"][0]["text"], "Hello");
+        assert_eq!(message["stop_reason"], "end_turn");
+        assert!(matches!(
+            events.last(),
+            Some(AnthropicSseEvent::MessageStop)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_into_events_reports_truncated_stream() {
+        let mut body = encode_event("assistantResponseEvent", &json!({"content": "Hi"}));
+        body.extend_from_slice(&[0, 0, 0, 64, 0]);
+
+        let events = collect(fetch(body).await).await;
+        match events.last() {
+            Some(AnthropicSseEvent::Error { error }) => {
+                assert!(error.message.contains("帧中间结束"), "{}", error.message)
+            }
+            other => panic!("应以 error 事件结束: {:?}", other),
+        }
+    }
+}